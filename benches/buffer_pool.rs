@@ -0,0 +1,55 @@
+//! Allocations per request with and without `set_buffer_pool`, over fresh
+//! connections so buffers can't just stay with one connection. The count
+//! includes the client's, which is the same for both.
+
+mod common;
+
+use std::error::Error;
+
+use common::{allocations, pipeline, start, Counting};
+use RustGin::{HTTPRequest, HttpServer};
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const CONNECTIONS: usize = 2_000;
+const BODY: usize = 4 * 1024;
+
+fn upload(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let len = request.get_body().len();
+    request.send(&len.to_string())
+}
+
+fn main() {
+    let request = format!(
+        "POST /upload HTTP/1.1\r\nHost: bench\r\nContent-Length: {}\r\n\r\n{}",
+        BODY,
+        "x".repeat(BODY)
+    );
+    for pooled in [false, true] {
+        let mut server = HttpServer::new(0);
+        server.set_workers(4);
+        if pooled {
+            server.set_buffer_pool(64);
+        }
+        server.post("/upload".to_string(), upload);
+        let addr = start(server);
+        // Fills the pool, if there is one.
+        pipeline(addr, &request, 10);
+        let allocations = allocations(|| {
+            for _ in 0..CONNECTIONS {
+                let (_, out) = pipeline(addr, &request, 1);
+                assert!(out.starts_with(b"HTTP/1.1 200"));
+            }
+        });
+        println!(
+            "{:<40} {:>10} allocations per request",
+            if pooled {
+                "buffer pool"
+            } else {
+                "no buffer pool"
+            },
+            allocations / CONNECTIONS
+        );
+    }
+}
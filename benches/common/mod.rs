@@ -0,0 +1,77 @@
+#![allow(dead_code)]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use RustGin::HttpServer;
+
+/// Serves `server` on a free local port from a background thread, allowing
+/// a whole benchmark run on one connection.
+pub fn start(mut server: HttpServer) -> SocketAddr {
+    server.set_max_requests_per_connection(usize::MAX);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || server.listen_on(listener).unwrap());
+    addr
+}
+
+/// Pipelines `count` copies of `request` on one connection, the last asking
+/// for it to be closed, and returns the average time per request along with
+/// everything the server wrote back.
+pub fn pipeline(addr: SocketAddr, request: &str, count: usize) -> (Duration, Vec<u8>) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_nodelay(true).unwrap();
+    let mut writer = stream.try_clone().unwrap();
+    let keep_alive = request.replacen("\r\n", "\r\nConnection: keep-alive\r\n", 1);
+    let close = request.replacen("\r\n", "\r\nConnection: close\r\n", 1);
+    let started = Instant::now();
+    let sender = thread::spawn(move || {
+        for _ in 1..count {
+            writer.write_all(keep_alive.as_bytes()).unwrap();
+        }
+        writer.write_all(close.as_bytes()).unwrap();
+    });
+    let mut out = Vec::new();
+    stream.read_to_end(&mut out).unwrap();
+    let elapsed = started.elapsed();
+    sender.join().unwrap();
+    (elapsed / count as u32, out)
+}
+
+/// Runs `bench` a few times, printing the fastest run.
+pub fn report(name: &str, mut bench: impl FnMut() -> Duration) {
+    bench();
+    let best = (0..5).map(|_| bench()).min().unwrap();
+    println!("{:<40} {:>10.2?} per request", name, best);
+}
+
+/// The system allocator, counting allocations. A benchmark that reports
+/// them installs it with `#[global_allocator]`.
+pub struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Allocations made by the whole process, client included, while `run`
+/// runs.
+pub fn allocations(run: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    run();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
@@ -0,0 +1,43 @@
+//! Serving a prebuilt response with `send_frozen` against building and
+//! serializing it on every request.
+
+mod common;
+
+use std::{error::Error, sync::OnceLock};
+
+use common::{pipeline, report, start};
+use RustGin::{FrozenResponse, HTTPRequest, HTTPResponse, HttpServer, Status};
+
+const REQUESTS: usize = 20_000;
+
+fn health() -> HTTPResponse {
+    let mut response = HTTPResponse::new(Status::Ok, r#"{"status":"ok","checks":[1,2,3]}"#.into());
+    response.add_header("Content-Type".to_string(), "application/json".to_string());
+    response.add_header("Cache-Control".to_string(), "no-store".to_string());
+    response.add_header("X-Served-By".to_string(), "bench".to_string());
+    response
+}
+
+fn dynamic(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send_response(&health())
+}
+
+fn frozen(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    static FROZEN: OnceLock<FrozenResponse> = OnceLock::new();
+    request.send_frozen(FROZEN.get_or_init(|| health().freeze()))
+}
+
+fn main() {
+    let mut server = HttpServer::new(0);
+    server.get("/dynamic".to_string(), dynamic);
+    server.get("/frozen".to_string(), frozen);
+    let addr = start(server);
+    for path in ["/dynamic", "/frozen"] {
+        let request = format!("GET {} HTTP/1.1\r\nHost: bench\r\n\r\n", path);
+        report(path, || {
+            let (per_request, out) = pipeline(addr, &request, REQUESTS);
+            assert!(out.starts_with(b"HTTP/1.1 200"));
+            per_request
+        });
+    }
+}
@@ -0,0 +1,52 @@
+//! Allocations per request on one keep-alive connection, where the
+//! connection's buffers are reused, against a fresh connection for every
+//! request. The count includes the client's.
+
+mod common;
+
+use std::error::Error;
+
+use common::{allocations, pipeline, start, Counting};
+use RustGin::{HTTPRequest, HttpServer};
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const REQUESTS: usize = 2_000;
+
+fn upload(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let len = request.get_body().len();
+    request.send(&len.to_string())
+}
+
+fn main() {
+    let request = format!(
+        "POST /upload HTTP/1.1\r\nHost: bench\r\nContent-Length: 1024\r\n\r\n{}",
+        "x".repeat(1024)
+    );
+    let mut server = HttpServer::new(0);
+    server.post("/upload".to_string(), upload);
+    let addr = start(server);
+    pipeline(addr, &request, 10);
+
+    let persistent = allocations(|| {
+        let (_, out) = pipeline(addr, &request, REQUESTS);
+        assert!(out.starts_with(b"HTTP/1.1 200"));
+    });
+    let fresh = allocations(|| {
+        for _ in 0..REQUESTS {
+            let (_, out) = pipeline(addr, &request, 1);
+            assert!(out.starts_with(b"HTTP/1.1 200"));
+        }
+    });
+    for (name, allocations) in [
+        ("keep-alive connection", persistent),
+        ("connection per request", fresh),
+    ] {
+        println!(
+            "{:<40} {:>10} allocations per request",
+            name,
+            allocations / REQUESTS
+        );
+    }
+}
@@ -0,0 +1,35 @@
+//! Pipelined uploads read with different `set_read_buffer_size` settings.
+
+mod common;
+
+use std::error::Error;
+
+use common::{pipeline, report, start};
+use RustGin::{HTTPRequest, HttpServer};
+
+const REQUESTS: usize = 2_000;
+const BODY: usize = 32 * 1024;
+
+fn upload(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let len = request.get_body().len();
+    request.send(&len.to_string())
+}
+
+fn main() {
+    let request = format!(
+        "POST /upload HTTP/1.1\r\nHost: bench\r\nContent-Length: {}\r\n\r\n{}",
+        BODY,
+        "x".repeat(BODY)
+    );
+    for size in [1024, 8 * 1024, 64 * 1024] {
+        let mut server = HttpServer::new(0);
+        server.set_read_buffer_size(size);
+        server.post("/upload".to_string(), upload);
+        let addr = start(server);
+        report(&format!("{} KiB read buffer", size / 1024), || {
+            let (per_request, out) = pipeline(addr, &request, REQUESTS);
+            assert!(out.starts_with(b"HTTP/1.1 200"));
+            per_request
+        });
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{HTTPResponse, Status};
+
+/// Configures [`crate::HttpServer::response_cache`].
+#[derive(Debug, Clone, Copy)]
+pub struct CacheConfig {
+    pub ttl: Duration,
+    pub max_entries: usize,
+}
+
+/// Successful GET responses from routes, keyed by target and by the request
+/// headers named in each response's `Vary`. The least recently used entry is
+/// evicted once `max_entries` is reached.
+pub(crate) struct ResponseCache {
+    config: CacheConfig,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, Vec<Entry>>,
+    /// The target of every entry, by when it was last used, so the least
+    /// recently used is found without looking through them all.
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+struct Entry {
+    /// Lowercased names from the response's `Vary`, with the value each
+    /// request header had.
+    vary: Vec<(String, Option<String>)>,
+    response: HTTPResponse,
+    expires: Instant,
+    last_used: u64,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(config: CacheConfig) -> ResponseCache {
+        ResponseCache {
+            config,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub(crate) fn get(
+        &self,
+        target: &str,
+        headers: &HashMap<String, String>,
+        now: Instant,
+    ) -> Option<HTTPResponse> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let CacheState {
+            entries: cached,
+            recency,
+            ..
+        } = &mut *state;
+
+        let entries = cached.get_mut(target)?;
+        entries.retain(|entry| {
+            let live = entry.expires > now;
+            if !live {
+                recency.remove(&entry.last_used);
+            }
+            live
+        });
+        let response = entries
+            .iter_mut()
+            .find(|entry| {
+                entry
+                    .vary
+                    .iter()
+                    .all(|(name, value)| headers.get(name) == value.as_ref())
+            })
+            .map(|entry| {
+                recency.remove(&entry.last_used);
+                recency.insert(tick, target.to_string());
+                entry.last_used = tick;
+                entry.response.clone()
+            });
+        if entries.is_empty() {
+            cached.remove(target);
+        }
+        response
+    }
+
+    pub(crate) fn put(
+        &self,
+        target: &str,
+        headers: &HashMap<String, String>,
+        response: &HTTPResponse,
+        now: Instant,
+    ) {
+        if self.config.max_entries == 0 || !cacheable(response) {
+            return;
+        }
+        let vary = response
+            .header("Vary")
+            .unwrap_or_default()
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                let value = headers.get(&name).cloned();
+                (name, value)
+            })
+            .collect::<Vec<_>>();
+
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        let entry = Entry {
+            vary,
+            response: response.clone(),
+            expires: now + self.config.ttl,
+            last_used: tick,
+        };
+        let CacheState {
+            entries: cached,
+            recency,
+            ..
+        } = &mut *state;
+        recency.insert(tick, target.to_string());
+        let entries = cached.entry(target.to_string()).or_default();
+        match entries
+            .iter_mut()
+            .find(|existing| existing.vary == entry.vary)
+        {
+            Some(existing) => {
+                recency.remove(&existing.last_used);
+                *existing = entry;
+            }
+            None => entries.push(entry),
+        }
+        while state.recency.len() > self.config.max_entries {
+            state.evict_least_recent();
+        }
+    }
+}
+
+impl CacheState {
+    fn evict_least_recent(&mut self) {
+        let Some((tick, target)) = self.recency.pop_first() else {
+            return;
+        };
+        let Some(entries) = self.entries.get_mut(&target) else {
+            return;
+        };
+        entries.retain(|entry| entry.last_used != tick);
+        if entries.is_empty() {
+            self.entries.remove(&target);
+        }
+    }
+}
+
+/// Responses that are shared with every client asking for the same thing.
+/// Anything setting cookies is left out so sessions are never handed to
+/// someone else.
+fn cacheable(response: &HTTPResponse) -> bool {
+    let cache_control = response
+        .header("Cache-Control")
+        .unwrap_or_default()
+        .to_lowercase();
+    response.status == Status::Ok
+        && response.stream.is_none()
+        && response.cookies.is_empty()
+        && !cache_control
+            .split(',')
+            .any(|directive| matches!(directive.trim(), "no-store" | "private"))
+        && response
+            .header("Vary")
+            .is_none_or(|vary| vary.trim() != "*")
+}
@@ -0,0 +1,18 @@
+use std::time::Instant;
+
+/// Where the server gets the current time from, see
+/// [`crate::HttpServer::set_clock`]. Tests can supply a clock they advance
+/// by hand instead of sleeping.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// The default clock, reading the system's monotonic clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
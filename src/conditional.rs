@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    fs,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    date::{format_http_date, parse_http_date},
+    HTTPResponse, Status,
+};
+
+/// The `ETag` and `Last-Modified` of a file, derived from its size and
+/// modification time, for answering conditional requests. Content without a
+/// modification time has only an `ETag`.
+pub(crate) struct FileValidators {
+    etag: String,
+    /// Whole seconds, the resolution of an HTTP date.
+    modified: Option<u64>,
+}
+
+impl FileValidators {
+    pub(crate) fn of(metadata: &fs::Metadata) -> FileValidators {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since| since.as_secs());
+        // Weak, since compressed and uncompressed copies of a file share it.
+        let etag = format!("W/\"{:x}-{:x}\"", modified.unwrap_or(0), metadata.len());
+        FileValidators { etag, modified }
+    }
+
+    pub(crate) fn with_etag(etag: String) -> FileValidators {
+        FileValidators {
+            etag,
+            modified: None,
+        }
+    }
+
+    /// Whether the copy the client already has, going by the lowercased
+    /// request `headers`, is current. `If-None-Match` takes precedence over
+    /// `If-Modified-Since`, as clients sending both expect.
+    pub(crate) fn is_current(&self, headers: &HashMap<String, String>) -> bool {
+        if let Some(tags) = headers.get("if-none-match") {
+            let ours = self.etag.trim_start_matches("W/");
+            return tags
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == ours);
+        }
+        let since = headers
+            .get("if-modified-since")
+            .and_then(|since| parse_http_date(since))
+            .and_then(|since| since.duration_since(UNIX_EPOCH).ok());
+        matches!((self.modified, since), (Some(modified), Some(since)) if modified <= since.as_secs())
+    }
+
+    /// A 304 carrying the validators.
+    pub(crate) fn not_modified(&self) -> HTTPResponse {
+        let mut response = HTTPResponse::from_bytes(Status::NotModified, Vec::new());
+        self.apply(&mut response);
+        response
+    }
+
+    pub(crate) fn apply(&self, response: &mut HTTPResponse) {
+        response.add_header("ETag".to_string(), self.etag.clone());
+        if let Some(modified) = self.modified {
+            let modified = UNIX_EPOCH + Duration::from_secs(modified);
+            response.add_header("Last-Modified".to_string(), format_http_date(modified));
+        }
+    }
+}
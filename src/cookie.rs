@@ -0,0 +1,146 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+#[cfg(feature = "private-cookies")]
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Nonce,
+};
+
+/// The secret that signs or encrypts cookies, see
+/// [`crate::HTTPResponse::set_signed_cookie`].
+#[derive(Clone)]
+pub struct CookieKey {
+    secret: Vec<u8>,
+}
+
+impl CookieKey {
+    pub fn new(secret: &[u8]) -> CookieKey {
+        CookieKey {
+            secret: secret.to_vec(),
+        }
+    }
+
+    fn mac(&self, context: &[u8]) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.secret)
+            .expect("HMAC accepts keys of any size");
+        mac.update(context);
+        mac
+    }
+
+    /// Appends a signature binding `value` to the cookie `name`, so it can't
+    /// be altered or moved to another cookie.
+    pub(crate) fn sign(&self, name: &str, value: &str) -> String {
+        let mut mac = self.mac(b"sign");
+        mac.update(format!("{}={}", name, value).as_bytes());
+        format!("{}.{}", value, hex(&mac.finalize().into_bytes()))
+    }
+
+    /// The value of a signed cookie, or `None` if the signature doesn't match.
+    pub(crate) fn verify<'a>(&self, name: &str, signed: &'a str) -> Option<&'a str> {
+        let (value, signature) = signed.rsplit_once('.')?;
+        let mut mac = self.mac(b"sign");
+        mac.update(format!("{}={}", name, value).as_bytes());
+        mac.verify_slice(&unhex(signature)?).ok()?;
+        Some(value)
+    }
+
+    #[cfg(feature = "private-cookies")]
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        let key = self.mac(b"encrypt").finalize().into_bytes();
+        ChaCha20Poly1305::new(&key)
+    }
+
+    /// Encrypts `value` so the client can neither read nor alter it. The
+    /// cookie name is authenticated along with it.
+    #[cfg(feature = "private-cookies")]
+    pub(crate) fn encrypt(&self, name: &str, value: &str) -> String {
+        let nonce = random_bytes(12);
+        let payload = Payload {
+            msg: value.as_bytes(),
+            aad: name.as_bytes(),
+        };
+        let sealed = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .expect("encrypting a cookie can't fail");
+        format!("{}{}", hex(&nonce), hex(&sealed))
+    }
+
+    #[cfg(feature = "private-cookies")]
+    pub(crate) fn decrypt(&self, name: &str, sealed: &str) -> Option<String> {
+        let bytes = unhex(sealed)?;
+        if bytes.len() < 12 {
+            return None;
+        }
+        let (nonce, sealed) = bytes.split_at(12);
+        let payload = Payload {
+            msg: sealed,
+            aad: name.as_bytes(),
+        };
+        let value = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .ok()?;
+        String::from_utf8(value).ok()
+    }
+}
+
+/// Bytes from the operating system's random source, for session IDs and
+/// nonces that clients must not be able to predict.
+#[cfg(any(feature = "sessions", feature = "private-cookies"))]
+pub(crate) fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    getrandom::getrandom(&mut bytes).expect("the operating system has no random source");
+    bytes
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_values_round_trip_and_resist_tampering() {
+        let key = CookieKey::new(b"secret");
+        let signed = key.sign("user", "alice");
+        assert_eq!(key.verify("user", &signed), Some("alice"));
+        assert_eq!(key.verify("admin", &signed), None);
+        assert_eq!(key.verify("user", &signed.replace("alice", "bob")), None);
+        assert_eq!(CookieKey::new(b"other").verify("user", &signed), None);
+    }
+
+    #[cfg(any(feature = "sessions", feature = "private-cookies"))]
+    #[test]
+    fn random_bytes_differ_between_calls() {
+        let first = random_bytes(16);
+        assert_eq!(first.len(), 16);
+        assert_ne!(first, random_bytes(16));
+        assert_eq!(random_bytes(12).len(), 12);
+    }
+
+    #[cfg(feature = "private-cookies")]
+    #[test]
+    fn encryption_uses_a_fresh_nonce_each_time() {
+        let key = CookieKey::new(b"secret");
+        let first = key.encrypt("user", "alice");
+        let second = key.encrypt("user", "alice");
+        assert_ne!(first[..24], second[..24]);
+        assert_eq!(key.decrypt("user", &first).as_deref(), Some("alice"));
+        assert_eq!(key.decrypt("user", &second).as_deref(), Some("alice"));
+        assert_eq!(key.decrypt("admin", &first), None);
+    }
+}
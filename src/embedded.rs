@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use crate::{conditional::FileValidators, HTTPResponse, Status};
+
+/// A file compiled into the binary, e.g. with `include_bytes!`, for
+/// [`crate::HttpServer::embedded_assets`].
+#[derive(Debug, Clone, Copy)]
+pub struct EmbeddedAsset {
+    pub bytes: &'static [u8],
+    pub content_type: &'static str,
+}
+
+/// Embedded assets keyed by URL path, each with an `ETag` hashed from its
+/// contents when it was added.
+#[derive(Default)]
+pub(crate) struct EmbeddedAssets {
+    assets: HashMap<String, (EmbeddedAsset, String)>,
+}
+
+impl EmbeddedAssets {
+    pub(crate) fn insert(&mut self, path: String, asset: EmbeddedAsset) {
+        let etag = content_etag(asset.bytes);
+        self.assets.insert(path, (asset, etag));
+    }
+
+    /// Returns `None` when no asset is embedded at `target`'s path. Answers
+    /// with 304 when the lowercased request `headers` show the client's copy
+    /// is current.
+    pub(crate) fn serve(
+        &self,
+        target: &str,
+        headers: &HashMap<String, String>,
+    ) -> Option<HTTPResponse> {
+        let path = target.split(['?', '#']).next().unwrap_or_default();
+        let (asset, etag) = self.assets.get(path)?;
+        let validators = FileValidators::with_etag(etag.clone());
+        if validators.is_current(headers) {
+            return Some(validators.not_modified());
+        }
+        let mut response = HTTPResponse::from_bytes(Status::Ok, asset.bytes.to_vec());
+        response.add_header("Content-Type".to_string(), asset.content_type.to_string());
+        validators.apply(&mut response);
+        Some(response)
+    }
+}
+
+/// A strong `ETag` from the FNV-1a hash of `bytes`. It needn't resist
+/// collisions made on purpose, since the server chose the bytes itself.
+fn content_etag(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("\"{:016x}\"", hash)
+}
@@ -0,0 +1,107 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+};
+
+/// Controls a running server from another thread. Obtained from
+/// [`crate::HttpServer::handle`] before calling `listen`; clones control the
+/// same server.
+#[derive(Clone, Default)]
+pub struct ServerHandle {
+    state: Arc<HandleState>,
+}
+
+#[derive(Default)]
+struct HandleState {
+    accept: Mutex<AcceptState>,
+    changed: Condvar,
+    /// Cleared by [`ServerHandle::set_ready`] while the application starts.
+    not_ready: AtomicBool,
+}
+
+#[derive(Default)]
+struct AcceptState {
+    paused: bool,
+    shutdown: bool,
+    /// Where the server is listening, so a blocked accept can be woken.
+    addr: Option<SocketAddr>,
+}
+
+impl ServerHandle {
+    /// Stops accepting connections. `listen` returns once every open
+    /// connection has finished: busy ones close after their current response,
+    /// idle ones when their idle timeout runs out.
+    pub fn shutdown(&self) {
+        self.state.accept.lock().unwrap().shutdown = true;
+        self.state.changed.notify_all();
+        self.wake();
+    }
+
+    /// Stops taking new connections off the listener until
+    /// [`ServerHandle::resume`]. Open connections carry on as normal, and
+    /// clients connecting meanwhile wait in the listen backlog.
+    pub fn pause(&self) {
+        self.state.accept.lock().unwrap().paused = true;
+        self.wake();
+    }
+
+    pub fn resume(&self) {
+        self.state.accept.lock().unwrap().paused = false;
+        self.state.changed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.accept.lock().unwrap().paused
+    }
+
+    /// While not ready, every request is answered with 503 and a
+    /// `Retry-After` header, apart from the `/livez` and `/readyz` probes,
+    /// and `/readyz` fails. Call `set_ready(false)` before `listen` to hold
+    /// traffic back while caches warm up or dependencies connect, then
+    /// `set_ready(true)` once they have. Servers are ready by default.
+    pub fn set_ready(&self, ready: bool) {
+        self.state.not_ready.store(!ready, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        !self.state.not_ready.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn is_shutdown(&self) -> bool {
+        self.state.accept.lock().unwrap().shutdown
+    }
+
+    /// Wakes the accept loop, which only notices a change once its accept
+    /// returns with a connection.
+    fn wake(&self) {
+        let addr = self.state.accept.lock().unwrap().addr;
+        if let Some(addr) = addr {
+            let _ = TcpStream::connect(addr);
+        }
+    }
+
+    pub(crate) fn set_addr(&self, mut addr: SocketAddr) {
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        self.state.accept.lock().unwrap().addr = Some(addr);
+    }
+
+    /// Called by the accept loop before it serves a connection. Blocks while
+    /// the server is paused, and returns false once it is shutting down.
+    pub(crate) fn admit(&self) -> bool {
+        let accept = self.state.accept.lock().unwrap();
+        let accept = self
+            .state
+            .changed
+            .wait_while(accept, |accept| accept.paused && !accept.shutdown)
+            .unwrap();
+        !accept.shutdown
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::HTTPResponse;
+
+/// Configures [`crate::HttpServer::idempotency`].
+#[derive(Debug, Clone, Copy)]
+pub struct IdempotencyConfig {
+    pub ttl: Duration,
+    pub max_entries: usize,
+}
+
+/// Responses to requests carrying an `Idempotency-Key`, so a retried request
+/// gets the response the first one got instead of running its handler again.
+/// The completed entry closest to expiring is evicted once `max_entries` is
+/// reached. Keys still being handled are never evicted, so a retry can't run
+/// alongside the request it retries; there are at most as many of those as
+/// requests being handled at once.
+pub(crate) struct IdempotencyStore {
+    config: IdempotencyConfig,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+struct Entry {
+    /// `None` while the first request with the key is still being handled.
+    response: Option<HTTPResponse>,
+    /// Also applies while the response is pending, so a handler that never
+    /// finishes doesn't hold on to its key for good.
+    expires: Instant,
+}
+
+/// What [`IdempotencyStore::begin`] found for a key.
+pub(crate) enum Lookup<'a> {
+    /// The key is new, and now reserved for the request.
+    Started(Reservation<'a>),
+    /// Another request with the key is still being handled.
+    InFlight,
+    Replay(HTTPResponse),
+}
+
+/// A key reserved for the request being handled. Dropped without
+/// [`Reservation::finish`], as when the handler panics, it frees the key for
+/// a retry rather than leaving it in flight until it expires.
+pub(crate) struct Reservation<'a> {
+    store: &'a IdempotencyStore,
+    key: String,
+    finished: bool,
+}
+
+impl Reservation<'_> {
+    pub(crate) fn finish(mut self, response: Option<&HTTPResponse>, now: Instant) {
+        self.finished = true;
+        self.store.finish(&self.key, response, now);
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.store.finish(&self.key, None, Instant::now());
+        }
+    }
+}
+
+impl IdempotencyStore {
+    pub(crate) fn new(config: IdempotencyConfig) -> IdempotencyStore {
+        IdempotencyStore {
+            config,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn begin(&self, key: &str, now: Instant) -> Lookup<'_> {
+        let reservation = Reservation {
+            store: self,
+            key: key.to_string(),
+            finished: false,
+        };
+        if self.config.max_entries == 0 {
+            return Lookup::Started(reservation);
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.expires > now);
+        if let Some(entry) = entries.get(key) {
+            return match &entry.response {
+                Some(response) => Lookup::Replay(response.clone()),
+                None => Lookup::InFlight,
+            };
+        }
+        self.evict(&mut entries, self.config.max_entries - 1);
+        let entry = Entry {
+            response: None,
+            expires: now + self.config.ttl,
+        };
+        entries.insert(key.to_string(), entry);
+        Lookup::Started(reservation)
+    }
+
+    /// Keeps the response to the request that reserved `key`, or frees the
+    /// key for a retry when there is no response worth replaying: none that
+    /// can be sent twice, or a server error a retry might not run into.
+    fn finish(&self, key: &str, response: Option<&HTTPResponse>, now: Instant) {
+        if self.config.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(key);
+        if let Some(response) = response.filter(|response| replayable(response)) {
+            self.evict(&mut entries, self.config.max_entries - 1);
+            let entry = Entry {
+                response: Some(response.clone()),
+                expires: now + self.config.ttl,
+            };
+            entries.insert(key.to_string(), entry);
+        }
+    }
+
+    /// Evicts completed entries, closest to expiring first, until at most
+    /// `keep` are left or only keys in flight remain.
+    fn evict(&self, entries: &mut HashMap<String, Entry>, keep: usize) {
+        while entries.len() > keep {
+            let Some(oldest) = entries
+                .iter()
+                .filter(|(_, entry)| entry.response.is_some())
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            entries.remove(&oldest);
+        }
+    }
+}
+
+fn replayable(response: &HTTPResponse) -> bool {
+    response.stream.is_none() && response.status.code() < 500
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{IdempotencyConfig, IdempotencyStore, Lookup};
+    use crate::{HTTPResponse, Status};
+
+    fn store(max_entries: usize) -> IdempotencyStore {
+        IdempotencyStore::new(IdempotencyConfig {
+            ttl: Duration::from_secs(60),
+            max_entries,
+        })
+    }
+
+    fn ok() -> HTTPResponse {
+        HTTPResponse::new(Status::Ok, "done".to_string())
+    }
+
+    fn complete(store: &IdempotencyStore, key: &str, now: Instant) {
+        match store.begin(key, now) {
+            Lookup::Started(reservation) => reservation.finish(Some(&ok()), now),
+            _ => panic!("{} was already taken", key),
+        }
+    }
+
+    #[test]
+    fn keys_in_flight_are_not_evicted() {
+        let store = store(1);
+        let now = Instant::now();
+        let Lookup::Started(_first) = store.begin("a", now) else {
+            panic!("a was already taken");
+        };
+        assert!(matches!(store.begin("b", now), Lookup::Started(_)));
+        assert!(matches!(store.begin("a", now), Lookup::InFlight));
+    }
+
+    #[test]
+    fn finishing_keeps_to_max_entries() {
+        let store = store(1);
+        let now = Instant::now();
+        let Lookup::Started(pending) = store.begin("a", now) else {
+            panic!("a was already taken");
+        };
+        // Kept past the limit while "a" is in flight.
+        complete(&store, "b", now);
+        assert_eq!(store.entries.lock().unwrap().len(), 2);
+        pending.finish(Some(&ok()), now);
+        assert_eq!(store.entries.lock().unwrap().len(), 1);
+        assert!(matches!(store.begin("a", now), Lookup::Replay(_)));
+    }
+
+    #[test]
+    fn dropped_reservation_frees_the_key() {
+        let store = store(10);
+        let now = Instant::now();
+        drop(store.begin("a", now));
+        assert!(matches!(store.begin("a", now), Lookup::Started(_)));
+    }
+}
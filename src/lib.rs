@@ -1,255 +1,3509 @@
+#![allow(non_snake_case)]
+
 use std::{
-    borrow::Borrow,
+    any::Any,
+    borrow::Cow,
+    cell::{OnceCell, RefCell, RefMut},
     collections::HashMap,
     error::Error,
-    fmt::Display,
-    io::{BufRead, BufReader, Read, Write},
-    net::{TcpListener, TcpStream},
+    fmt::{Display, Write as _},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, TcpListener, TcpStream},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "digest")]
+use sha2::{Digest, Sha256, Sha512};
+
+mod cache;
+mod clock;
+mod conditional;
+#[cfg(feature = "signed-cookies")]
+mod cookie;
+mod date;
+mod embedded;
+mod handle;
+mod idempotency;
+mod middleware;
+mod pipeline;
+mod pool;
+mod proxy;
+mod range;
+mod router;
+#[cfg(feature = "sessions")]
+mod session;
+mod span;
+mod static_files;
+mod stream;
+mod template;
+mod vhost;
+
+use cache::ResponseCache;
+use conditional::FileValidators;
+use embedded::EmbeddedAssets;
+use idempotency::{IdempotencyStore, Lookup};
+use middleware::Middleware;
+use pipeline::PipelineLimit;
+use pool::{BufferPool, ConnectionBuffers};
+use proxy::{node_ip, TrustedProxies};
+use range::Range;
+use router::{Params, Router};
+use stream::{last_chunk, BodyStream};
+
+pub use cache::CacheConfig;
+pub use clock::{Clock, SystemClock};
+#[cfg(feature = "signed-cookies")]
+pub use cookie::CookieKey;
+pub use embedded::EmbeddedAsset;
+pub use handle::ServerHandle;
+pub use idempotency::IdempotencyConfig;
+pub use middleware::MiddlewareStack;
+pub use proxy::ForwardedElement;
+pub use router::{FieldError, Route, RouteStats};
+#[cfg(feature = "sessions")]
+pub use session::{MemoryStore, Session, SessionConfig, SessionData, SessionStore, Sessions};
+pub use span::RequestSpan;
+pub use static_files::StaticDir;
+pub use stream::{JsonArrayWriter, ResponseStream};
+pub use template::html_escape;
+pub use vhost::VirtualHost;
+
 type HTTPHandler = fn(HTTPRequest) -> Result<(), Box<dyn Error>>;
+/// A readiness check for [`HttpServer::readiness`]; an error explains what
+/// isn't ready.
+pub type HealthCheck = fn() -> Result<(), String>;
+type RequestHook = Box<dyn Fn(&HTTPRequest) -> Option<HTTPResponse> + Send + Sync>;
+type ResponseHook = Box<dyn Fn(&HTTPRequest, &mut HTTPResponse) + Send + Sync>;
+type StreamHook = Box<dyn Fn(&TcpStream) + Send + Sync>;
 
 pub struct HttpServer {
     port: i32,
-    functions: HashMap<(String, Method), HTTPHandler>,
+    router: Router,
+    virtual_hosts: Vec<VirtualHost>,
+    static_dirs: Vec<StaticDir>,
+    embedded: EmbeddedAssets,
+    before_body: Vec<RequestHook>,
+    after: Vec<ResponseHook>,
+    configure_stream: Option<StreamHook>,
+    cache: Option<ResponseCache>,
+    idempotency: Option<IdempotencyStore>,
+    trace: bool,
+    readiness: Option<Vec<HealthCheck>>,
+    slow_requests: Option<Duration>,
+    api_mode: bool,
+    dev_mode: bool,
+    default_content_type: String,
+    limits: RequestLimits,
+    allowed_methods: Option<Vec<Method>>,
+    max_response_header_size: Option<usize>,
+    keep_alive: bool,
+    idle_timeout: Duration,
+    max_requests_per_connection: usize,
+    max_pipelined: Option<usize>,
+    max_idle_connections: Option<usize>,
+    read_buffer_size: usize,
+    workers: Option<usize>,
+    pool: PoolCounters,
+    handle: ServerHandle,
+    clock: Arc<dyn Clock>,
+    proxies: Arc<TrustedProxies>,
+    buffer_pool: Option<Arc<BufferPool>>,
+    capture_raw: bool,
+    #[cfg(feature = "sessions")]
+    sessions: Option<Sessions>,
+}
+
+/// The outcome of routing a request.
+struct Dispatched<'a> {
+    reply: Reply,
+    /// The route that handled the request, which its traffic is counted
+    /// against.
+    route: Option<&'a Route>,
+    /// Cleared by routes that always close the connection.
+    keep_alive: bool,
+}
+
+/// Worker pool statistics returned by [`HttpServer::pool_stats`]. Without a
+/// fixed pool every connection gets its own thread, so `workers` is the number
+/// of active connections and nothing is ever queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub workers: usize,
+    pub active: usize,
+    pub idle: usize,
+    pub queue_depth: usize,
+    pub peak_queue_depth: usize,
+    /// Connections ended by a panic in their handler since the server
+    /// started.
+    pub panicked: usize,
+}
+
+#[derive(Default)]
+struct PoolCounters {
+    active: AtomicUsize,
+    queued: AtomicUsize,
+    peak_queued: AtomicUsize,
+    panicked: AtomicUsize,
+    /// Kept-alive connections waiting for their next request.
+    idle_connections: AtomicUsize,
+}
+
+/// A place among the idle connections allowed by
+/// [`HttpServer::set_max_idle_connections`], given up when dropped.
+struct IdleSlot<'a>(&'a AtomicUsize);
+
+impl<'a> IdleSlot<'a> {
+    fn take(count: &'a AtomicUsize, max: usize) -> Option<IdleSlot<'a>> {
+        count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |idle| {
+                (idle < max).then_some(idle + 1)
+            })
+            .ok()
+            .map(|_| IdleSlot(count))
+    }
+}
+
+impl Drop for IdleSlot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl HttpServer {
     pub fn new(port: i32) -> HttpServer {
         HttpServer {
             port,
-            functions: HashMap::new(),
+            router: Router::default(),
+            virtual_hosts: Vec::new(),
+            static_dirs: Vec::new(),
+            embedded: EmbeddedAssets::default(),
+            before_body: Vec::new(),
+            after: Vec::new(),
+            configure_stream: None,
+            cache: None,
+            idempotency: None,
+            trace: false,
+            readiness: None,
+            slow_requests: None,
+            api_mode: false,
+            dev_mode: false,
+            default_content_type: "text/plain; charset=utf-8".to_string(),
+            limits: RequestLimits::default(),
+            allowed_methods: None,
+            max_response_header_size: None,
+            keep_alive: true,
+            idle_timeout: Duration::from_secs(5),
+            max_requests_per_connection: 100,
+            max_pipelined: None,
+            max_idle_connections: None,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            workers: None,
+            pool: PoolCounters::default(),
+            handle: ServerHandle::default(),
+            clock: Arc::new(SystemClock),
+            proxies: Arc::default(),
+            buffer_pool: None,
+            capture_raw: false,
+            #[cfg(feature = "sessions")]
+            sessions: None,
         }
     }
 
-    pub fn get(&mut self, url: String, func: HTTPHandler) {
-        self.functions.insert((url, Method::GET), func);
+    /// Serves connections from a fixed pool of `workers` threads. Connections
+    /// that arrive while every worker is busy wait in a queue. By default each
+    /// connection gets its own thread.
+    pub fn set_workers(&mut self, workers: usize) {
+        self.workers = Some(workers.max(1));
     }
 
-    pub fn post(&mut self, url: String, func: HTTPHandler) {
-        self.functions.insert((url, Method::POST), func);
+    /// Keeps connections open between requests when the client allows it.
+    /// Enabled by default.
+    pub fn set_keep_alive(&mut self, enabled: bool) {
+        self.keep_alive = enabled;
     }
 
-    pub fn add_method(&mut self, method: Method, url: String, func: HTTPHandler) {
-        self.functions.insert((url, method), func);
+    /// How long a kept-alive connection may sit idle waiting for its next
+    /// request before it is closed. Defaults to 5 seconds.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.idle_timeout = timeout.max(Duration::from_millis(1));
     }
 
-    pub fn listen(&self) -> Result<(), Box<dyn Error>> {
-        let listener = TcpListener::bind(format!("localhost:{}", self.port))?;
+    /// The number of requests served on one connection before it is closed.
+    /// Defaults to 100.
+    pub fn set_max_requests_per_connection(&mut self, max: usize) {
+        self.max_requests_per_connection = max.max(1);
+    }
 
-        for stream in listener.incoming() {
-            log::debug!("Incoming stream");
-            if let Some(mut request) = HTTPRequest::new(stream?) {
-                let url = request.url.clone();
-                let method = request.method;
+    /// Requests on a connection are handled one at a time and in order, each
+    /// response written as soon as it is ready. This bounds how many requests
+    /// a client that pipelines can have read ahead: once `max` requests have
+    /// arrived without being answered, the server reads no further until the
+    /// first of them has been. The rest wait in the socket, held back by TCP
+    /// flow control. Unlimited by default, apart from what fits in the read
+    /// buffer, see [`HttpServer::set_read_buffer_size`].
+    pub fn set_max_pipelined(&mut self, max: usize) {
+        self.max_pipelined = Some(max.max(1));
+    }
 
-                let func = self.functions.get(&(url, method));
+    /// The number of kept-alive connections allowed to sit waiting for their
+    /// next request at once. Once that many are idle, further responses are
+    /// sent with `Connection: close` and their connections closed. Unlimited
+    /// by default.
+    pub fn set_max_idle_connections(&mut self, max: usize) {
+        self.max_idle_connections = Some(max);
+    }
 
-                match func {
-                    Some(f) => f(request)?,
-                    None => request.send("404")?,
-                }
-            }
-        }
-        Ok(())
+    /// Answers TRACE requests by echoing the request line and headers back as
+    /// `message/http`, leaving out credentials. TRACE is otherwise refused
+    /// with 405, since reflected headers can leak to scripts.
+    pub fn enable_trace(&mut self) {
+        self.trace = true;
     }
-}
 
-pub enum Status {
-    Ok,
-    NotFound,
-    SwitchingProtocols,
-}
+    /// The size of each connection's read buffer. Defaults to 8 KiB. A larger
+    /// buffer takes fewer reads for big headers and bodies, but is held for
+    /// the lifetime of every open connection, idle ones included.
+    pub fn set_read_buffer_size(&mut self, bytes: usize) {
+        self.read_buffer_size = bytes.max(1);
+    }
 
-impl Display for Status {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let res_str = match self {
-            Status::Ok => "200 OK",
-            Status::NotFound => "404 NOT FOUND",
-            Status::SwitchingProtocols => "101 Switching Protocols",
-        };
-        write!(f, "{}", res_str)
+    /// Keeps up to `buffers` byte buffers for request bodies and response
+    /// heads and reuses them across requests and connections, instead of
+    /// allocating fresh ones for every request. Buffers are emptied between
+    /// uses, and ones grown past 64 KiB are freed rather than kept.
+    pub fn set_buffer_pool(&mut self, buffers: usize) {
+        self.buffer_pool = Some(Arc::new(BufferPool::new(buffers)));
     }
-}
 
-pub struct HTTPResponse {
-    protocol: String,
-    status: Status,
-    data: String,
-    headers: HashMap<String, String>,
-}
+    /// Keeps a copy of every byte of each request as it is read, for
+    /// [`HTTPRequest::raw`]. Off by default: the copy holds the whole body
+    /// in memory a second time, up to the maximum body size.
+    pub fn capture_raw(&mut self, enabled: bool) {
+        self.capture_raw = enabled;
+    }
 
-impl Display for HTTPResponse {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let headers = self
-            .headers
+    /// Serves Kubernetes-style probes: `/livez` answers 200 whenever the
+    /// server is up, and `/readyz` answers 200 only if every check passes,
+    /// otherwise 503 listing what failed. Routes registered on either path
+    /// take precedence.
+    pub fn readiness(&mut self, checks: Vec<HealthCheck>) {
+        self.readiness = Some(checks);
+    }
+
+    /// Reads the time from `clock` instead of the system clock, for the
+    /// response cache, request timings and the default session store. Set it
+    /// before enabling sessions. Socket timeouts always follow real time.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Believes the `Forwarded`, `X-Forwarded-For`, `X-Forwarded-Proto` and
+    /// `X-Forwarded-Host` headers of requests from peers in `ranges`, given
+    /// as addresses or blocks like `10.0.0.0/8`. Requests from anyone else
+    /// have theirs ignored, so clients can't spoof them. See
+    /// [`HTTPRequest::peer_addr`], [`HTTPRequest::scheme`] and
+    /// [`HTTPRequest::host`].
+    pub fn trust_proxies(&mut self, ranges: &[&str]) {
+        self.proxies = Arc::new(TrustedProxies::parse(ranges));
+    }
+
+    /// Logs a warning for every request that takes longer than `threshold`
+    /// from being read to its response being written, naming its method,
+    /// path and how long it took.
+    pub fn log_slow_requests(&mut self, threshold: Duration) {
+        self.slow_requests = Some(threshold);
+    }
+
+    /// When enabled, the default 404/405/500 responses are sent as JSON error
+    /// envelopes (see [`HTTPResponse::json_error`]) instead of plain text.
+    pub fn set_api_mode(&mut self, enabled: bool) {
+        self.api_mode = enabled;
+    }
+
+    /// The `Content-Type` sent with responses that have a body but didn't set
+    /// one, including the server's own plain text errors. Defaults to
+    /// `text/plain; charset=utf-8`. Frozen responses are sent as serialized.
+    pub fn set_default_content_type(&mut self, content_type: &str) {
+        self.default_content_type = content_type.to_string();
+    }
+
+    /// When enabled, the error response for a handler that returns an error
+    /// without having responded names the error and every error in its
+    /// `source()` chain, to show the root cause while developing. Leave it
+    /// off in production, where the response stays generic.
+    pub fn set_dev_mode(&mut self, enabled: bool) {
+        self.dev_mode = enabled;
+    }
+
+    /// Aborts connections whose request headers or body arrive slower than
+    /// `bytes_per_sec`. The headers are timed from their first byte and the
+    /// body from when the handler starts reading it, so time the server
+    /// spends before then, including a client waiting for 100 Continue,
+    /// isn't held against the client. The first second of each is exempt so
+    /// that slow starts on small requests are not punished.
+    pub fn set_min_rate(&mut self, bytes_per_sec: u64) {
+        self.limits.min_rate = Some(bytes_per_sec);
+    }
+
+    /// Rejects requests declaring a body larger than `bytes` with 413 before
+    /// any of it is read. Chunked bodies, which declare no size up front, are
+    /// refused with 413 once a chunk would take them over the limit, and the
+    /// rest of the body is never read.
+    pub fn set_max_body_size(&mut self, bytes: u64) {
+        self.limits.max_body_size = Some(bytes);
+    }
+
+    /// Rejects requests with more than `count` headers with 431. Defaults to
+    /// 100. Like every request limit, it applies to each request on a
+    /// kept-alive connection separately.
+    pub fn set_max_headers(&mut self, count: usize) {
+        self.limits.max_headers = count;
+    }
+
+    /// Rejects chunked bodies with 400 as soon as one of their chunks
+    /// declares more than `bytes`, before any of it is read.
+    pub fn set_max_chunk_size(&mut self, bytes: u64) {
+        self.limits.max_chunk_size = Some(bytes);
+    }
+
+    /// Accepts GET and HEAD requests that carry a body, which its handler can
+    /// read like any other. By default they are rejected with 400.
+    pub fn set_allow_get_bodies(&mut self, allowed: bool) {
+        self.limits.allow_get_bodies = allowed;
+    }
+
+    /// Rejects requests whose request line and headers together exceed
+    /// `bytes` with 431. Defaults to 16 KiB.
+    pub fn set_max_header_size(&mut self, bytes: usize) {
+        self.limits.max_header_size = bytes;
+    }
+
+    /// Sends 500 in place of any response whose status line and headers
+    /// together exceed `bytes`, logging the route that produced it, rather
+    /// than putting a head on the wire that clients and proxies will reject.
+    /// The `Connection` and `Keep-Alive` headers the server adds aren't
+    /// counted. Unlimited by default.
+    pub fn set_max_response_header_size(&mut self, bytes: usize) {
+        self.max_response_header_size = Some(bytes);
+    }
+
+    /// Answers every request whose method isn't in `methods` with 405 before
+    /// it is routed, whatever routes are registered. HEAD is allowed along
+    /// with GET. All methods are allowed by default.
+    pub fn allowed_methods(&mut self, methods: &[Method]) {
+        self.allowed_methods = Some(methods.to_vec());
+    }
+
+    pub fn get(&mut self, url: String, func: HTTPHandler) -> &mut Route {
+        self.add_method(Method::GET, url, func)
+    }
+
+    pub fn post(&mut self, url: String, func: HTTPHandler) -> &mut Route {
+        self.add_method(Method::POST, url, func)
+    }
+
+    pub fn add_method(&mut self, method: Method, url: String, func: HTTPHandler) -> &mut Route {
+        self.router.insert(method, &url, func)
+    }
+
+    /// Hands every request at or below `prefix`, whatever its method, to
+    /// `func`, which sees the path with the prefix stripped: `/admin/users`
+    /// under `/admin` is `/users`, see [`HTTPRequest::path`] and
+    /// [`HTTPRequest::original_path`]. Only tried once no other route
+    /// matched, the longest prefix first.
+    pub fn mount_prefix(&mut self, prefix: &str, func: HTTPHandler) -> &mut Route {
+        self.router.insert_mount(prefix, func)
+    }
+
+    /// Routes paths matching `pattern`, e.g. `^/items/(?P<id>\d+)$`, with
+    /// its named groups available through [`HTTPRequest::param`]. Only tried
+    /// when no exact path or path with parameters matched.
+    #[cfg(feature = "regex")]
+    pub fn route_regex(&mut self, pattern: &str, method: Method, func: HTTPHandler) -> &mut Route {
+        self.router.insert_regex(method, pattern, func)
+    }
+
+    /// Routes for requests whose `Host` is `host`, which are matched instead
+    /// of the server's own routes. `example.com` matches whatever port the
+    /// client gives; `example.com:8080` only that port. On a server with no
+    /// routes of its own, requests for other hosts that nothing else answers
+    /// get 421 rather than 404.
+    pub fn virtual_host(&mut self, host: &str) -> &mut VirtualHost {
+        let vhost = VirtualHost::new(host);
+        let index = match self
+            .virtual_hosts
             .iter()
-            .map(|(k, v)| format!("{}: {}", k, v))
-            .collect::<Vec<String>>()
-            .join("\n");
-        write!(
-            f,
-            "{} {}\r\nContent-Length: {}\r\n{}\r\n{}",
-            self.protocol,
-            self.status,
-            self.data.len(),
-            headers,
-            self.data
-        )
+            .position(|existing| existing.name() == vhost.name())
+        {
+            Some(index) => index,
+            None => {
+                self.virtual_hosts.push(vhost);
+                self.virtual_hosts.len() - 1
+            }
+        };
+        &mut self.virtual_hosts[index]
     }
-}
 
-impl HTTPResponse {
-    pub fn new(status: Status, data: String) -> HTTPResponse {
-        HTTPResponse {
-            protocol: "HTTP/1.1".to_owned(),
-            status,
-            data,
-            headers: HashMap::new(),
+    /// Serves the files under `dir` for GET and HEAD requests below `prefix`
+    /// that no route matched.
+    pub fn static_dir(&mut self, prefix: String, dir: impl Into<PathBuf>) -> &mut StaticDir {
+        self.static_dirs.push(StaticDir::new(prefix, dir.into()));
+        self.static_dirs.last_mut().unwrap()
+    }
+
+    /// Serves each asset for GET and HEAD requests to its path that no route
+    /// matched, straight from memory, with an `ETag` hashed from its
+    /// contents. Embedded assets take precedence over static directories.
+    pub fn embedded_assets(&mut self, assets: HashMap<String, EmbeddedAsset>) {
+        for (path, asset) in assets {
+            self.embedded.insert(path, asset);
         }
     }
 
-    pub fn add_header(&mut self, key: String, value: String) {
-        match self.headers.get(&key) {
-            Some(_) => return,
-            None => self.headers.insert(key, value),
-        };
+    /// Gives every routed request a session, see [`HTTPRequest::session`].
+    /// Sessions are kept in memory unless another store is set with
+    /// [`Sessions::store`].
+    #[cfg(feature = "sessions")]
+    pub fn sessions(&mut self, config: SessionConfig) -> &mut Sessions {
+        self.sessions
+            .insert(Sessions::new(config, self.clock.clone()))
     }
-}
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
-pub enum Method {
-    OPTIONS,
-    GET,
-    HEAD,
-    POST,
-    PUT,
-    DELETE,
-    TRACE,
-    CONNECT,
-}
+    /// Runs `hook` on every accepted connection before anything is read from
+    /// it, to set whatever socket options are needed. Read timeouts are
+    /// managed by the server and are overwritten.
+    pub fn configure_stream(&mut self, hook: impl Fn(&TcpStream) + Send + Sync + 'static) {
+        self.configure_stream = Some(Box::new(hook));
+    }
 
-impl From<&str> for Method {
-    fn from(value: &str) -> Self {
-        match value.to_lowercase().borrow() {
-            "options" => Self::OPTIONS,
-            "get" => Self::GET,
-            "head" => Self::HEAD,
-            "post" => Self::POST,
-            "put" => Self::PUT,
-            "delete" => Self::DELETE,
-            "trace" => Self::TRACE,
-            "connect" => Self::CONNECT,
-            _ => panic!("Invalid conversion to Method from String: {}", value),
+    /// Runs `hook` once a request's headers have arrived, before its handler
+    /// and before any of its body is read. Returning a response sends it in
+    /// place of running the handler. A client that sent
+    /// `Expect: 100-continue` is then never told to send its body, which
+    /// makes this the place to turn down large or unauthorized uploads.
+    pub fn before_body(
+        &mut self,
+        hook: impl Fn(&HTTPRequest) -> Option<HTTPResponse> + Send + Sync + 'static,
+    ) {
+        self.before_body.push(Box::new(hook));
+    }
+
+    /// Runs `hook` on every response after its handler returns and before it
+    /// is written, in the order hooks were added. This includes the server's
+    /// own 404/405/500 responses, but not frozen responses, which are sent as
+    /// serialized. The request's body may already have been read by then.
+    pub fn after(
+        &mut self,
+        hook: impl Fn(&HTTPRequest, &mut HTTPResponse) + Send + Sync + 'static,
+    ) {
+        self.after.push(Box::new(hook));
+    }
+
+    /// Adds the hooks of `stack` after those already added, each running as
+    /// if given to [`HttpServer::before_body`] or [`HttpServer::after`]. The
+    /// same stack can be added to several servers.
+    pub fn middleware(&mut self, stack: &MiddlewareStack) {
+        for hook in stack.hooks().cloned() {
+            match hook {
+                Middleware::Before(hook) => self
+                    .before_body
+                    .push(Box::new(move |request| hook(request))),
+                Middleware::After(hook) => self
+                    .after
+                    .push(Box::new(move |request, response| hook(request, response))),
+            }
         }
     }
-}
 
-impl Display for Method {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let res_str = match self {
-            Method::OPTIONS => "OPTIONS",
-            Method::GET => "GET",
-            Method::HEAD => "HEAD",
-            Method::POST => "POST",
-            Method::PUT => "PUT",
-            Method::DELETE => "DELETE",
-            Method::TRACE => "TRACE",
-            Method::CONNECT => "CONNECT",
-        };
+    /// Keeps successful responses to GET routes for `ttl` and answers repeat
+    /// requests from memory without running the handler. Responses marked
+    /// `Cache-Control: no-store` or `private`, or that set cookies, are never
+    /// stored, and requests carrying cookies or credentials always reach
+    /// their handler. Responses are told apart by the request headers their
+    /// `Vary` header names.
+    pub fn response_cache(&mut self, config: CacheConfig) {
+        self.cache = Some(ResponseCache::new(config));
+    }
 
-        write!(f, "{}", res_str)
+    /// Remembers the response to each routed POST or PATCH request carrying
+    /// an `Idempotency-Key` header for `ttl`, and answers later requests with
+    /// the same key, method and target with it, without running the handler
+    /// again. A request arriving while one with its key is still being
+    /// handled gets 409. Streamed responses and server errors aren't kept,
+    /// so those requests can be retried.
+    pub fn idempotency(&mut self, config: IdempotencyConfig) {
+        self.idempotency = Some(IdempotencyStore::new(config));
     }
-}
 
-//GET / HTTP/1.1
-//Host: localhost:5000
-//User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/110.0
-//Accept: text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8
-//Accept-Language: en-US,en;q=0.5
-//Accept-Encoding: gzip, deflate, br
-//Connection: keep-alive
-//Upgrade-Insecure-Requests: 1
-//Sec-Fetch-Dest: document
-//Sec-Fetch-Mode: navigate
-//Sec-Fetch-Site: none
-//
-//Sec-Fetch-User: ?1
+    /// A handle for pausing or stopping the server once it is listening.
+    pub fn handle(&self) -> ServerHandle {
+        self.handle.clone()
+    }
 
-pub struct HTTPRequest {
-    method: String,
-    target: String,
-    http_version: String,
-    headers: HashMap<String, String>,
-    body: String,
-    stream: TcpStream,
-}
+    pub fn listen(&self) -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind(format!("localhost:{}", self.port))?;
+        self.listen_on(listener)
+    }
 
-impl HTTPRequest {
-    pub fn new(stream: TcpStream) -> Option<HTTPRequest> {
-        let mut bufreader = BufReader::new(stream);
-        let mut first_line = String::new();
-        bufreader.by_ref().read_line(&mut first_line).unwrap();
-        let mut first_line = first_line.split(' ');
-        let method = first_line.next().unwrap();
-        let target = first_line.next().unwrap();
-        let http_version = first_line.next().unwrap().trim_end();
-        let mut headers = HashMap::new();
+    /// Serves connections from a listener the caller has already bound. The
+    /// configured port is ignored.
+    pub fn listen_on(&self, listener: TcpListener) -> Result<(), Box<dyn Error>> {
+        self.handle.set_addr(listener.local_addr()?);
+        let Some(workers) = self.workers else {
+            return thread::scope(|scope| {
+                while let Some(stream) = self.next_connection(&listener)? {
+                    scope.spawn(move || self.run_connection(stream));
+                }
+                Ok(())
+            });
+        };
 
-        let mut line = String::new();
-        while bufreader.by_ref().read_line(&mut line).unwrap() != 0 {
-            if line.is_empty() || !line.contains(": ") {
-                break;
+        let (sender, receiver) = mpsc::channel::<TcpStream>();
+        let receiver = Mutex::new(receiver);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let Ok(stream) = receiver.lock().unwrap().recv() else {
+                        break;
+                    };
+                    self.pool.queued.fetch_sub(1, Ordering::Relaxed);
+                    self.run_connection(stream);
+                });
+            }
+            // Owned by the accept loop so the workers exit once it stops,
+            // including on an accept error.
+            let sender = sender;
+            while let Some(stream) = self.next_connection(&listener)? {
+                let queued = self.pool.queued.fetch_add(1, Ordering::Relaxed) + 1;
+                self.pool.peak_queued.fetch_max(queued, Ordering::Relaxed);
+                sender.send(stream)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Like [`HttpServer::listen`], but a panic escaping the accept loop is
+    /// logged and the loop restarted on the same listener instead of
+    /// unwinding further. Restarts back off exponentially from 100ms up to
+    /// 30s, starting over once the loop has stayed up for 30s. Errors are returned as
+    /// from `listen`, and no restart happens after a shutdown.
+    ///
+    /// A panic in a handler never reaches the accept loop, however the server
+    /// listens: it ends only its own connection, is logged, and is counted in
+    /// [`PoolStats::panicked`], while the worker goes on to the next one.
+    pub fn listen_supervised(&self) -> Result<(), Box<dyn Error>> {
+        let listener = TcpListener::bind(format!("localhost:{}", self.port))?;
+        self.listen_supervised_on(listener)
+    }
+
+    /// Like [`HttpServer::listen_supervised`], on a listener the caller has
+    /// already bound.
+    pub fn listen_supervised_on(&self, listener: TcpListener) -> Result<(), Box<dyn Error>> {
+        let mut backoff = SUPERVISED_MIN_BACKOFF;
+        loop {
+            let started = Instant::now();
+            let accepting = listener.try_clone()?;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| self.listen_on(accepting)));
+            let Err(payload) = result else {
+                return result.unwrap();
+            };
+            let message = panic_message(&*payload);
+            if self.handle.is_shutdown() {
+                log::error!("Accept loop panicked during shutdown: {}", message);
+                return Ok(());
             }
-            let mut line_split = line.split(": ");
-            let key = line_split.next().unwrap();
-            let value = line_split.next().unwrap().trim_end();
-            headers.insert(
-                key.to_string().to_lowercase(),
-                value.to_string().to_lowercase(),
+            if started.elapsed() >= SUPERVISED_MAX_BACKOFF {
+                backoff = SUPERVISED_MIN_BACKOFF;
+            }
+            log::error!(
+                "Accept loop panicked: {}; restarting in {:?}",
+                message,
+                backoff
             );
-            line.clear();
+            thread::sleep(backoff);
+            backoff = (backoff * 2).min(SUPERVISED_MAX_BACKOFF);
         }
+    }
 
-        let mut body = String::new();
-        if headers.contains_key("content-length") {
-            let size_string = headers.get("content-length").unwrap();
-            println!("size_string: '{}'", size_string);
-            let size = headers
-                .get("content-length")
-                .unwrap()
-                .parse::<usize>()
-                .unwrap();
-            let mut buf = vec![0; size];
-            bufreader.read_exact(&mut buf).unwrap();
-            body = String::from_utf8(buf).unwrap();
-        }
-
-        Some(HTTPRequest {
-            method: method.to_string(),
-            target: target.to_string(),
-            http_version: http_version.to_string(),
-            headers,
-            body,
-            stream,
-        })
+    /// Requests and bytes served by each route so far, keyed by method and
+    /// route as registered, e.g. `GET /posts/:id`. Routes of a virtual host
+    /// are prefixed with its name, e.g. `example.com GET /posts/:id`.
+    pub fn route_stats(&self) -> HashMap<String, RouteStats> {
+        let vhost_routes = self.virtual_hosts.iter().flat_map(|vhost| {
+            vhost
+                .router
+                .routes()
+                .map(|route| (format!("{} {}", vhost.name(), route.name()), route.stats()))
+        });
+        self.router
+            .routes()
+            .map(|route| (route.name(), route.stats()))
+            .chain(vhost_routes)
+            .collect()
     }
 
-    pub fn send(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
-        let response = HTTPResponse::new(Status::Ok, text.to_string());
-        self.stream.write_all(response.to_string().as_bytes())?;
-        Ok(())
+    /// A snapshot of how busy the connection workers are.
+    pub fn pool_stats(&self) -> PoolStats {
+        let active = self.pool.active.load(Ordering::Relaxed);
+        let workers = self.workers.unwrap_or(active);
+        PoolStats {
+            workers,
+            active,
+            idle: workers.saturating_sub(active),
+            queue_depth: self.pool.queued.load(Ordering::Relaxed),
+            peak_queue_depth: self.pool.peak_queued.load(Ordering::Relaxed),
+            panicked: self.pool.panicked.load(Ordering::Relaxed),
+        }
     }
 
-    pub fn send_json(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
-        let mut response = HTTPResponse::new(Status::Ok, text.to_string());
-        response.add_header("Content-Type".to_string(), "application/json".to_string());
-        self.stream.write_all(response.to_string().as_bytes())?;
-        Ok(())
+    /// The next connection to serve, or `None` once the server is shutting
+    /// down. While the server is paused nothing is taken off the listener.
+    fn next_connection(&self, listener: &TcpListener) -> io::Result<Option<TcpStream>> {
+        if !self.handle.admit() {
+            return Ok(None);
+        }
+        let (stream, _) = listener.accept()?;
+        log::debug!("Incoming stream");
+        // Pausing or shutting down wakes an accept already waiting with a
+        // connection of its own, which waits here in turn.
+        Ok(self.handle.admit().then_some(stream))
     }
 
-    pub fn get_headers(&self) -> &HashMap<String, String> {
-        &self.headers
+    fn run_connection(&self, stream: TcpStream) {
+        self.pool.active.fetch_add(1, Ordering::Relaxed);
+        if let Some(configure) = &self.configure_stream {
+            configure(&stream);
+        }
+        // A panicking handler takes its connection down with it, but not the
+        // worker serving it.
+        match panic::catch_unwind(AssertUnwindSafe(|| self.serve_connection(stream))) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::debug!("Connection closed with error: {}", e),
+            Err(payload) => {
+                self.pool.panicked.fetch_add(1, Ordering::Relaxed);
+                log::error!("Connection panicked: {}", panic_message(&*payload));
+            }
+        }
+        self.pool.active.fetch_sub(1, Ordering::Relaxed);
     }
-}
 
+    /// The per-connection state for `stream`, set up as configured.
+    fn connection(&self, stream: TcpStream) -> io::Result<Connection> {
+        let mut conn = Connection::new(
+            stream,
+            &self.limits,
+            self.read_buffer_size,
+            self.clock.clone(),
+        )?;
+        conn.proxies = self.proxies.clone();
+        conn.buffers = ConnectionBuffers::new(self.buffer_pool.clone());
+        if self.capture_raw {
+            conn.reader.get_mut().captured = Some(Vec::new());
+        }
+        conn.reader.get_mut().pipeline = self.max_pipelined.map(PipelineLimit::new);
+        Ok(conn)
+    }
+
+    fn serve_connection(&self, stream: TcpStream) -> Result<(), Box<dyn Error>> {
+        let mut writer = stream.try_clone()?;
+        let conn = Rc::new(RefCell::new(self.connection(stream.try_clone()?)?));
+        let mut served = 0;
+        let mut connection_headers = String::new();
+        let mut idle_slot = None;
+
+        loop {
+            let ready = conn.borrow_mut().wait_for_request(self.idle_timeout)?;
+            // No longer idle, whether a request arrived or the wait ran out.
+            drop(idle_slot.take());
+            if !ready {
+                break;
+            }
+            let request = match HTTPRequest::parse(conn.clone()) {
+                Ok(request) => request,
+                Err(RequestError::Io(e)) => {
+                    log::debug!("Failed to read request: {}", e);
+                    break;
+                }
+                Err(e) => {
+                    if let RequestError::Incomplete = e {
+                        log::warn!("Connection closed in the middle of a request head");
+                    }
+                    let reply = Reply::Response(self.error_response(e.status()));
+                    reply.write_to(
+                        &mut writer,
+                        "Connection: close\r\n",
+                        false,
+                        false,
+                        &mut conn.borrow_mut().buffers,
+                    )?;
+                    break;
+                }
+            };
+            served += 1;
+            let keep_alive = self.keep_alive
+                && !self.handle.is_shutdown()
+                && request.wants_keep_alive()
+                && served < self.max_requests_per_connection;
+
+            let head_len = request.head_len;
+            let http_1_0 = request.http_version == "HTTP/1.0";
+            // Whatever body a handler gives a HEAD response, only its length
+            // is sent.
+            let head = request.is_head();
+            let span = request.span.clone();
+            // Named in the log should the response's headers be too large.
+            let described = self
+                .max_response_header_size
+                .map(|_| format!("{} {}", request.method, request.path()));
+            let detached = (!self.after.is_empty()).then(|| request.detached());
+            let mut dispatched = self.dispatch(request);
+            if let (Some(request), Reply::Response(response)) = (&detached, &mut dispatched.reply) {
+                for hook in &self.after {
+                    hook(request, response);
+                }
+            }
+            if let Reply::Response(response) = &mut dispatched.reply {
+                if http_1_0 {
+                    response.trailers.clear();
+                }
+                let has_body = !response.data.is_empty() || response.stream.is_some();
+                if has_body
+                    && response.status != Status::NoContent
+                    && response.header("Content-Type").is_none()
+                {
+                    response.add_header(
+                        "Content-Type".to_string(),
+                        self.default_content_type.clone(),
+                    );
+                }
+            }
+            // Frozen responses are sent exactly as serialized.
+            if let (Some(timing), Reply::Response(response)) =
+                (span.server_timing_header(), &mut dispatched.reply)
+            {
+                response.add_header("Server-Timing".to_string(), timing);
+            }
+            if let (Some(max), Some(described)) = (self.max_response_header_size, &described) {
+                let head_size = dispatched.reply.head_len();
+                if head_size > max {
+                    log::error!(
+                        "Response to {} has {} bytes of headers, over the limit of {}",
+                        described,
+                        head_size,
+                        max
+                    );
+                    dispatched.reply =
+                        Reply::Response(self.error_response(Status::InternalServerError));
+                }
+            }
+            let connection = dispatched.reply.connection_header();
+            // A client still waiting for 100 Continue may or may not send its
+            // body now, so the connection can't be reused.
+            // A streamed body can only be ended by closing the connection
+            // for HTTP/1.0 clients.
+            let close_delimited = http_1_0 && dispatched.reply.is_streamed();
+            let keep_alive = keep_alive
+                && dispatched.keep_alive
+                && !close_delimited
+                && !conn.borrow().expect_continue
+                && conn.borrow().body_rejected.is_none()
+                && !connection.is_some_and(|value| value.eq_ignore_ascii_case("close"));
+            if let (true, Some(max)) = (keep_alive, self.max_idle_connections) {
+                idle_slot = IdleSlot::take(&self.pool.idle_connections, max);
+            }
+            let keep_alive =
+                keep_alive && (self.max_idle_connections.is_none() || idle_slot.is_some());
+            connection_headers.clear();
+            if connection.is_none() {
+                let value = if keep_alive { "keep-alive" } else { "close" };
+                let _ = write!(connection_headers, "Connection: {}\r\n", value);
+            }
+            if keep_alive {
+                let _ = write!(
+                    connection_headers,
+                    "Keep-Alive: timeout={}, max={}\r\n",
+                    self.idle_timeout.as_secs(),
+                    self.max_requests_per_connection - served + 1
+                );
+            }
+
+            let sent = dispatched.reply.write_to(
+                &mut writer,
+                &connection_headers,
+                head,
+                close_delimited,
+                &mut conn.borrow_mut().buffers,
+            )?;
+            if let Some(limit) = &mut conn.borrow_mut().reader.get_mut().pipeline {
+                limit.answered();
+            }
+
+            // Unread body bytes would otherwise be parsed as the next request,
+            // or reset the connection before the client has read the response.
+            let discarded = if conn.borrow().expect_continue {
+                Ok(0)
+            } else {
+                conn.borrow_mut().discard_body()
+            };
+            if let Some(route) = dispatched.route {
+                let received = head_len as u64 + conn.borrow().body_read;
+                route.record(received, sent as u64);
+            }
+            span.finish(dispatched.reply.status().code(), self.slow_requests);
+            if let Err(e) = discarded {
+                log::debug!("Failed to discard unread request body: {}", e);
+                break;
+            }
+            if !keep_alive {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the handler for `request` and returns the response to write.
+    fn dispatch(&self, mut request: HTTPRequest) -> Dispatched<'_> {
+        let url = request.path().to_string();
+        let method = Method::from(request.method.as_str());
+
+        let is_probe = self.readiness.is_some()
+            && matches!(method, Method::GET | Method::HEAD)
+            && matches!(url.as_str(), "/livez" | "/readyz");
+        if !self.handle.is_ready() && !is_probe {
+            let mut response = self.error_response(Status::ServiceUnavailable);
+            response.add_header("Retry-After".to_string(), STARTUP_RETRY_AFTER.to_string());
+            return Dispatched {
+                reply: Reply::Response(response),
+                route: None,
+                keep_alive: true,
+            };
+        }
+        if !self.method_allowed(method) {
+            let allowed = allow_list(self.allowed_methods.clone().unwrap_or_default());
+            let mut response = self.error_response(Status::MethodNotAllowed);
+            response.add_header("Allow".to_string(), allowed.join(", "));
+            return Dispatched {
+                reply: Reply::Response(response),
+                route: None,
+                keep_alive: true,
+            };
+        }
+        if let Some(response) = self.before_body.iter().find_map(|hook| hook(&request)) {
+            return Dispatched {
+                reply: Reply::Response(response),
+                route: None,
+                keep_alive: true,
+            };
+        }
+        if method == Method::TRACE {
+            return Dispatched {
+                reply: Reply::Response(self.trace_response(&request)),
+                route: None,
+                keep_alive: true,
+            };
+        }
+
+        let router = self.router_for(&request);
+        let mut func = router.find(method, &url);
+        let mut head_as_get = false;
+        // HEAD falls back to the GET handler unless one was registered
+        // explicitly.
+        if func.is_none() && method == Method::HEAD {
+            func = router.find(Method::GET, &url);
+            head_as_get = func.is_some();
+        }
+
+        let response = match func {
+            Some((route, params)) => {
+                let cache = self.cache.as_ref().filter(|_| {
+                    (method == Method::GET || head_as_get)
+                        && !request.headers.contains_key("cookie")
+                        && !request.headers.contains_key("authorization")
+                });
+                // Keyed by host as well, since virtual hosts share the cache.
+                let cache_target = format!("{}{}", request.host().to_lowercase(), request.target);
+                if let Some(response) = cache
+                    .and_then(|cache| cache.get(&cache_target, &request.headers, self.clock.now()))
+                {
+                    return Dispatched {
+                        reply: Reply::Response(response),
+                        route: Some(route),
+                        keep_alive: route.keep_alive,
+                    };
+                }
+                let cache_key = cache.map(|_| (cache_target, request.headers.clone()));
+
+                request.params = params;
+                request.mount = route.mount.clone();
+                if let Some(Err(errors)) =
+                    route.validator.as_ref().map(|validate| validate(&request))
+                {
+                    return Dispatched {
+                        reply: Reply::Response(HTTPResponse::field_errors(&errors)),
+                        route: Some(route),
+                        keep_alive: route.keep_alive,
+                    };
+                }
+                // Keyed by host as well, like the response cache.
+                let idempotency = self
+                    .idempotency
+                    .as_ref()
+                    .filter(|_| matches!(method, Method::POST | Method::PATCH))
+                    .zip(request.headers.get("idempotency-key"))
+                    .map(|(store, key)| {
+                        let host = request.host().to_lowercase();
+                        (
+                            store,
+                            format!("{} {}{} {}", method, host, request.target, key),
+                        )
+                    });
+                let mut reservation = None;
+                if let Some((store, key)) = &idempotency {
+                    let replay = match store.begin(key, self.clock.now()) {
+                        Lookup::Started(reserved) => {
+                            reservation = Some(reserved);
+                            None
+                        }
+                        Lookup::InFlight => Some(self.error_response(Status::Conflict)),
+                        Lookup::Replay(response) => Some(response),
+                    };
+                    if let Some(response) = replay {
+                        return Dispatched {
+                            reply: Reply::Response(response),
+                            route: Some(route),
+                            keep_alive: route.keep_alive,
+                        };
+                    }
+                }
+                if let Some(sink) = &route.body_sink {
+                    let mut sink = sink(&request);
+                    request.streamed = Some(request.stream_body_into(&mut sink));
+                }
+                #[cfg(feature = "sessions")]
+                let session = self.sessions.as_ref().map(|sessions| {
+                    let session = Rc::new(sessions.load(request.cookie(sessions.cookie_name())));
+                    request.session = Some(session.clone());
+                    (sessions, session)
+                });
+                let conn = request.conn.clone();
+                #[cfg(feature = "tracing")]
+                let span = request.span.clone();
+                #[cfg(feature = "tracing")]
+                let _entered = span.enter();
+                let result = (route.handler)(request);
+                let sent = conn.borrow_mut().response.take();
+                let mut reply = match (result, sent) {
+                    (Ok(()), Some(reply)) => reply,
+                    (Ok(()), None) => {
+                        log::error!("Handler for {} {} sent no response", method, url);
+                        Reply::Response(self.error_response(Status::InternalServerError))
+                    }
+                    (Err(e), sent) => {
+                        log::error!("Handler for {} {} failed: {}", method, url, e);
+                        sent.unwrap_or_else(|| Reply::Response(self.handler_error_response(&*e)))
+                    }
+                };
+                // Whatever the handler made of a truncated body, the client
+                // is told what was wrong with it.
+                if let Some(status) = conn.borrow().body_rejected {
+                    reply = Reply::Response(self.error_response(status));
+                }
+                #[cfg(feature = "sessions")]
+                if let Some((sessions, session)) = session {
+                    sessions.save(&session, &mut reply);
+                }
+                if let (Some(cache), Some((target, headers)), Reply::Response(response)) =
+                    (cache, &cache_key, &reply)
+                {
+                    cache.put(target, headers, response, self.clock.now());
+                }
+                if let Some(reservation) = reservation {
+                    let response = match &reply {
+                        Reply::Response(response) => Some(response),
+                        Reply::Frozen(_) => None,
+                    };
+                    reservation.finish(response, self.clock.now());
+                }
+                return Dispatched {
+                    reply,
+                    route: Some(route),
+                    keep_alive: route.keep_alive,
+                };
+            }
+            None => match self
+                .probe(method, &url)
+                .or_else(|| self.serve_static(method, &request))
+            {
+                Some(response) => response,
+                None => {
+                    let allowed = self.route_methods(router, &url);
+                    if allowed.is_empty() && self.misdirected(&request) {
+                        self.error_response(Status::MisdirectedRequest)
+                    } else if allowed.is_empty() {
+                        self.error_response(Status::NotFound)
+                    } else {
+                        let mut response = self.error_response(Status::MethodNotAllowed);
+                        response.add_header("Allow".to_string(), allowed.join(", "));
+                        response
+                    }
+                }
+            },
+        };
+        Dispatched {
+            reply: Reply::Response(response),
+            route: None,
+            keep_alive: true,
+        }
+    }
+
+    fn trace_response(&self, request: &HTTPRequest) -> HTTPResponse {
+        if !self.trace {
+            let mut response = self.error_response(Status::MethodNotAllowed);
+            response.add_header(
+                "Allow".to_string(),
+                self.route_methods(self.router_for(request), request.path())
+                    .join(", "),
+            );
+            return response;
+        }
+        let mut message = format!(
+            "{} {} {}\r\n",
+            request.method, request.target, request.http_version
+        );
+        for (name, value) in request.original_headers() {
+            let sensitive = ["cookie", "authorization", "proxy-authorization"]
+                .iter()
+                .any(|sensitive| name.eq_ignore_ascii_case(sensitive));
+            if !sensitive {
+                message.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        let mut response = HTTPResponse::new(Status::Ok, message);
+        response.add_header("Content-Type".to_string(), "message/http".to_string());
+        response
+    }
+
+    fn probe(&self, method: Method, path: &str) -> Option<HTTPResponse> {
+        let checks = self.readiness.as_ref()?;
+        if !matches!(method, Method::GET | Method::HEAD) {
+            return None;
+        }
+        match path {
+            "/livez" => Some(HTTPResponse::new(Status::Ok, "OK".to_string())),
+            "/readyz" => {
+                let mut failures = checks
+                    .iter()
+                    .filter_map(|check| check().err())
+                    .collect::<Vec<String>>();
+                if !self.handle.is_ready() {
+                    failures.insert(0, "server: starting".to_string());
+                }
+                Some(if failures.is_empty() {
+                    HTTPResponse::new(Status::Ok, "OK".to_string())
+                } else if self.api_mode {
+                    HTTPResponse::json_error(Status::ServiceUnavailable, &failures.join("; "))
+                } else {
+                    HTTPResponse::new(Status::ServiceUnavailable, failures.join("\n"))
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn serve_static(&self, method: Method, request: &HTTPRequest) -> Option<HTTPResponse> {
+        if !matches!(method, Method::GET | Method::HEAD) {
+            return None;
+        }
+        self.embedded
+            .serve(&request.target, &request.headers)
+            .map(|response| Ok((response, None)))
+            .or_else(|| {
+                self.static_dirs
+                    .iter()
+                    .find_map(|dir| dir.serve(&request.target, &request.headers))
+            })
+            .map(|served| {
+                let response = served.and_then(|(mut response, file)| {
+                    if response.status == Status::NotModified {
+                        return Ok(response);
+                    }
+                    let body = match file {
+                        Some(file) => range::Body::file(file)?,
+                        None => range::Body::Data(std::mem::take(&mut response.data)),
+                    };
+                    self.apply_range(response, body, request.headers.get("range"))
+                });
+                response.unwrap_or_else(|e| {
+                    log::error!("Failed to serve {}: {}", request.target, e);
+                    self.error_response(Status::InternalServerError)
+                })
+            })
+    }
+
+    /// Answers a `Range` request with the part of `body` it asks for, and
+    /// any other request with all of it.
+    fn apply_range(
+        &self,
+        mut response: HTTPResponse,
+        mut body: range::Body,
+        range: Option<&String>,
+    ) -> io::Result<HTTPResponse> {
+        response.add_header("Accept-Ranges".to_string(), "bytes".to_string());
+        let len = body.len();
+        match Range::parse(range.map(String::as_str), len) {
+            Range::Full => {
+                response.data = body.into_data()?;
+                Ok(response)
+            }
+            Range::Partial(ranges) if ranges.len() > 1 => {
+                let content_type = response.header("Content-Type").map(str::to_string);
+                response
+                    .headers
+                    .retain(|(name, _)| !name.eq_ignore_ascii_case("Content-Type"));
+                let boundary = range::boundary();
+                response.data =
+                    range::multipart(&mut body, &ranges, content_type.as_deref(), &boundary)?;
+                response.status = Status::PartialContent;
+                response.add_header(
+                    "Content-Type".to_string(),
+                    format!("multipart/byteranges; boundary={}", boundary),
+                );
+                Ok(response)
+            }
+            Range::Partial(ranges) => {
+                let (start, end) = ranges[0];
+                response.status = Status::PartialContent;
+                response.data = body.read(start, end)?;
+                response.add_header(
+                    "Content-Range".to_string(),
+                    format!("bytes {}-{}/{}", start, end, len),
+                );
+                Ok(response)
+            }
+            Range::Unsatisfiable => {
+                // Tells the client how long the body actually is.
+                let mut unsatisfiable = self.error_response(Status::RangeNotSatisfiable);
+                unsatisfiable.add_header("Content-Range".to_string(), format!("bytes */{}", len));
+                Ok(unsatisfiable)
+            }
+        }
+    }
+
+    /// The routes of the virtual host the request is for, or the server's own.
+    fn router_for(&self, request: &HTTPRequest) -> &Router {
+        let (host, port) = (request.host(), request.host_port());
+        self.virtual_hosts
+            .iter()
+            .find(|vhost| vhost.matches(host, port))
+            .map_or(&self.router, |vhost| &vhost.router)
+    }
+
+    /// Whether the request is for a host no virtual host matches, on a
+    /// server without routes of its own to fall back on.
+    fn misdirected(&self, request: &HTTPRequest) -> bool {
+        let (host, port) = (request.host(), request.host_port());
+        !self.virtual_hosts.is_empty()
+            && self.router.is_empty()
+            && !self
+                .virtual_hosts
+                .iter()
+                .any(|vhost| vhost.matches(host, port))
+    }
+
+    fn route_methods(&self, router: &Router, url: &str) -> Vec<String> {
+        let methods = router
+            .methods_for(url)
+            .into_iter()
+            .filter(|method| self.method_allowed(*method))
+            .collect();
+        allow_list(methods)
+    }
+
+    fn method_allowed(&self, method: Method) -> bool {
+        self.allowed_methods.as_ref().is_none_or(|allowed| {
+            allowed.contains(&method) || (method == Method::HEAD && allowed.contains(&Method::GET))
+        })
+    }
+
+    /// The response for a handler that failed with `error`: 400 for a body
+    /// that isn't valid UTF-8, and 500 for anything else.
+    fn handler_error_response(&self, error: &(dyn Error + 'static)) -> HTTPResponse {
+        let status = if error.is::<InvalidUtf8Body>() {
+            Status::BadRequest
+        } else {
+            Status::InternalServerError
+        };
+        if !self.dev_mode {
+            return self.error_response(status);
+        }
+        let mut chain = vec![error.to_string()];
+        let mut source = error.source();
+        while let Some(error) = source {
+            chain.push(format!("caused by: {}", error));
+            source = error.source();
+        }
+        let message = chain.join("\n");
+        if self.api_mode {
+            HTTPResponse::json_error(status, &message)
+        } else {
+            HTTPResponse::new(status, format!("{}\n\n{}", status.reason(), message))
+        }
+    }
+
+    fn error_response(&self, status: Status) -> HTTPResponse {
+        let message = status.reason().to_string();
+        if self.api_mode {
+            HTTPResponse::json_error(status, &message)
+        } else {
+            HTTPResponse::new(status, message)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    NoContent,
+    PartialContent,
+    MovedPermanently,
+    Found,
+    NotModified,
+    TemporaryRedirect,
+    PermanentRedirect,
+    BadRequest,
+    NotFound,
+    MethodNotAllowed,
+    RequestTimeout,
+    Conflict,
+    PayloadTooLarge,
+    RangeNotSatisfiable,
+    MisdirectedRequest,
+    UnprocessableEntity,
+    UpgradeRequired,
+    RequestHeaderFieldsTooLarge,
+    InternalServerError,
+    NotImplemented,
+    ServiceUnavailable,
+    SwitchingProtocols,
+}
+
+impl Status {
+    pub fn code(&self) -> u16 {
+        match self {
+            Status::Ok => 200,
+            Status::NoContent => 204,
+            Status::PartialContent => 206,
+            Status::MovedPermanently => 301,
+            Status::Found => 302,
+            Status::NotModified => 304,
+            Status::TemporaryRedirect => 307,
+            Status::PermanentRedirect => 308,
+            Status::BadRequest => 400,
+            Status::NotFound => 404,
+            Status::MethodNotAllowed => 405,
+            Status::RequestTimeout => 408,
+            Status::Conflict => 409,
+            Status::PayloadTooLarge => 413,
+            Status::RangeNotSatisfiable => 416,
+            Status::MisdirectedRequest => 421,
+            Status::UnprocessableEntity => 422,
+            Status::UpgradeRequired => 426,
+            Status::RequestHeaderFieldsTooLarge => 431,
+            Status::InternalServerError => 500,
+            Status::NotImplemented => 501,
+            Status::ServiceUnavailable => 503,
+            Status::SwitchingProtocols => 101,
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::NoContent => "No Content",
+            Status::PartialContent => "Partial Content",
+            Status::MovedPermanently => "Moved Permanently",
+            Status::Found => "Found",
+            Status::NotModified => "Not Modified",
+            Status::TemporaryRedirect => "Temporary Redirect",
+            Status::PermanentRedirect => "Permanent Redirect",
+            Status::BadRequest => "Bad Request",
+            Status::NotFound => "NOT FOUND",
+            Status::MethodNotAllowed => "Method Not Allowed",
+            Status::RequestTimeout => "Request Timeout",
+            Status::Conflict => "Conflict",
+            Status::PayloadTooLarge => "Payload Too Large",
+            Status::RangeNotSatisfiable => "Range Not Satisfiable",
+            Status::MisdirectedRequest => "Misdirected Request",
+            Status::UnprocessableEntity => "Unprocessable Entity",
+            Status::UpgradeRequired => "Upgrade Required",
+            Status::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            Status::InternalServerError => "Internal Server Error",
+            Status::NotImplemented => "Not Implemented",
+            Status::ServiceUnavailable => "Service Unavailable",
+            Status::SwitchingProtocols => "Switching Protocols",
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code(), self.reason())
+    }
+}
+
+#[derive(Clone)]
+pub struct HTTPResponse {
+    protocol: String,
+    status: Status,
+    data: Vec<u8>,
+    /// In the order they were added, which is the order they are sent in.
+    headers: Vec<(String, String)>,
+    /// `Set-Cookie` values, kept apart from `headers` since each needs a
+    /// header line of its own.
+    cookies: Vec<String>,
+    /// Replaces `data` for responses whose body is produced while it is sent.
+    stream: Option<BodyStream>,
+    /// Sent after the body, which is then chunked.
+    trailers: Vec<(String, String)>,
+}
+
+impl Display for HTTPResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.head(), String::from_utf8_lossy(&self.data))
+    }
+}
+
+impl HTTPResponse {
+    /// The status line and headers, including the blank line that ends them.
+    /// Content-Length always reflects the body, even when the body is omitted.
+    fn head(&self) -> String {
+        format!("{}\r\n", self.head_lines())
+    }
+
+    /// The head without its closing blank line, so the server can append the
+    /// headers that manage the connection.
+    fn head_lines(&self) -> String {
+        self.framed_head_lines(false)
+    }
+
+    /// The head for a streamed body delimited by closing the connection
+    /// rather than chunked, for HTTP/1.0 clients, carries no framing header.
+    fn framed_head_lines(&self, close_delimited: bool) -> String {
+        let mut head = Vec::new();
+        self.write_head_lines(&mut head, close_delimited);
+        String::from_utf8(head).expect("response heads are built from strings")
+    }
+
+    /// Appends the head to `out` without allocating anything else, so a
+    /// buffer kept by the connection can be reused for every response.
+    fn write_head_lines(&self, out: &mut Vec<u8>, close_delimited: bool) {
+        // Writing to a Vec can't fail.
+        let _ = write!(out, "{} {}\r\n", self.protocol, self.status);
+        match self.stream {
+            // A 204 never has a body, and a 304 isn't the body it refers to,
+            // so neither says anything about one.
+            _ if matches!(self.status, Status::NoContent | Status::NotModified) => {}
+            Some(_) if close_delimited => {}
+            Some(_) => out.extend_from_slice(b"Transfer-Encoding: chunked\r\n"),
+            None if !self.trailers.is_empty() => {
+                out.extend_from_slice(b"Transfer-Encoding: chunked\r\n")
+            }
+            None => {
+                let _ = write!(out, "Content-Length: {}\r\n", self.data.len());
+            }
+        }
+        if self.sends_trailers() {
+            let names = self.trailers.iter().map(|(name, _)| name.as_str());
+            let _ = write!(out, "Trailer: {}\r\n", names.collect::<Vec<_>>().join(", "));
+        }
+        for (name, value) in &self.headers {
+            let _ = write!(out, "{}: {}\r\n", name, value);
+        }
+        for cookie in &self.cookies {
+            let _ = write!(out, "Set-Cookie: {}\r\n", cookie);
+        }
+    }
+
+    /// The response exactly as it is put on the wire, binary bodies included,
+    /// apart from the `Connection` and `Keep-Alive` headers the server adds.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let head = self.head();
+        let body = self.wire_body();
+        let mut bytes = Vec::with_capacity(head.len() + body.len());
+        bytes.extend_from_slice(head.as_bytes());
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    /// Whether the trailers are sent, which needs a body to follow.
+    fn sends_trailers(&self) -> bool {
+        !self.trailers.is_empty() && !matches!(self.status, Status::NoContent | Status::NotModified)
+    }
+
+    /// The body of a response that isn't streamed as it is sent: a single
+    /// chunk followed by the trailers when there are any.
+    fn wire_body(&self) -> Cow<'_, [u8]> {
+        if !self.sends_trailers() {
+            return Cow::Borrowed(&self.data);
+        }
+        let mut body = Vec::with_capacity(self.data.len() + 32);
+        if !self.data.is_empty() {
+            let _ = write!(body, "{:x}\r\n", self.data.len());
+            body.extend_from_slice(&self.data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(&last_chunk(&self.trailers));
+        Cow::Owned(body)
+    }
+
+    /// Serializes the response once so it can be sent any number of times
+    /// without formatting it again. See [`HTTPRequest::send_frozen`].
+    /// A streamed body can only be sent once, so it isn't kept.
+    pub fn freeze(mut self) -> FrozenResponse {
+        self.stream = None;
+        FrozenResponse {
+            status: self.status,
+            head: self.head_lines().into_bytes().into(),
+            connection: self.header("Connection").map(str::to_string),
+            body: self.wire_body().into(),
+        }
+    }
+
+    pub fn new(status: Status, data: String) -> HTTPResponse {
+        HTTPResponse::from_bytes(status, data.into_bytes())
+    }
+
+    pub fn from_bytes(status: Status, data: Vec<u8>) -> HTTPResponse {
+        HTTPResponse {
+            protocol: "HTTP/1.1".to_owned(),
+            status,
+            data,
+            headers: Vec::new(),
+            cookies: Vec::new(),
+            stream: None,
+            trailers: Vec::new(),
+        }
+    }
+
+    /// A response whose body is whatever is sent on `receiver`, written to the
+    /// client with chunked encoding as each part arrives. The body ends when
+    /// every sender has been dropped; HTTP/1.0 clients get the parts as they
+    /// are, ended by closing the connection. Lets other threads produce the
+    /// body after the handler has returned.
+    pub fn from_channel(status: Status, receiver: mpsc::Receiver<Vec<u8>>) -> HTTPResponse {
+        let mut response = HTTPResponse::from_bytes(status, Vec::new());
+        response.stream = Some(BodyStream::new(receiver));
+        response
+    }
+
+    /// A response whose body is written through the returned
+    /// [`ResponseStream`] after it has been sent, ending with
+    /// [`ResponseStream::finish`].
+    pub fn streaming(status: Status) -> (HTTPResponse, ResponseStream) {
+        let (stream, body) = ResponseStream::new();
+        let mut response = HTTPResponse::from_bytes(status, Vec::new());
+        response.stream = Some(body);
+        (response, stream)
+    }
+
+    /// A JSON array whose items are written by `produce` on a thread of its
+    /// own while the response is sent, so the whole array is never held in
+    /// memory. Items are sent in chunks as the buffer fills.
+    pub fn json_array_stream(
+        status: Status,
+        produce: impl FnOnce(&mut JsonArrayWriter) -> io::Result<()> + Send + 'static,
+    ) -> HTTPResponse {
+        let (mut response, stream) = HTTPResponse::streaming(status);
+        response.add_header("Content-Type".to_string(), "application/json".to_string());
+        thread::spawn(move || {
+            if let Err(e) = JsonArrayWriter::run(stream, produce) {
+                log::debug!("Streamed JSON array ended early: {}", e);
+            }
+        });
+        response
+    }
+
+    /// An HTML page made from `template`, with each `{key}` replaced by its
+    /// value from `values`, escaped with [`html_escape`]. `{!key}` inserts the
+    /// value as is, for markup that is already safe.
+    pub fn render(template: &str, values: &[(&str, &str)]) -> HTTPResponse {
+        let mut response = HTTPResponse::new(Status::Ok, template::render(template, values));
+        response.add_header(
+            "Content-Type".to_string(),
+            "text/html; charset=utf-8".to_string(),
+        );
+        response
+    }
+
+    /// A 426 telling the client to switch to `protocol`, e.g. `TLS/1.2` or
+    /// `HTTP/2`, and try again, for when redirecting isn't an option.
+    pub fn upgrade_required(protocol: &str) -> HTTPResponse {
+        let status = Status::UpgradeRequired;
+        let mut response = HTTPResponse::new(status, status.reason().to_string());
+        response.add_header("Upgrade".to_string(), protocol.to_string());
+        response.add_header("Connection".to_string(), "Upgrade".to_string());
+        response
+    }
+
+    /// The file at `path` with its `Content-Type`, and an `ETag` and
+    /// `Last-Modified` taken from its size and modification time, or a 304
+    /// when `request` is a GET or HEAD showing the client's copy is current.
+    /// Static directories answer conditional requests the same way.
+    pub fn conditional_file(
+        request: &HTTPRequest,
+        path: impl AsRef<Path>,
+    ) -> io::Result<HTTPResponse> {
+        let path = path.as_ref();
+        let validators = FileValidators::of(&fs::metadata(path)?);
+        let method = Method::from(request.method.as_str());
+        if matches!(method, Method::GET | Method::HEAD) && validators.is_current(&request.headers) {
+            return Ok(validators.not_modified());
+        }
+        let mut response = HTTPResponse::from_bytes(Status::Ok, fs::read(path)?);
+        response.add_header(
+            "Content-Type".to_string(),
+            static_files::content_type(path).to_string(),
+        );
+        validators.apply(&mut response);
+        Ok(response)
+    }
+
+    /// A 301 redirect to `location`. Clients may follow it with a GET even if
+    /// the request was a POST.
+    pub fn redirect_permanent(location: &str) -> HTTPResponse {
+        HTTPResponse::redirect(Status::MovedPermanently, location)
+    }
+
+    /// A 302 redirect to `location`. Clients may follow it with a GET even if
+    /// the request was a POST.
+    pub fn redirect_temporary(location: &str) -> HTTPResponse {
+        HTTPResponse::redirect(Status::Found, location)
+    }
+
+    /// A 308 redirect to `location`, followed with the same method and body.
+    pub fn redirect_permanent_preserve(location: &str) -> HTTPResponse {
+        HTTPResponse::redirect(Status::PermanentRedirect, location)
+    }
+
+    /// A 307 redirect to `location`, followed with the same method and body.
+    pub fn redirect_temporary_preserve(location: &str) -> HTTPResponse {
+        HTTPResponse::redirect(Status::TemporaryRedirect, location)
+    }
+
+    fn redirect(status: Status, location: &str) -> HTTPResponse {
+        let mut response = HTTPResponse::from_bytes(status, Vec::new());
+        response.add_header("Location".to_string(), location.to_string());
+        response
+    }
+
+    /// Builds a JSON error envelope of the form
+    /// `{"error": {"code": 404, "message": "..."}}`.
+    pub fn json_error(status: Status, message: &str) -> HTTPResponse {
+        let data = format!(
+            "{{\"error\": {{\"code\": {}, \"message\": \"{}\"}}}}",
+            status.code(),
+            json_escape(message)
+        );
+        let mut response = HTTPResponse::new(status, data);
+        response.add_header("Content-Type".to_string(), "application/json".to_string());
+        response
+    }
+
+    /// A 422 listing why each field was rejected, of the form
+    /// `{"errors": [{"field": "name", "message": "..."}]}`.
+    pub fn field_errors(errors: &[FieldError]) -> HTTPResponse {
+        let errors = errors
+            .iter()
+            .map(|error| {
+                format!(
+                    "{{\"field\": \"{}\", \"message\": \"{}\"}}",
+                    json_escape(&error.field),
+                    json_escape(&error.message)
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let mut response = HTTPResponse::new(
+            Status::UnprocessableEntity,
+            format!("{{\"errors\": [{}]}}", errors),
+        );
+        response.add_header("Content-Type".to_string(), "application/json".to_string());
+        response
+    }
+
+    /// Asks the browser to save the response as `filename`. Non-ASCII names
+    /// are sent RFC 5987 encoded alongside a plain ASCII fallback.
+    pub fn attachment(&mut self, filename: &str) {
+        let fallback = filename
+            .chars()
+            .map(|c| match c {
+                '"' | '\\' => format!("\\{}", c),
+                c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+                _ => "_".to_string(),
+            })
+            .collect::<String>();
+        let mut value = format!("attachment; filename=\"{}\"", fallback);
+        if !filename.is_ascii() {
+            value.push_str(&format!("; filename*=UTF-8''{}", rfc5987_encode(filename)));
+        }
+        self.add_header("Content-Disposition".to_string(), value);
+    }
+
+    /// Sets a cookie for the whole site. Unlike other headers, any number of
+    /// cookies can be set on one response.
+    pub fn set_cookie(&mut self, name: &str, value: &str) {
+        self.cookies.push(format!("{}={}; Path=/", name, value));
+    }
+
+    /// Sets a cookie the client can read but not alter. Read it back with
+    /// [`HTTPRequest::signed_cookie`].
+    #[cfg(feature = "signed-cookies")]
+    pub fn set_signed_cookie(&mut self, name: &str, value: &str, key: &CookieKey) {
+        self.set_cookie(name, &key.sign(name, value));
+    }
+
+    /// Sets a cookie the client can neither read nor alter. Read it back with
+    /// [`HTTPRequest::private_cookie`].
+    #[cfg(feature = "private-cookies")]
+    pub fn set_private_cookie(&mut self, name: &str, value: &str, key: &CookieKey) {
+        self.set_cookie(name, &key.encrypt(name, value));
+    }
+
+    fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn add_header(&mut self, key: String, value: String) {
+        if !self.headers.iter().any(|(name, _)| *name == key) {
+            self.headers.push((key, value));
+        }
+    }
+
+    /// Adds a trailer, a header sent after the body, e.g. the status of a
+    /// gRPC-web call whose outcome is only known once the body is written.
+    /// The response is sent chunked, listing the trailer names in a
+    /// `Trailer` header. HTTP/1.0 clients, which can't receive trailers, get
+    /// the response without them, though not once it is frozen.
+    pub fn trailer(&mut self, key: String, value: String) {
+        self.trailers.push((key, value));
+    }
+}
+
+/// A fully serialized response, written to the wire verbatim apart from the
+/// `Connection` and `Keep-Alive` headers the server manages. Cloning is cheap.
+#[derive(Clone)]
+pub struct FrozenResponse {
+    status: Status,
+    head: Arc<[u8]>,
+    connection: Option<String>,
+    body: Arc<[u8]>,
+}
+
+/// What a handler sent back for a request.
+enum Reply {
+    Response(HTTPResponse),
+    Frozen(FrozenResponse),
+}
+
+impl Reply {
+    /// The `Connection` header the handler set itself, if any.
+    fn connection_header(&self) -> Option<&str> {
+        match self {
+            Reply::Response(response) => response.header("Connection"),
+            Reply::Frozen(frozen) => frozen.connection.as_deref(),
+        }
+    }
+
+    fn status(&self) -> Status {
+        match self {
+            Reply::Response(response) => response.status,
+            Reply::Frozen(frozen) => frozen.status,
+        }
+    }
+
+    /// The size of the status line and headers as they will be sent, before
+    /// the server adds its own.
+    fn head_len(&self) -> usize {
+        match self {
+            Reply::Response(response) => response.head_lines().len(),
+            Reply::Frozen(frozen) => frozen.head.len(),
+        }
+    }
+
+    fn is_streamed(&self) -> bool {
+        matches!(self, Reply::Response(response) if response.stream.is_some())
+    }
+
+    /// A streamed body is sent chunked, or for HTTP/1.0 clients, which don't
+    /// understand chunked encoding, as is with the connection closed after it
+    /// when `close_delimited` is set.
+    fn write_to(
+        &self,
+        writer: &mut impl Write,
+        connection_headers: &str,
+        omit_body: bool,
+        close_delimited: bool,
+        buffers: &mut ConnectionBuffers,
+    ) -> io::Result<usize> {
+        let mut buf = buffers.take();
+        let body = match self {
+            Reply::Response(response) => {
+                response.write_head_lines(&mut buf, close_delimited);
+                response.wire_body()
+            }
+            Reply::Frozen(frozen) => {
+                buf.extend_from_slice(&frozen.head);
+                Cow::Borrowed(&frozen.body[..])
+            }
+        };
+        buf.extend_from_slice(connection_headers.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+        let written = writer.write_all(&buf).map(|_| buf.len());
+        buffers.give(buf);
+        let head_len = written?;
+        if let Reply::Response(HTTPResponse {
+            stream: Some(stream),
+            trailers,
+            ..
+        }) = self
+        {
+            if omit_body {
+                stream.skip();
+                return Ok(head_len);
+            }
+            return Ok(head_len + stream.write(writer, !close_delimited, trailers)?);
+        }
+        if omit_body {
+            return Ok(head_len);
+        }
+        writer.write_all(&body)?;
+        Ok(head_len + body.len())
+    }
+}
+
+/// The methods for an `Allow` header, sorted, with HEAD added wherever GET
+/// is allowed since GET routes answer it.
+fn allow_list(mut methods: Vec<Method>) -> Vec<String> {
+    if methods.contains(&Method::GET) && !methods.contains(&Method::HEAD) {
+        methods.push(Method::HEAD);
+    }
+    let mut methods = methods
+        .iter()
+        .map(Method::to_string)
+        .collect::<Vec<String>>();
+    methods.sort();
+    methods
+}
+
+fn rfc5987_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Method {
+    OPTIONS,
+    GET,
+    HEAD,
+    POST,
+    PUT,
+    PATCH,
+    DELETE,
+    TRACE,
+    CONNECT,
+}
+
+impl Method {
+    /// The method named `value`, or `None` if it isn't one of these.
+    pub fn parse(value: &str) -> Option<Method> {
+        let method = match value.to_lowercase().as_str() {
+            "options" => Self::OPTIONS,
+            "get" => Self::GET,
+            "head" => Self::HEAD,
+            "post" => Self::POST,
+            "put" => Self::PUT,
+            "patch" => Self::PATCH,
+            "delete" => Self::DELETE,
+            "trace" => Self::TRACE,
+            "connect" => Self::CONNECT,
+            _ => return None,
+        };
+        Some(method)
+    }
+}
+
+impl From<&str> for Method {
+    fn from(value: &str) -> Self {
+        Method::parse(value)
+            .unwrap_or_else(|| panic!("Invalid conversion to Method from String: {}", value))
+    }
+}
+
+impl Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let res_str = match self {
+            Method::OPTIONS => "OPTIONS",
+            Method::GET => "GET",
+            Method::HEAD => "HEAD",
+            Method::POST => "POST",
+            Method::PUT => "PUT",
+            Method::PATCH => "PATCH",
+            Method::DELETE => "DELETE",
+            Method::TRACE => "TRACE",
+            Method::CONNECT => "CONNECT",
+        };
+
+        write!(f, "{}", res_str)
+    }
+}
+
+//GET / HTTP/1.1
+//Host: localhost:5000
+//User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:109.0) Gecko/20100101 Firefox/110.0
+//Accept: text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8
+//Accept-Language: en-US,en;q=0.5
+//Accept-Encoding: gzip, deflate, br
+//Connection: keep-alive
+//Upgrade-Insecure-Requests: 1
+//Sec-Fetch-Dest: document
+//Sec-Fetch-Mode: navigate
+//Sec-Fetch-Site: none
+//
+//Sec-Fetch-User: ?1
+
+pub struct HTTPRequest {
+    method: String,
+    target: String,
+    http_version: String,
+    /// Keyed by lowercased name; the last of repeated headers wins.
+    headers: HashMap<String, String>,
+    /// Every header line in order, with its name as sent.
+    header_lines: Vec<(String, String)>,
+    /// Bytes taken by the request line and headers.
+    head_len: usize,
+    body: OnceCell<Vec<u8>>,
+    params: Params,
+    /// The prefix of the mount handling the request, hidden from
+    /// [`HTTPRequest::path`].
+    mount: Option<String>,
+    conn: Rc<RefCell<Connection>>,
+    responded: bool,
+    /// Set when the peer is a trusted proxy, whose forwarding headers are
+    /// believed.
+    from_trusted_proxy: bool,
+    /// Parsed on first use.
+    forwarded: OnceCell<Vec<ForwardedElement>>,
+    /// Copied out of the connection on first use.
+    raw: OnceCell<Vec<u8>>,
+    /// The body with invalid UTF-8 replaced, made by `get_body` on first use.
+    lossy_body: OnceCell<String>,
+    /// How piping the body to the route's sink went, see
+    /// [`Route::stream_body_to`].
+    streamed: Option<io::Result<u64>>,
+    span: Rc<RequestSpan>,
+    #[cfg(feature = "sessions")]
+    session: Option<Rc<Session>>,
+}
+
+/// Returned when a handler tries to respond to a request a second time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseAlreadySent;
+
+impl Display for ResponseAlreadySent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "a response has already been sent for this request")
+    }
+}
+
+impl Error for ResponseAlreadySent {}
+
+/// Returned by [`HTTPRequest::body_text`] for a body that isn't valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidUtf8Body {
+    /// Where the first invalid byte sequence starts.
+    pub offset: usize,
+}
+
+impl Display for InvalidUtf8Body {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the request body is not valid UTF-8 at byte {}",
+            self.offset
+        )
+    }
+}
+
+impl Error for InvalidUtf8Body {}
+
+/// Size limits applied afresh to every request read from a connection.
+#[derive(Clone)]
+struct RequestLimits {
+    min_rate: Option<u64>,
+    max_body_size: Option<u64>,
+    max_chunk_size: Option<u64>,
+    max_headers: usize,
+    /// Covers the request line and every header line, line endings included.
+    max_header_size: usize,
+    allow_get_bodies: bool,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        RequestLimits {
+            min_rate: None,
+            max_body_size: None,
+            max_chunk_size: None,
+            max_headers: 100,
+            max_header_size: 16 * 1024,
+            allow_get_bodies: false,
+        }
+    }
+}
+
+/// Why a request couldn't be read off the connection.
+#[derive(Debug)]
+enum RequestError {
+    /// The connection failed or closed; nothing can be sent back.
+    Io(io::Error),
+    Malformed,
+    /// The client closed the connection before the head was complete.
+    Incomplete,
+    HeadersTooLarge,
+    BodyTooLarge,
+    /// A well-formed method the server doesn't know.
+    UnknownMethod,
+}
+
+impl RequestError {
+    fn status(&self) -> Status {
+        match self {
+            RequestError::Io(_) | RequestError::Malformed | RequestError::Incomplete => {
+                Status::BadRequest
+            }
+            RequestError::HeadersTooLarge => Status::RequestHeaderFieldsTooLarge,
+            RequestError::BodyTooLarge => Status::PayloadTooLarge,
+            RequestError::UnknownMethod => Status::NotImplemented,
+        }
+    }
+}
+
+/// The message a panic was raised with.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string payload")
+}
+
+/// Whether `b` may appear in a token, such as a method or header name.
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// The length of the head at the start of `buf` and the number of headers
+/// in it, if all of it has arrived, is valid UTF-8 and fits in `max_size`.
+/// Anything else is left to be read a line at a time, which reports it.
+fn buffered_head(buf: &[u8], max_size: usize) -> Option<(usize, usize)> {
+    let buf = &buf[..buf.len().min(max_size)];
+    let mut start = 0;
+    let mut count = 0;
+    while let Some(end) = buf[start..].iter().position(|&b| b == b'\n') {
+        let line = &buf[start..start + end + 1];
+        start += end + 1;
+        // The request line, then headers up to the blank line. Any other line
+        // is left to `add_header` to reject.
+        if count > 0 && (line == b"\r\n" || line == b"\n") {
+            std::str::from_utf8(&buf[..start]).ok()?;
+            return Some((start, count - 1));
+        }
+        count += 1;
+    }
+    None
+}
+
+/// A request line, then its headers by lowercased name and as sent.
+type Head = (String, HashMap<String, String>, Vec<(String, String)>);
+
+/// Reads a request head. Usually the whole head has already arrived, and is
+/// parsed straight out of the read buffer instead of a line at a time.
+fn read_head<R: Read>(
+    reader: &mut BufReader<R>,
+    limits: &RequestLimits,
+    budget: &mut usize,
+) -> Result<Head, RequestError> {
+    match buffered_head(reader.buffer(), limits.max_header_size) {
+        Some((len, count)) => head_from_buffer(reader, len, count, limits, budget),
+        None => head_by_lines(reader, limits, budget),
+    }
+}
+
+/// Parses the `len` byte head `buffered_head` found in the read buffer.
+fn head_from_buffer<R: Read>(
+    reader: &mut BufReader<R>,
+    len: usize,
+    count: usize,
+    limits: &RequestLimits,
+    budget: &mut usize,
+) -> Result<Head, RequestError> {
+    let mut headers = HashMap::with_capacity(count);
+    let mut header_lines = Vec::with_capacity(count);
+    let head = std::str::from_utf8(&reader.buffer()[..len]).unwrap_or_default();
+    let mut lines = head.split_inclusive('\n');
+    let first_line = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        if !add_header(line, &mut headers, &mut header_lines, limits)? {
+            break;
+        }
+    }
+    reader.consume(len);
+    *budget -= len;
+    Ok((first_line, headers, header_lines))
+}
+
+/// Reads a head that hasn't all arrived yet a line at a time.
+fn head_by_lines(
+    reader: &mut impl BufRead,
+    limits: &RequestLimits,
+    budget: &mut usize,
+) -> Result<Head, RequestError> {
+    let mut headers = HashMap::new();
+    let mut header_lines = Vec::new();
+    let mut first_line = String::new();
+    read_head_line(reader, &mut first_line, budget)?;
+    let mut line = String::new();
+    loop {
+        read_head_line(reader, &mut line, budget)?;
+        if !add_header(&line, &mut headers, &mut header_lines, limits)? {
+            break;
+        }
+        line.clear();
+    }
+    Ok((first_line, headers, header_lines))
+}
+
+/// Adds the header on `line` to the request's, returning false once the line
+/// is the blank one that ends the head. Only a blank line ends it: a line
+/// that isn't a header is malformed, since a proxy in front may not agree
+/// where a head like that ends.
+fn add_header(
+    line: &str,
+    headers: &mut HashMap<String, String>,
+    header_lines: &mut Vec<(String, String)>,
+    limits: &RequestLimits,
+) -> Result<bool, RequestError> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    if line.is_empty() {
+        return Ok(false);
+    }
+    // No whitespace is allowed around the name, which also rules out lines
+    // folded onto the one before.
+    let (key, value) = line.split_once(':').ok_or(RequestError::Malformed)?;
+    if key.is_empty() || !key.bytes().all(is_token_byte) {
+        return Err(RequestError::Malformed);
+    }
+    if header_lines.len() == limits.max_headers {
+        return Err(RequestError::HeadersTooLarge);
+    }
+    let value = value.trim_matches([' ', '\t']);
+    headers.insert(key.to_lowercase(), value.to_string());
+    header_lines.push((key.to_string(), value.to_string()));
+    Ok(true)
+}
+
+/// Reads one line of the request head, charging it against `budget` so a
+/// peer can't make the server buffer an endless line.
+fn read_head_line(
+    reader: &mut impl BufRead,
+    line: &mut String,
+    budget: &mut usize,
+) -> Result<(), RequestError> {
+    let n = match reader.take(*budget as u64 + 1).read_line(line) {
+        Ok(n) => n,
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => return Err(RequestError::Malformed),
+        Err(e) => return Err(RequestError::Io(e)),
+    };
+    if n > *budget {
+        return Err(RequestError::HeadersTooLarge);
+    }
+    // Every line of a complete head, the blank one ending it included, has a
+    // line ending; anything short of one means the client stopped sending.
+    if !line.ends_with('\n') {
+        return Err(RequestError::Incomplete);
+    }
+    *budget -= n;
+    Ok(())
+}
+
+impl From<io::Error> for RequestError {
+    fn from(value: io::Error) -> Self {
+        RequestError::Io(value)
+    }
+}
+
+/// Headers that describe a single connection and must not be forwarded.
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+const DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Seconds a client is told to wait before retrying a request refused while
+/// the server isn't ready, see [`ServerHandle::set_ready`].
+const STARTUP_RETRY_AFTER: u64 = 5;
+
+/// The first and the longest pause before
+/// [`HttpServer::listen_supervised`] restarts a panicked accept loop.
+const SUPERVISED_MIN_BACKOFF: Duration = Duration::from_millis(100);
+const SUPERVISED_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+const MIN_RATE_GRACE: Duration = Duration::from_secs(1);
+
+/// Wraps the connection while a request is read, failing with
+/// `io::ErrorKind::TimedOut` once the average data rate drops below the
+/// configured minimum.
+struct MinRateReader<R> {
+    inner: R,
+    min_rate: Option<u64>,
+    enforcing: bool,
+    started: Instant,
+    received: u64,
+    /// Everything read, when capturing raw requests. Bytes of requests
+    /// already handled are dropped by [`Connection::start_capture`].
+    captured: Option<Vec<u8>>,
+    pipeline: Option<PipelineLimit>,
+}
+
+impl<R> MinRateReader<R> {
+    fn new(inner: R, min_rate: Option<u64>) -> MinRateReader<R> {
+        MinRateReader {
+            inner,
+            min_rate,
+            enforcing: true,
+            started: Instant::now(),
+            received: 0,
+            captured: None,
+            pipeline: None,
+        }
+    }
+
+    /// Starts measuring from scratch, for the next request on the connection.
+    fn restart(&mut self) {
+        self.enforcing = true;
+        self.started = Instant::now();
+        self.received = 0;
+    }
+
+    fn check_rate(&self) -> io::Result<()> {
+        let Some(min_rate) = self.min_rate.filter(|_| self.enforcing) else {
+            return Ok(());
+        };
+        let elapsed = self.started.elapsed();
+        if elapsed <= MIN_RATE_GRACE {
+            return Ok(());
+        }
+        let expected = min_rate as f64 * (elapsed - MIN_RATE_GRACE).as_secs_f64();
+        if (self.received as f64) < expected {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "request data rate below minimum",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Read for MinRateReader<TcpStream> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            // Bytes past the pipelining limit are left in the socket.
+            let read = match &self.pipeline {
+                Some(limit) => self.inner.peek(buf).and_then(|n| {
+                    let allowed = limit.allowed(&buf[..n]);
+                    self.inner.read(&mut buf[..allowed])
+                }),
+                None => self.inner.read(buf),
+            };
+            match read {
+                Ok(n) => {
+                    if let Some(limit) = &mut self.pipeline {
+                        limit.advance(&buf[..n]);
+                    }
+                    self.received += n as u64;
+                    if let Some(captured) = &mut self.captured {
+                        captured.extend_from_slice(&buf[..n]);
+                    }
+                    self.check_rate()?;
+                    return Ok(n);
+                }
+                // The socket read timeout is only set to wake us up for a rate check.
+                Err(e)
+                    if self.min_rate.is_some()
+                        && self.enforcing
+                        && matches!(
+                            e.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                {
+                    self.check_rate()?
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Connection state shared between the server and the request being handled,
+/// so the body can be read lazily and the response written by the server.
+struct Connection {
+    reader: BufReader<MinRateReader<TcpStream>>,
+    limits: RequestLimits,
+    /// Bytes left in the body, or in the current chunk of a chunked body.
+    body_remaining: u64,
+    /// Set while more chunks of a chunked body are to come.
+    chunked: bool,
+    /// Whether the current chunk's data is followed by a line ending yet to
+    /// be read.
+    chunk_started: bool,
+    /// Body bytes read so far, after chunked decoding.
+    body_read: u64,
+    /// Header lines sent after the last chunk, with their names as sent.
+    trailers: Vec<(String, String)>,
+    /// Set once a chunked body announces a chunk taking it over the maximum
+    /// body size (413), or one that is too large by itself or whose size
+    /// can't be read (400). None of the rest of the body is read.
+    body_rejected: Option<Status>,
+    /// Set while a client that sent `Expect: 100-continue` is waiting to be
+    /// told to send the body. The interim response goes out on first read.
+    expect_continue: bool,
+    /// Set once reading the body has begun, which starts timing it against
+    /// the minimum data rate.
+    body_started: bool,
+    /// Set when the server writes the response after the handler returns;
+    /// otherwise requests write straight to their stream.
+    defer_response: bool,
+    response: Option<Reply>,
+    clock: Arc<dyn Clock>,
+    proxies: Arc<TrustedProxies>,
+    buffers: ConnectionBuffers,
+}
+
+impl Connection {
+    fn new(
+        stream: TcpStream,
+        limits: &RequestLimits,
+        buffer_size: usize,
+        clock: Arc<dyn Clock>,
+    ) -> io::Result<Connection> {
+        if limits.min_rate.is_some() {
+            stream.set_read_timeout(Some(MIN_RATE_GRACE))?;
+        }
+        Ok(Connection {
+            reader: BufReader::with_capacity(
+                buffer_size,
+                MinRateReader::new(stream, limits.min_rate),
+            ),
+            limits: limits.clone(),
+            body_remaining: 0,
+            chunked: false,
+            chunk_started: false,
+            body_read: 0,
+            trailers: Vec::new(),
+            body_rejected: None,
+            expect_continue: false,
+            body_started: false,
+            defer_response: true,
+            response: None,
+            clock,
+            proxies: Arc::default(),
+            buffers: ConnectionBuffers::default(),
+        })
+    }
+
+    fn peer_is_trusted_proxy(&self) -> bool {
+        self.reader
+            .get_ref()
+            .inner
+            .peer_addr()
+            .is_ok_and(|peer| self.proxies.contains(peer.ip()))
+    }
+
+    /// Waits up to `idle_timeout` for the next request to start arriving.
+    /// Returns false if the client closed the connection or stayed idle.
+    fn wait_for_request(&mut self, idle_timeout: Duration) -> io::Result<bool> {
+        let request_timeout = self.reader.get_ref().min_rate.map(|_| MIN_RATE_GRACE);
+        self.reader.get_mut().enforcing = false;
+        self.reader
+            .get_ref()
+            .inner
+            .set_read_timeout(Some(idle_timeout))?;
+        // A client closing the connection between requests, cleanly or with
+        // a reset, is done with it rather than failing.
+        let ready = match self.reader.fill_buf() {
+            Ok(buf) => !buf.is_empty(),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                ) =>
+            {
+                false
+            }
+            Err(e) => return Err(e),
+        };
+        self.reader
+            .get_ref()
+            .inner
+            .set_read_timeout(request_timeout)?;
+        self.reader.get_mut().restart();
+        Ok(ready)
+    }
+
+    /// Forgets the captured bytes of earlier requests, keeping those read
+    /// ahead, which belong to the request about to be parsed.
+    fn start_capture(&mut self) {
+        let buffered = self.reader.buffer().len();
+        if let Some(captured) = &mut self.reader.get_mut().captured {
+            captured.drain(..captured.len() - buffered);
+        }
+    }
+
+    /// The captured bytes the current request has been read from so far.
+    fn captured(&self) -> Vec<u8> {
+        let buffered = self.reader.buffer().len();
+        self.reader
+            .get_ref()
+            .captured
+            .as_ref()
+            .map_or_else(Vec::new, |captured| {
+                captured[..captured.len() - buffered].to_vec()
+            })
+    }
+
+    /// Starts the body of a new request.
+    fn start_body(&mut self, length: u64, chunked: bool, expect_continue: bool) {
+        self.body_remaining = length;
+        self.chunked = chunked;
+        self.chunk_started = false;
+        self.body_read = 0;
+        self.trailers.clear();
+        self.body_rejected = None;
+        self.expect_continue = expect_continue && (length > 0 || chunked);
+        self.body_started = false;
+        // Nothing is asked of the client until the body is read.
+        self.reader.get_mut().enforcing = false;
+    }
+
+    /// Sends the 100 Continue a client may be waiting for and starts timing
+    /// the body, the first time it is read.
+    fn begin_body(&mut self) -> io::Result<()> {
+        if self.expect_continue {
+            self.expect_continue = false;
+            let mut stream = &self.reader.get_ref().inner;
+            stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+        }
+        if !self.body_started {
+            self.body_started = true;
+            self.reader.get_mut().restart();
+        }
+        Ok(())
+    }
+
+    /// Reads the size line of the next chunk, and the trailer after the last
+    /// one.
+    fn next_chunk(&mut self) -> io::Result<()> {
+        if self.body_rejected.is_some() {
+            return Err(rejected_body());
+        }
+        let mut line = String::new();
+        if self.chunk_started {
+            self.read_chunk_line(&mut line)?;
+            if !line.trim_end().is_empty() {
+                return Err(invalid_chunk());
+            }
+            line.clear();
+        }
+        self.read_chunk_line(&mut line)?;
+        let size = line
+            .trim_end()
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim_end_matches([' ', '\t']);
+        // Hex digits only: `from_str_radix` would also take a sign, as in
+        // `+5`, which a proxy in front may read differently.
+        let size = Some(size)
+            .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|size| u64::from_str_radix(size, 16).ok());
+        // Chunks are read into the reader's buffers whatever size they
+        // declare, so a huge one costs nothing up front; it is refused as
+        // soon as it is announced, before any of its data is read.
+        let Some(size) = size else {
+            self.body_rejected = Some(Status::BadRequest);
+            return Err(invalid_chunk());
+        };
+        self.chunk_started = true;
+        if self.limits.max_chunk_size.is_some_and(|max| size > max) {
+            self.body_rejected = Some(Status::BadRequest);
+            return Err(rejected_body());
+        }
+        if self
+            .limits
+            .max_body_size
+            .is_some_and(|max| self.body_read.saturating_add(size) > max)
+        {
+            self.body_rejected = Some(Status::PayloadTooLarge);
+            return Err(rejected_body());
+        }
+        if size > 0 {
+            self.body_remaining = size;
+            return Ok(());
+        }
+        // Trailers are held to the same limits as the request's headers.
+        let mut budget = self.limits.max_header_size;
+        loop {
+            line.clear();
+            self.read_chunk_line(&mut line)?;
+            let trailer = line.strip_suffix('\n').unwrap_or(&line);
+            let trailer = trailer.strip_suffix('\r').unwrap_or(trailer);
+            if trailer.is_empty() {
+                break;
+            }
+            budget = budget
+                .checked_sub(line.len())
+                .ok_or_else(trailers_too_large)?;
+            if self.trailers.len() >= self.limits.max_headers {
+                return Err(trailers_too_large());
+            }
+            // Named like a header: no whitespace around the name.
+            let (name, value) = trailer.split_once(':').ok_or_else(invalid_chunk)?;
+            if name.is_empty() || !name.bytes().all(is_token_byte) {
+                return Err(invalid_chunk());
+            }
+            let value = value.trim_matches([' ', '\t']);
+            self.trailers.push((name.to_string(), value.to_string()));
+        }
+        self.chunked = false;
+        Ok(())
+    }
+
+    fn read_chunk_line(&mut self, line: &mut String) -> io::Result<()> {
+        let limit = self.limits.max_header_size as u64;
+        let n = (&mut self.reader).take(limit).read_line(line)?;
+        if n == 0 {
+            return Err(incomplete_body());
+        }
+        if !line.ends_with('\n') {
+            return Err(invalid_chunk());
+        }
+        Ok(())
+    }
+
+    fn read_body(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.begin_body()?;
+        while self.body_remaining == 0 {
+            if !self.chunked {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+        let max = buf.len().min(self.body_remaining as usize);
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(incomplete_body());
+        }
+        self.body_remaining -= n as u64;
+        self.body_read += n as u64;
+        Ok(n)
+    }
+
+    /// Skips the rest of the body straight out of the read buffer, a
+    /// buffer's worth at a time, without copying it anywhere.
+    fn discard_body(&mut self) -> io::Result<u64> {
+        if !self.body_started {
+            self.body_started = true;
+            self.reader.get_mut().restart();
+        }
+        let mut discarded = 0;
+        loop {
+            while self.body_remaining == 0 {
+                if !self.chunked {
+                    return Ok(discarded);
+                }
+                self.next_chunk()?;
+            }
+            let available = self.reader.fill_buf()?.len();
+            if available == 0 {
+                return Err(incomplete_body());
+            }
+            let n = available.min(self.body_remaining as usize);
+            self.reader.consume(n);
+            self.body_remaining -= n as u64;
+            self.body_read += n as u64;
+            discarded += n as u64;
+        }
+    }
+}
+
+/// Reads the remainder of the current request's body, and nothing past it.
+struct BodyReader<C> {
+    conn: C,
+}
+
+impl<C: std::ops::DerefMut<Target = Connection>> Read for BodyReader<C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let conn = &mut *self.conn;
+        let read = conn.read_body(buf);
+        // Whatever the handler makes of it, the client is told its body
+        // couldn't be read, as for a chunked body that was refused.
+        if let (Err(e), None) = (&read, conn.body_rejected) {
+            conn.body_rejected = Some(match e.kind() {
+                io::ErrorKind::TimedOut => Status::RequestTimeout,
+                _ => Status::BadRequest,
+            });
+        }
+        read
+    }
+}
+
+fn incomplete_body() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "connection closed before the request body was complete",
+    )
+}
+
+fn rejected_body() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "chunked request body exceeds the size limits",
+    )
+}
+
+fn trailers_too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "request trailers exceed the header limits",
+    )
+}
+
+fn invalid_chunk() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed chunked request body")
+}
+
+/// A file in the system temporary directory that is removed on drop.
+#[derive(Debug)]
+pub struct TempPath {
+    path: PathBuf,
+}
+
+impl TempPath {
+    fn create() -> io::Result<(TempPath, File)> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let path = std::env::temp_dir().join(format!(
+                "rustgin-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => return Ok((TempPath { path }, file)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Keeps the file on disk and returns its path.
+    pub fn keep(self) -> PathBuf {
+        let path = self.path.clone();
+        std::mem::forget(self);
+        path
+    }
+}
+
+impl std::ops::Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Hands the body's buffer back to the pool, if the server keeps one.
+impl Drop for HTTPRequest {
+    fn drop(&mut self) {
+        if let (Some(body), Ok(mut conn)) = (self.body.take(), self.conn.try_borrow_mut()) {
+            conn.buffers.give(body);
+        }
+    }
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(feature = "digest")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl HTTPRequest {
+    pub fn new(stream: TcpStream) -> Option<HTTPRequest> {
+        let mut conn = Connection::new(
+            stream,
+            &RequestLimits::default(),
+            DEFAULT_READ_BUFFER_SIZE,
+            Arc::new(SystemClock),
+        )
+        .ok()?;
+        conn.defer_response = false;
+        HTTPRequest::parse(Rc::new(RefCell::new(conn))).ok()
+    }
+
+    fn parse(conn: Rc<RefCell<Connection>>) -> Result<HTTPRequest, RequestError> {
+        let mut connection = conn.borrow_mut();
+        connection.start_capture();
+        let limits = connection.limits.clone();
+        let mut budget = limits.max_header_size;
+        let (first_line, headers, header_lines) =
+            read_head(&mut connection.reader, &limits, &mut budget)?;
+        let mut first_line = first_line.split(' ');
+        let method = first_line.next().ok_or(RequestError::Malformed)?;
+        let target = first_line.next().ok_or(RequestError::Malformed)?;
+        let http_version = first_line.next().ok_or(RequestError::Malformed)?.trim_end();
+        if Method::parse(method).is_none() {
+            let token = !method.is_empty() && method.bytes().all(is_token_byte);
+            return Err(if token {
+                RequestError::UnknownMethod
+            } else {
+                RequestError::Malformed
+            });
+        }
+
+        // Conflicting lengths would let the server and a proxy in front of it
+        // disagree about where this request ends.
+        let mut lengths = header_lines
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+            .map(|(_, value)| value);
+        let body_size = match lengths.next() {
+            // Digits only: `parse` would also take a sign, as in `+0`, which
+            // a proxy in front may read differently.
+            Some(size)
+                if lengths.all(|other| other == size)
+                    && !size.is_empty()
+                    && size.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                size.parse::<u64>().map_err(|_| RequestError::Malformed)?
+            }
+            Some(_) => return Err(RequestError::Malformed),
+            None => 0,
+        };
+        // Only chunked is supported, and never alongside a Content-Length.
+        let mut codings = header_lines
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("transfer-encoding"))
+            .map(|(_, value)| value);
+        let chunked = match (codings.next(), codings.next()) {
+            (None, _) => false,
+            (Some(coding), None)
+                if coding.eq_ignore_ascii_case("chunked")
+                    && !headers.contains_key("content-length") =>
+            {
+                true
+            }
+            _ => return Err(RequestError::Malformed),
+        };
+        // A body means nothing on GET or HEAD, so one is far more likely to
+        // be a smuggling attempt than anything a handler expects.
+        if !limits.allow_get_bodies
+            && (body_size > 0 || chunked)
+            && (method.eq_ignore_ascii_case("GET") || method.eq_ignore_ascii_case("HEAD"))
+        {
+            return Err(RequestError::Malformed);
+        }
+        if limits.max_body_size.is_some_and(|max| body_size > max) {
+            return Err(RequestError::BodyTooLarge);
+        }
+        let expect_continue = http_version != "HTTP/1.0"
+            && headers
+                .get("expect")
+                .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"));
+        connection.start_body(body_size, chunked, expect_continue);
+        let head_len = limits.max_header_size - budget;
+        let method = method.to_string();
+        let target = target.to_string();
+        let http_version = http_version.to_string();
+        let span = Rc::new(RequestSpan::new(&method, &target, connection.clock.clone()));
+        let from_trusted_proxy = connection.peer_is_trusted_proxy();
+        drop(connection);
+
+        Ok(HTTPRequest {
+            method,
+            target,
+            http_version,
+            headers,
+            header_lines,
+            head_len,
+            body: OnceCell::new(),
+            params: Params::new(),
+            mount: None,
+            span,
+            conn,
+            responded: false,
+            from_trusted_proxy,
+            forwarded: OnceCell::new(),
+            raw: OnceCell::new(),
+            lossy_body: OnceCell::new(),
+            streamed: None,
+            #[cfg(feature = "sessions")]
+            session: None,
+        })
+    }
+
+    /// A copy of the request line and headers that outlives the handler, for
+    /// the server's response hooks. It can't be responded to.
+    fn detached(&self) -> HTTPRequest {
+        HTTPRequest {
+            method: self.method.clone(),
+            target: self.target.clone(),
+            http_version: self.http_version.clone(),
+            headers: self.headers.clone(),
+            header_lines: self.header_lines.clone(),
+            head_len: self.head_len,
+            body: OnceCell::new(),
+            params: self.params.clone(),
+            mount: self.mount.clone(),
+            conn: self.conn.clone(),
+            responded: true,
+            from_trusted_proxy: self.from_trusted_proxy,
+            forwarded: self.forwarded.clone(),
+            raw: self.raw.clone(),
+            lossy_body: OnceCell::new(),
+            streamed: None,
+            span: self.span.clone(),
+            #[cfg(feature = "sessions")]
+            session: None,
+        }
+    }
+
+    fn body_reader(&self) -> BodyReader<RefMut<'_, Connection>> {
+        BodyReader {
+            conn: self.conn.borrow_mut(),
+        }
+    }
+
+    fn body_bytes(&self) -> &[u8] {
+        self.body.get_or_init(|| {
+            let mut buf = self.conn.borrow_mut().buffers.take();
+            if let Err(e) = self.body_reader().read_to_end(&mut buf) {
+                log::debug!("Aborted reading request body: {}", e);
+            }
+            buf
+        })
+    }
+
+    /// Reads and throws away whatever is left of the body, returning how many
+    /// bytes that was, so the connection can take the next request. A client
+    /// still waiting for 100 Continue isn't asked for its body; the connection
+    /// is closed after the response instead.
+    pub fn drain_body(&mut self) -> io::Result<u64> {
+        if self.body.get().is_some() || self.conn.borrow().expect_continue {
+            return Ok(0);
+        }
+        self.conn.borrow_mut().discard_body()
+    }
+
+    /// Streams the body into a new temporary file instead of memory, returning
+    /// its path and size. The file is deleted when the returned [`TempPath`]
+    /// is dropped unless [`TempPath::keep`] is called.
+    pub fn body_to_temp_file(&mut self) -> io::Result<(TempPath, u64)> {
+        let (path, mut file) = TempPath::create()?;
+        let size = match self.body.get() {
+            Some(body) => {
+                file.write_all(body)?;
+                body.len() as u64
+            }
+            None => io::copy(&mut self.body_reader(), &mut file)?,
+        };
+        file.sync_all()?;
+        Ok((path, size))
+    }
+
+    /// For a route with [`Route::stream_body_to`], the number of body bytes
+    /// written to its sink, or why that stopped short.
+    pub fn streamed_body(&self) -> Option<&io::Result<u64>> {
+        self.streamed.as_ref()
+    }
+
+    fn stream_body_into(&mut self, sink: &mut impl Write) -> io::Result<u64> {
+        let written = io::copy(&mut self.body_reader(), sink)?;
+        sink.flush()?;
+        Ok(written)
+    }
+
+    /// Reads the body, calling `progress` with the bytes read so far and the
+    /// declared total after every read. The total is `None` for chunked
+    /// bodies. The body is kept, so [`HTTPRequest::get_body`] keeps working
+    /// afterwards.
+    pub fn read_body_with_progress(
+        &mut self,
+        mut progress: impl FnMut(u64, Option<u64>),
+    ) -> io::Result<&[u8]> {
+        if self.body.get().is_none() {
+            let total = match self.headers.get("content-length") {
+                Some(length) => length.parse::<u64>().ok(),
+                None => None,
+            };
+            let mut reader = self.body_reader();
+            let mut body = Vec::new();
+            let mut buf = [0; 8192];
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                body.extend_from_slice(&buf[..n]);
+                progress(body.len() as u64, total);
+            }
+            drop(reader);
+            let _ = self.body.set(body);
+        }
+        Ok(self.body_bytes())
+    }
+
+    /// Reads the body in a single pass, hashing it as it arrives, and returns
+    /// it with its digest. The body is kept once, in the request, so
+    /// [`HTTPRequest::get_body`] keeps working afterwards without a copy.
+    #[cfg(feature = "digest")]
+    pub fn body_digest(&mut self, algo: DigestAlgorithm) -> io::Result<(&[u8], Vec<u8>)> {
+        fn digest<D: Digest>(mut reader: impl Read, body: &mut Vec<u8>) -> io::Result<Vec<u8>> {
+            let mut hasher = D::new();
+            let mut buf = [0; 8192];
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+                body.extend_from_slice(&buf[..n]);
+            }
+            Ok(hasher.finalize().to_vec())
+        }
+
+        if let Some(body) = self.body.get() {
+            let hash = match algo {
+                DigestAlgorithm::Sha256 => Sha256::digest(body).to_vec(),
+                DigestAlgorithm::Sha512 => Sha512::digest(body).to_vec(),
+            };
+            return Ok((body, hash));
+        }
+        let mut body = Vec::new();
+        let hash = match algo {
+            DigestAlgorithm::Sha256 => digest::<Sha256>(self.body_reader(), &mut body)?,
+            DigestAlgorithm::Sha512 => digest::<Sha512>(self.body_reader(), &mut body)?,
+        };
+        Ok((self.body.get_or_init(|| body), hash))
+    }
+
+    pub fn send(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let response = HTTPResponse::new(Status::Ok, text.to_string());
+        self.send_response(&response)
+    }
+
+    /// Holds the request open until a body arrives on `receiver` and sends
+    /// it with 200, or sends 204 once `timeout` passes without one, or as
+    /// soon as every sender is gone.
+    pub fn long_poll(
+        &mut self,
+        receiver: &mpsc::Receiver<Vec<u8>>,
+        timeout: Duration,
+    ) -> Result<(), Box<dyn Error>> {
+        let response = match receiver.recv_timeout(timeout) {
+            Ok(data) => HTTPResponse::from_bytes(Status::Ok, data),
+            Err(_) => HTTPResponse::from_bytes(Status::NoContent, Vec::new()),
+        };
+        self.send_response(&response)
+    }
+
+    pub fn send_json(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let mut response = HTTPResponse::new(Status::Ok, text.to_string());
+        response.add_header("Content-Type".to_string(), "application/json".to_string());
+        self.send_response(&response)
+    }
+
+    /// Sends `response` to the client. Each request gets exactly one response,
+    /// so any further call fails with [`ResponseAlreadySent`] and leaves the
+    /// first response in place. The server only falls back to its own 500
+    /// response when a handler returns without having sent anything.
+    pub fn send_response(&mut self, response: &HTTPResponse) -> Result<(), Box<dyn Error>> {
+        self.reply(Reply::Response(response.clone()))
+    }
+
+    /// Sends a response serialized ahead of time with [`HTTPResponse::freeze`].
+    pub fn send_frozen(&mut self, response: &FrozenResponse) -> Result<(), Box<dyn Error>> {
+        self.reply(Reply::Frozen(response.clone()))
+    }
+
+    fn reply(&mut self, mut reply: Reply) -> Result<(), Box<dyn Error>> {
+        if self.responded {
+            return Err(Box::new(ResponseAlreadySent));
+        }
+        self.responded = true;
+        let mut conn = self.conn.borrow_mut();
+        if conn.defer_response {
+            conn.response = Some(reply);
+        } else {
+            let conn = &mut *conn;
+            let mut stream = &conn.reader.get_ref().inner;
+            let close_delimited = self.http_version == "HTTP/1.0";
+            if let (true, Reply::Response(response)) = (close_delimited, &mut reply) {
+                response.trailers.clear();
+            }
+            reply.write_to(
+                &mut stream,
+                "",
+                self.is_head(),
+                close_delimited,
+                &mut conn.buffers,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Methods are matched in any case, so `head` is a HEAD request too.
+    fn is_head(&self) -> bool {
+        Method::parse(&self.method) == Some(Method::HEAD)
+    }
+
+    fn wants_keep_alive(&self) -> bool {
+        let tokens = self.connection_tokens();
+        if tokens.iter().any(|token| token == "close") {
+            return false;
+        }
+        self.http_version != "HTTP/1.0" || tokens.iter().any(|token| token == "keep-alive")
+    }
+
+    /// The lowercased options listed in every `Connection` header, such as
+    /// `close`, `keep-alive`, `upgrade`, or the names of headers meant only
+    /// for this hop.
+    pub fn connection_tokens(&self) -> Vec<String> {
+        self.header_values("connection")
+            .flat_map(|value| value.split(','))
+            .map(|token| token.trim().to_lowercase())
+            .filter(|token| !token.is_empty())
+            .collect()
+    }
+
+    /// The headers to pass on when forwarding the request, as sent by the
+    /// client but without the hop-by-hop headers: the standard ones and any
+    /// named in `Connection`.
+    pub fn forwardable_headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        let tokens = self.connection_tokens();
+        self.original_headers().filter(move |(name, _)| {
+            let name = name.to_lowercase();
+            !HOP_BY_HOP_HEADERS.contains(&name.as_str()) && !tokens.contains(&name)
+        })
+    }
+
+    pub fn get_method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn get_target(&self) -> &str {
+        &self.target
+    }
+
+    /// The host the request is for, from the `Host` header without its port.
+    /// Empty when the client didn't send one.
+    /// Behind a trusted proxy, `X-Forwarded-Host` is used instead.
+    pub fn host(&self) -> &str {
+        self.host_header()
+            .map_or("", |value| vhost::split_host(value).0)
+    }
+
+    /// The port given in the `Host` header, if any.
+    pub fn host_port(&self) -> Option<u16> {
+        self.host_header()
+            .and_then(|value| vhost::split_host(value).1)
+    }
+
+    fn host_header(&self) -> Option<&str> {
+        let forwarded = match self.client_element() {
+            Some(element) => element.host.as_deref(),
+            None => self.forwarded_header("x-forwarded-host"),
+        };
+        forwarded.or_else(|| self.headers.get("host").map(String::as_str))
+    }
+
+    /// The elements of the `Forwarded` headers, one per proxy, nearest the
+    /// client first, when the request came through a trusted proxy, see
+    /// [`HttpServer::trust_proxies`]. Where present, they are preferred over
+    /// the `X-Forwarded-*` headers for [`HTTPRequest::peer_addr`],
+    /// [`HTTPRequest::scheme`] and [`HTTPRequest::host`].
+    pub fn forwarded(&self) -> &[ForwardedElement] {
+        self.forwarded.get_or_init(|| {
+            if !self.from_trusted_proxy {
+                return Vec::new();
+            }
+            self.header_lines
+                .iter()
+                .filter(|(name, _)| name.eq_ignore_ascii_case("forwarded"))
+                .flat_map(|(_, value)| ForwardedElement::parse_all(value))
+                .collect()
+        })
+    }
+
+    /// The `Forwarded` element describing the request the client sent, the
+    /// same one [`HTTPRequest::peer_addr`] takes the client from.
+    fn client_element(&self) -> Option<&ForwardedElement> {
+        let (_, offset) = self.client_hop()?;
+        let elements = self.forwarded();
+        elements.get(elements.len().checked_sub(offset?)?)
+    }
+
+    /// The value of an `X-Forwarded-*` header describing the request the
+    /// client sent. Each proxy appends one value to each header, so it is the
+    /// one as far from the end as the client's `X-Forwarded-For` entry.
+    fn forwarded_header(&self, name: &str) -> Option<&str> {
+        if !self.forwarded().is_empty() {
+            return None;
+        }
+        let (_, offset) = self.client_hop()?;
+        let values = self.headers.get(name)?.split(',').collect::<Vec<_>>();
+        values
+            .get(values.len().checked_sub(offset?)?)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// The address of the client. Behind a trusted proxy this is the last
+    /// untrusted address in `X-Forwarded-For`, so addresses the client made
+    /// up itself are skipped.
+    pub fn peer_addr(&self) -> Option<IpAddr> {
+        self.client_hop().map(|(client, _)| client)
+    }
+
+    /// The client's address and, behind a trusted proxy, how far from the
+    /// end of the forwarding headers the entry for its request is, 1 being
+    /// the last. Entries are walked from the end for as long as they were
+    /// added by trusted proxies.
+    fn client_hop(&self) -> Option<(IpAddr, Option<usize>)> {
+        let peer = self
+            .conn
+            .borrow()
+            .reader
+            .get_ref()
+            .inner
+            .peer_addr()
+            .ok()?
+            .ip();
+        if !self.from_trusted_proxy {
+            return Some((peer, None));
+        }
+        let hops = if self.forwarded().is_empty() {
+            let forwarded_for = self.headers.get("x-forwarded-for");
+            forwarded_for
+                .into_iter()
+                .flat_map(|value| value.split(','))
+                .map(node_ip)
+                .collect::<Vec<_>>()
+        } else {
+            self.forwarded()
+                .iter()
+                .map(|element| element.for_.as_deref().and_then(node_ip))
+                .collect()
+        };
+        let proxies = self.conn.borrow().proxies.clone();
+        let mut client = peer;
+        // The peer is trusted, so the last entry, if any, is its own.
+        let mut offset = 1;
+        for (i, hop) in hops.into_iter().rev().enumerate() {
+            if !proxies.contains(client) {
+                break;
+            }
+            offset = i + 1;
+            match hop {
+                Some(hop) => client = hop,
+                None => break,
+            }
+        }
+        Some((client, Some(offset)))
+    }
+
+    /// `https` when a trusted proxy says the client connected with TLS,
+    /// otherwise `http`.
+    pub fn scheme(&self) -> &str {
+        let proto = match self.client_element() {
+            Some(element) => element.proto.as_deref(),
+            None => self.forwarded_header("x-forwarded-proto"),
+        };
+        match proto {
+            Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+            _ => "http",
+        }
+    }
+
+    /// The target without its query string, below the prefix for a request
+    /// handled by a mount, e.g. `/users` for `/admin/users` under `/admin`.
+    pub fn path(&self) -> &str {
+        let path = self.original_path();
+        match &self.mount {
+            Some(prefix) => match &path[prefix.len()..] {
+                "" => "/",
+                rest => rest,
+            },
+            None => path,
+        }
+    }
+
+    /// The target without its query string as the client sent it, prefix and
+    /// all.
+    pub fn original_path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// A parameter captured by the matched route, e.g. `id` for `/posts/:id`,
+    /// or `path` for `/files/*path`, which captures the rest of the path.
+    /// Parameters in an optional group that wasn't present are `None`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(String::as_str)
+    }
+
+    /// A handle to the connection's socket, e.g. to check the options set
+    /// with [`HttpServer::configure_stream`]. Reading from or writing to it
+    /// directly bypasses the server.
+    pub fn tcp_stream(&self) -> io::Result<TcpStream> {
+        self.conn.borrow().reader.get_ref().inner.try_clone()
+    }
+
+    pub fn get_http_version(&self) -> &str {
+        &self.http_version
+    }
+
+    pub fn get_headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
+    /// Every header exactly as the client sent it: in order, repeats
+    /// included, with names in their original casing. Useful for forwarding
+    /// a request upstream unchanged.
+    pub fn original_headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.header_lines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Every value sent for the header `name`, in order, for headers that
+    /// may be repeated.
+    pub fn header_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.header_lines
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The value of the cookie `name` sent with the request.
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get("cookie")?
+            .split(';')
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The value of a cookie set with [`HTTPResponse::set_signed_cookie`], or
+    /// `None` if it is missing or its signature doesn't match `key`.
+    #[cfg(feature = "signed-cookies")]
+    pub fn signed_cookie(&self, name: &str, key: &CookieKey) -> Option<String> {
+        key.verify(name, self.cookie(name)?).map(str::to_string)
+    }
+
+    /// The value of a cookie set with [`HTTPResponse::set_private_cookie`], or
+    /// `None` if it is missing or wasn't encrypted with `key`.
+    #[cfg(feature = "private-cookies")]
+    pub fn private_cookie(&self, name: &str, key: &CookieKey) -> Option<String> {
+        key.decrypt(name, self.cookie(name)?)
+    }
+
+    /// The session for this request, or `None` unless sessions were enabled
+    /// with [`HttpServer::sessions`]. Values set here are readable by later
+    /// requests from the same client.
+    #[cfg(feature = "sessions")]
+    pub fn session(&self) -> Option<&Session> {
+        self.session.as_deref()
+    }
+
+    /// Where handlers and hooks record values to be logged with the request,
+    /// e.g. `request.span().record("db_ms", 12)`.
+    pub fn span(&self) -> &RequestSpan {
+        &self.span
+    }
+
+    /// Reports that `name` took `millis` milliseconds in a `Server-Timing`
+    /// header on the response, e.g. `db;dur=12.3`, which browsers show in
+    /// their developer tools. Can be called from handlers and from
+    /// [`HttpServer::after`] hooks; every metric recorded is sent, in order.
+    pub fn server_timing(&self, name: &str, millis: f64) {
+        self.span.server_timing(name, millis);
+    }
+
+    /// The request exactly as it was received: request line, headers and
+    /// body, with a chunked body still in its chunks. The rest of the body is
+    /// read first, as [`HTTPRequest::get_body`] does. Empty unless the server
+    /// was told to [`HttpServer::capture_raw`].
+    pub fn raw(&self) -> &[u8] {
+        self.raw.get_or_init(|| {
+            self.body_bytes();
+            self.conn.borrow().captured()
+        })
+    }
+
+    /// The size of the body after chunked decoding. The body is read first if
+    /// the handler hasn't read it yet.
+    pub fn body_len(&self) -> usize {
+        self.body_bytes();
+        self.conn.borrow().body_read as usize
+    }
+
+    /// The trailer fields sent after a chunked body, in order, with their
+    /// names as sent. The body is read first if the handler hasn't read it
+    /// yet.
+    pub fn trailers(&self) -> Vec<(String, String)> {
+        self.body_bytes();
+        self.conn.borrow().trailers.clone()
+    }
+
+    /// The body, or why it couldn't be read in full: the client closed the
+    /// connection or sent it too slowly, or a chunked body broke the limits.
+    /// The server answers such a request with 400 (408 when too slow, 413
+    /// when too large) whatever the handler sends.
+    pub fn try_body(&self) -> io::Result<&[u8]> {
+        let body = self.body_bytes();
+        match self.conn.borrow().body_rejected {
+            None => Ok(body),
+            Some(status) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("the request body could not be read: {}", status),
+            )),
+        }
+    }
+
+    /// The body as text, with anything that isn't valid UTF-8 replaced by
+    /// U+FFFD as in [`HTTPRequest::body_lossy`]. Use
+    /// [`HTTPRequest::body_text`] to reject such bodies instead.
+    pub fn get_body(&self) -> &str {
+        match self.body_text() {
+            Ok(text) => text,
+            Err(_) => self
+                .lossy_body
+                .get_or_init(|| self.body_lossy().into_owned()),
+        }
+    }
+
+    /// The body as text, or where it stops being valid UTF-8. Returning the
+    /// error from a handler answers the request with 400.
+    pub fn body_text(&self) -> Result<&str, InvalidUtf8Body> {
+        std::str::from_utf8(self.body_bytes()).map_err(|e| InvalidUtf8Body {
+            offset: e.valid_up_to(),
+        })
+    }
+
+    /// The body as text, with anything that isn't valid UTF-8 replaced by
+    /// U+FFFD.
+    pub fn body_lossy(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(self.body_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        time::Instant,
+    };
+
+    use super::{
+        buffered_head, head_by_lines, head_from_buffer, Head, HttpServer, RequestLimits,
+        DEFAULT_READ_BUFFER_SIZE,
+    };
+
+    fn stream() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        TcpStream::connect(listener.local_addr().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn connections_use_the_configured_read_buffer_size() {
+        let mut server = HttpServer::new(0);
+        let conn = server.connection(stream()).unwrap();
+        assert_eq!(conn.reader.capacity(), DEFAULT_READ_BUFFER_SIZE);
+
+        for size in [1, 512, 64 * 1024] {
+            server.set_read_buffer_size(size);
+            let conn = server.connection(stream()).unwrap();
+            assert_eq!(conn.reader.capacity(), size);
+        }
+        server.set_read_buffer_size(0);
+        assert_eq!(server.connection(stream()).unwrap().reader.capacity(), 1);
+    }
+
+    #[test]
+    fn pipelined_requests_past_the_limit_are_left_unread() {
+        const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let pipelined = || {
+            let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let (accepted, _) = listener.accept().unwrap();
+            client.write_all(&GET.repeat(10)).unwrap();
+            let mut peeked = [0; 1024];
+            while accepted.peek(&mut peeked).unwrap() < GET.len() * 10 {}
+            (client, accepted)
+        };
+
+        let mut server = HttpServer::new(0);
+        let (_client, accepted) = pipelined();
+        let mut conn = server.connection(accepted).unwrap();
+        assert_eq!(conn.reader.fill_buf().unwrap().len(), GET.len() * 10);
+
+        server.set_max_pipelined(3);
+        let (_client, accepted) = pipelined();
+        let mut conn = server.connection(accepted).unwrap();
+        assert_eq!(conn.reader.fill_buf().unwrap().len(), GET.len() * 3);
+        // With the first answered, only one more may be read once the rest
+        // of those read have been.
+        conn.reader.consume(GET.len() * 3);
+        conn.reader.get_mut().pipeline.as_mut().unwrap().answered();
+        assert_eq!(conn.reader.fill_buf().unwrap().len(), GET.len());
+    }
+
+    const HEADS: [&str; 4] = [
+        "GET / HTTP/1.1\r\nHost: x\r\n\r\n",
+        "GET /a?b=c HTTP/1.1\r\nHost: x\r\nAccept: */*\r\nX-Test:\t spaced \r\n\r\n",
+        "POST /upload HTTP/1.1\nHost: x\nContent-Length: 3\n\nabc",
+        "HEAD / HTTP/1.0\r\nhost: x\r\nX-Dup: 1\r\nx-dup: 2\r\n\r\n",
+    ];
+
+    /// Parses `head` from a filled read buffer, returning what's left unread.
+    fn fast(head: &str) -> (Head, usize, Vec<u8>) {
+        let limits = RequestLimits::default();
+        let mut budget = limits.max_header_size;
+        let mut reader = BufReader::with_capacity(head.len(), head.as_bytes());
+        reader.fill_buf().unwrap();
+        let (len, count) = buffered_head(reader.buffer(), limits.max_header_size).unwrap();
+        let parsed = head_from_buffer(&mut reader, len, count, &limits, &mut budget).unwrap();
+        (parsed, budget, reader.buffer().to_vec())
+    }
+
+    fn slow(head: &str) -> (Head, usize, Vec<u8>) {
+        let limits = RequestLimits::default();
+        let mut budget = limits.max_header_size;
+        let mut reader = BufReader::with_capacity(head.len(), head.as_bytes());
+        let parsed = head_by_lines(&mut reader, &limits, &mut budget).unwrap();
+        (parsed, budget, reader.fill_buf().unwrap().to_vec())
+    }
+
+    #[test]
+    fn buffered_head_parses_like_the_line_path() {
+        for head in HEADS {
+            assert_eq!(fast(head), slow(head), "{:?}", head);
+        }
+    }
+
+    /// Parsing a minimal health check head both ways. Run with
+    /// `cargo test --release -- --ignored --nocapture head_benchmark`.
+    #[test]
+    #[ignore]
+    fn head_benchmark() {
+        const PARSES: u32 = 200_000;
+        let started = Instant::now();
+        for _ in 0..PARSES {
+            assert_eq!(fast(HEADS[0]).0 .2.len(), 1);
+        }
+        let buffered = started.elapsed();
+        let started = Instant::now();
+        for _ in 0..PARSES {
+            assert_eq!(slow(HEADS[0]).0 .2.len(), 1);
+        }
+        let lines = started.elapsed();
+        println!(
+            "buffered {:?} per head, by lines {:?} per head",
+            buffered / PARSES,
+            lines / PARSES
+        );
+    }
+}
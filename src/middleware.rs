@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use crate::{HTTPRequest, HTTPResponse};
+
+type BeforeHook = Arc<dyn Fn(&HTTPRequest) -> Option<HTTPResponse> + Send + Sync>;
+type AfterHook = Arc<dyn Fn(&HTTPRequest, &mut HTTPResponse) + Send + Sync>;
+
+#[derive(Clone)]
+pub(crate) enum Middleware {
+    Before(BeforeHook),
+    After(AfterHook),
+}
+
+/// An ordered, named list of request and response hooks, built once and
+/// attached to any number of servers with [`crate::HttpServer::middleware`].
+/// Clones share the hooks themselves.
+#[derive(Clone, Default)]
+pub struct MiddlewareStack {
+    entries: Vec<(String, Middleware)>,
+}
+
+impl MiddlewareStack {
+    pub fn new() -> MiddlewareStack {
+        MiddlewareStack::default()
+    }
+
+    /// Adds a hook run like one given to [`crate::HttpServer::before_body`].
+    pub fn before(
+        &mut self,
+        name: &str,
+        hook: impl Fn(&HTTPRequest) -> Option<HTTPResponse> + Send + Sync + 'static,
+    ) -> &mut MiddlewareStack {
+        self.entries
+            .push((name.to_string(), Middleware::Before(Arc::new(hook))));
+        self
+    }
+
+    /// Adds a hook run like one given to [`crate::HttpServer::after`].
+    pub fn after(
+        &mut self,
+        name: &str,
+        hook: impl Fn(&HTTPRequest, &mut HTTPResponse) + Send + Sync + 'static,
+    ) -> &mut MiddlewareStack {
+        self.entries
+            .push((name.to_string(), Middleware::After(Arc::new(hook))));
+        self
+    }
+
+    /// The names of the hooks, in the order they run.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn hooks(&self) -> impl Iterator<Item = &Middleware> {
+        self.entries.iter().map(|(_, hook)| hook)
+    }
+}
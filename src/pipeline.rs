@@ -0,0 +1,227 @@
+/// Past this, a line stops the framer following the connection. Heads with
+/// lines this long are rejected anyway; it only bounds what is kept.
+const MAX_LINE: usize = 64 * 1024;
+
+/// Follows the requests in the bytes read from a connection, independently
+/// of the request parser, so that reads can stop at a request boundary. See
+/// [`crate::HttpServer::set_max_pipelined`]. It only decides how much is
+/// read, never how requests are parsed, so a stream it can't follow just
+/// stops it holding anything back.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestFramer {
+    state: State,
+    /// The current line of a head or of a chunked body's framing.
+    line: Vec<u8>,
+    /// Requests whose first byte has been read.
+    started: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Between requests.
+    Idle,
+    RequestLine,
+    /// The header lines, with the framing they have given so far.
+    Headers {
+        length: u64,
+        chunked: bool,
+    },
+    Body(u64),
+    ChunkSize,
+    ChunkData(u64),
+    /// The line ending after a chunk's data.
+    ChunkEnd,
+    Trailers,
+    /// Lost track of the requests, so nothing is held back.
+    Lost,
+}
+
+impl RequestFramer {
+    pub(crate) fn new() -> RequestFramer {
+        RequestFramer {
+            state: State::Idle,
+            line: Vec::new(),
+            started: 0,
+        }
+    }
+
+    /// Follows `bytes`, just read from the connection.
+    pub(crate) fn advance(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.step(byte);
+        }
+    }
+
+    /// How many of `ahead`, the next bytes to arrive, come before the first
+    /// byte of request number `max + 1` on the connection.
+    pub(crate) fn allowed(&self, ahead: &[u8], max: usize) -> usize {
+        let mut framer = self.clone();
+        for (i, &byte) in ahead.iter().enumerate() {
+            if framer.state == State::Idle && framer.started >= max {
+                return i;
+            }
+            framer.step(byte);
+        }
+        ahead.len()
+    }
+
+    fn step(&mut self, byte: u8) {
+        self.state = match self.state {
+            State::Idle => {
+                self.started += 1;
+                self.line.clear();
+                self.line.push(byte);
+                State::RequestLine
+            }
+            State::Body(remaining) if remaining > 1 => State::Body(remaining - 1),
+            State::Body(_) => State::Idle,
+            State::ChunkData(remaining) if remaining > 1 => State::ChunkData(remaining - 1),
+            State::ChunkData(_) => State::ChunkEnd,
+            State::Lost => State::Lost,
+            state if byte != b'\n' && self.line.len() < MAX_LINE => {
+                self.line.push(byte);
+                state
+            }
+            _ if byte != b'\n' => State::Lost,
+            state => {
+                let next = self.end_line(state);
+                self.line.clear();
+                next
+            }
+        };
+    }
+
+    /// The state after the line in `line` has ended.
+    fn end_line(&self, state: State) -> State {
+        let line = self.line.strip_suffix(b"\r").unwrap_or(&self.line);
+        match state {
+            State::RequestLine => State::Headers {
+                length: 0,
+                chunked: false,
+            },
+            State::Headers { length, chunked } if line.is_empty() => match (chunked, length) {
+                (true, _) => State::ChunkSize,
+                (false, 0) => State::Idle,
+                (false, length) => State::Body(length),
+            },
+            State::Headers { length, chunked } => {
+                let Some((name, value)) = std::str::from_utf8(line)
+                    .ok()
+                    .and_then(|line| line.split_once(':'))
+                else {
+                    return State::Lost;
+                };
+                let value = value.trim();
+                if name.eq_ignore_ascii_case("content-length") {
+                    value
+                        .parse()
+                        .map_or(State::Lost, |length| State::Headers { length, chunked })
+                } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                    State::Headers {
+                        length,
+                        chunked: value.eq_ignore_ascii_case("chunked"),
+                    }
+                } else {
+                    state
+                }
+            }
+            State::ChunkSize => {
+                let size = std::str::from_utf8(line)
+                    .ok()
+                    .and_then(|line| line.split(';').next())
+                    .and_then(|size| u64::from_str_radix(size.trim(), 16).ok());
+                match size {
+                    Some(0) => State::Trailers,
+                    Some(size) => State::ChunkData(size),
+                    None => State::Lost,
+                }
+            }
+            State::ChunkEnd if line.is_empty() => State::ChunkSize,
+            State::Trailers if line.is_empty() => State::Idle,
+            State::Trailers => State::Trailers,
+            _ => State::Lost,
+        }
+    }
+}
+
+/// Stops reading from a connection at the start of a request while `max`
+/// requests that came before it are still unanswered.
+#[derive(Debug, Clone)]
+pub(crate) struct PipelineLimit {
+    framer: RequestFramer,
+    max: usize,
+    answered: usize,
+}
+
+impl PipelineLimit {
+    pub(crate) fn new(max: usize) -> PipelineLimit {
+        PipelineLimit {
+            framer: RequestFramer::new(),
+            max,
+            answered: 0,
+        }
+    }
+
+    /// How many of `ahead`, the bytes waiting on the connection, to read.
+    /// The server only reads for a request it has yet to answer, so this is
+    /// never none of them unless the framer has been misled, and then all
+    /// are read rather than the connection stalling.
+    pub(crate) fn allowed(&self, ahead: &[u8]) -> usize {
+        match self.framer.allowed(ahead, self.answered + self.max) {
+            0 => ahead.len(),
+            allowed => allowed,
+        }
+    }
+
+    /// Follows `bytes`, just read from the connection.
+    pub(crate) fn advance(&mut self, bytes: &[u8]) {
+        self.framer.advance(bytes);
+    }
+
+    /// Counts a request as answered, once its response is written.
+    pub(crate) fn answered(&mut self) {
+        self.answered += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestFramer;
+
+    const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+    const POST: &[u8] = b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+    const CHUNKED: &[u8] =
+        b"POST / HTTP/1.1\nTransfer-Encoding: chunked\n\n5;x=1\r\nhello\r\n0\r\nA: b\r\n\r\n";
+
+    #[test]
+    fn stops_before_the_request_past_the_limit() {
+        let stream = [GET, POST, CHUNKED, GET].concat();
+        let framer = RequestFramer::new();
+        assert_eq!(framer.allowed(&stream, 0), 0);
+        assert_eq!(framer.allowed(&stream, 1), GET.len());
+        assert_eq!(framer.allowed(&stream, 2), GET.len() + POST.len());
+        assert_eq!(
+            framer.allowed(&stream, 3),
+            GET.len() + POST.len() + CHUNKED.len()
+        );
+        assert_eq!(framer.allowed(&stream, 4), stream.len());
+    }
+
+    #[test]
+    fn follows_requests_split_across_reads() {
+        let stream = [POST, CHUNKED, GET].concat();
+        let mut framer = RequestFramer::new();
+        for piece in stream[..POST.len() + 20].chunks(3) {
+            framer.advance(piece);
+        }
+        let rest = &stream[POST.len() + 20..];
+        assert_eq!(framer.allowed(rest, 2), CHUNKED.len() - 20);
+        assert_eq!(framer.allowed(rest, 3), rest.len());
+    }
+
+    #[test]
+    fn a_stream_it_cannot_follow_is_not_held_back() {
+        let stream = [&b"POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n"[..], GET].concat();
+        assert_eq!(RequestFramer::new().allowed(&stream, 1), stream.len());
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex};
+
+/// Buffers bigger than this are freed rather than kept, so one huge body
+/// doesn't stay allocated for good.
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+/// Enough for a request body and a response head, the most a connection
+/// uses at once.
+const CONNECTION_SPARE_BUFFERS: usize = 2;
+
+/// Byte buffers reused across requests for bodies and response heads, see
+/// [`crate::HttpServer::set_buffer_pool`]. Buffers are emptied before they
+/// go back, and only ever handed out empty, so nothing written to one can be
+/// read through it by the next request.
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    pub(crate) fn new(max_buffers: usize) -> BufferPool {
+        BufferPool {
+            buffers: Mutex::new(Vec::new()),
+            max_buffers,
+        }
+    }
+
+    /// An empty buffer, allocated afresh only when none is free.
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.buffers.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    pub(crate) fn give(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buf.clear();
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buf);
+        }
+    }
+}
+
+/// The buffers one connection keeps between its requests, so a keep-alive
+/// connection doesn't allocate them afresh for each. Falls back on the
+/// shared pool, if there is one, which gets them back when the connection
+/// ends. Like the pool, it only ever hands out empty buffers.
+#[derive(Default)]
+pub(crate) struct ConnectionBuffers {
+    spare: Vec<Vec<u8>>,
+    pool: Option<Arc<BufferPool>>,
+}
+
+impl ConnectionBuffers {
+    pub(crate) fn new(pool: Option<Arc<BufferPool>>) -> ConnectionBuffers {
+        ConnectionBuffers {
+            spare: Vec::new(),
+            pool,
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<u8> {
+        match (self.spare.pop(), &self.pool) {
+            (Some(buf), _) => buf,
+            (None, Some(pool)) => pool.take(),
+            (None, None) => Vec::new(),
+        }
+    }
+
+    pub(crate) fn give(&mut self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buf.clear();
+        if self.spare.len() < CONNECTION_SPARE_BUFFERS {
+            self.spare.push(buf);
+        } else if let Some(pool) = &self.pool {
+            pool.give(buf);
+        }
+    }
+}
+
+impl Drop for ConnectionBuffers {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            for buf in self.spare.drain(..) {
+                pool.give(buf);
+            }
+        }
+    }
+}
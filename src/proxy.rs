@@ -0,0 +1,180 @@
+use std::net::IpAddr;
+
+/// The peers whose `X-Forwarded-*` headers are believed, see
+/// [`crate::HttpServer::trust_proxies`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TrustedProxies {
+    ranges: Vec<IpRange>,
+}
+
+/// An address block such as `10.0.0.0/8`, or a single address.
+#[derive(Debug, Clone, Copy)]
+struct IpRange {
+    network: IpAddr,
+    prefix: u32,
+}
+
+impl TrustedProxies {
+    /// Panics on anything that isn't an address or address block, like an
+    /// invalid route path.
+    pub(crate) fn parse(ranges: &[&str]) -> TrustedProxies {
+        let ranges = ranges
+            .iter()
+            .map(|range| {
+                IpRange::parse(range)
+                    .unwrap_or_else(|| panic!("Invalid trusted proxy range {}", range))
+            })
+            .collect();
+        TrustedProxies { ranges }
+    }
+
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.ranges.iter().any(|range| range.contains(ip))
+    }
+}
+
+impl IpRange {
+    fn parse(range: &str) -> Option<IpRange> {
+        let (network, prefix) = match range.trim().split_once('/') {
+            Some((network, prefix)) => {
+                (network.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?))
+            }
+            None => (range.trim().parse::<IpAddr>().ok()?, None),
+        };
+        let bits: u32 = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            return None;
+        }
+        // An IPv4-mapped block such as `::ffff:10.0.0.0/104` covers the IPv4
+        // addresses it maps, which peers are compared as.
+        let canonical = network.to_canonical();
+        let prefix = match (network, canonical) {
+            (IpAddr::V6(_), IpAddr::V4(_)) => prefix.checked_sub(96)?,
+            _ => prefix,
+        };
+        Some(IpRange {
+            network: canonical,
+            prefix,
+        })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// One element of a `Forwarded` header (RFC 7239), describing a single hop,
+/// see [`crate::HTTPRequest::forwarded`]. Values are unquoted; parameters the
+/// proxy left out are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardedElement {
+    /// The client, or previous proxy, the hop received the request from,
+    /// e.g. `192.0.2.60`, `"[2001:db8::1]:4711"` or `unknown`.
+    pub for_: Option<String>,
+    /// The interface the proxy received the request on.
+    pub by: Option<String>,
+    /// The `Host` the request was sent with.
+    pub host: Option<String>,
+    /// The scheme the request was received with, e.g. `https`.
+    pub proto: Option<String>,
+}
+
+impl ForwardedElement {
+    /// Parses every element of a `Forwarded` value. Elements are separated
+    /// by commas and their parameters by semicolons, either of which may
+    /// appear inside a quoted value. Unknown parameters are skipped, and a
+    /// malformed element yields no parameters rather than failing the rest.
+    pub(crate) fn parse_all(value: &str) -> Vec<ForwardedElement> {
+        split_unquoted(value, ',')
+            .into_iter()
+            .filter(|element| !element.trim().is_empty())
+            .map(ForwardedElement::parse)
+            .collect()
+    }
+
+    fn parse(element: &str) -> ForwardedElement {
+        let mut parsed = ForwardedElement::default();
+        for pair in split_unquoted(element, ';') {
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+            let value = unquote(value.trim());
+            match name.trim().to_ascii_lowercase().as_str() {
+                "for" => parsed.for_ = Some(value),
+                "by" => parsed.by = Some(value),
+                "host" => parsed.host = Some(value),
+                "proto" => parsed.proto = Some(value),
+                _ => {}
+            }
+        }
+        parsed
+    }
+}
+
+/// Splits `value` on `separator` wherever it isn't inside a quoted string.
+fn split_unquoted(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Removes the quotes and escapes of a quoted string; anything else is
+/// returned as is.
+fn unquote(value: &str) -> String {
+    let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    else {
+        return value.to_string();
+    };
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// The address of a node as given in `Forwarded` or `X-Forwarded-For`, with
+/// any port and IPv6 brackets removed. Obfuscated and `unknown` nodes have
+/// none.
+pub(crate) fn node_ip(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.rsplit_once(':')?.0.parse().ok()
+}
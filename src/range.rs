@@ -0,0 +1,255 @@
+use std::{
+    collections::hash_map::RandomState,
+    fs::File,
+    hash::{BuildHasher, Hasher},
+    io::{self, Read, Seek, SeekFrom},
+};
+
+/// Past this many ranges the whole body is sent instead, so a header can't
+/// make the server build a response out of thousands of tiny parts.
+const MAX_RANGES: usize = 16;
+
+/// What a `Range` header asks for from a body of a given length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Range {
+    /// No usable `Range` header, so the whole body is sent.
+    Full,
+    /// Inclusive byte offsets, in order and neither overlapping nor
+    /// adjacent.
+    Partial(Vec<(u64, u64)>),
+    /// Every range starts past the end of the body, or asks for none of it.
+    Unsatisfiable,
+}
+
+impl Range {
+    /// Headers that aren't valid byte ranges are ignored rather than
+    /// rejected, as RFC 9110 asks, and so are ones with more than 16 ranges.
+    /// Ranges that overlap or touch are merged, so no byte is sent twice.
+    pub(crate) fn parse(header: Option<&str>, len: u64) -> Range {
+        let Some(specs) = header
+            .map(str::trim)
+            .and_then(|header| header.split_once('='))
+            .filter(|(unit, _)| unit.trim().eq_ignore_ascii_case("bytes"))
+            .map(|(_, specs)| specs)
+        else {
+            return Range::Full;
+        };
+
+        let mut ranges = Vec::new();
+        for (i, spec) in specs
+            .split(',')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .enumerate()
+        {
+            if i == MAX_RANGES {
+                return Range::Full;
+            }
+            let Some((start, end)) = spec.split_once('-') else {
+                return Range::Full;
+            };
+            // `-n` asks for the last n bytes, all of them if the body is
+            // shorter.
+            if start.trim().is_empty() {
+                let Ok(suffix) = end.trim().parse::<u64>() else {
+                    return Range::Full;
+                };
+                if suffix > 0 && len > 0 {
+                    ranges.push((len.saturating_sub(suffix), len - 1));
+                }
+                continue;
+            }
+            let Ok(start) = start.trim().parse::<u64>() else {
+                return Range::Full;
+            };
+            let end = match end.trim() {
+                "" => None,
+                end => match end.parse::<u64>() {
+                    Ok(end) if end >= start => Some(end),
+                    _ => return Range::Full,
+                },
+            };
+            if start < len {
+                ranges.push((start, end.map_or(len - 1, |end| end.min(len - 1))));
+            }
+        }
+        if ranges.is_empty() {
+            Range::Unsatisfiable
+        } else {
+            Range::Partial(merge(ranges))
+        }
+    }
+}
+
+/// Sorts `ranges` and merges those that overlap or touch.
+fn merge(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// A multipart boundary that can't be predicted, so a body can't contain it
+/// by design.
+pub(crate) fn boundary() -> String {
+    format!(
+        "rustgin-{:016x}",
+        RandomState::new().build_hasher().finish()
+    )
+}
+
+/// The body ranges are taken from.
+pub(crate) enum Body {
+    Data(Vec<u8>),
+    /// A file not read yet, so that only the ranges asked for ever are.
+    File(File, u64),
+}
+
+impl Body {
+    pub(crate) fn file(file: File) -> io::Result<Body> {
+        let len = file.metadata()?.len();
+        Ok(Body::File(file, len))
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        match self {
+            Body::Data(data) => data.len() as u64,
+            Body::File(_, len) => *len,
+        }
+    }
+
+    /// The bytes from `start` to `end`, inclusive.
+    pub(crate) fn read(&mut self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        match self {
+            Body::Data(data) => Ok(data[start as usize..=end as usize].to_vec()),
+            Body::File(file, _) => {
+                let mut part = vec![0; (end - start + 1) as usize];
+                file.seek(SeekFrom::Start(start))?;
+                file.read_exact(&mut part)?;
+                Ok(part)
+            }
+        }
+    }
+
+    pub(crate) fn into_data(self) -> io::Result<Vec<u8>> {
+        match self {
+            Body::Data(data) => Ok(data),
+            Body::File(mut file, len) => {
+                let mut data = Vec::with_capacity(len as usize);
+                file.read_to_end(&mut data)?;
+                Ok(data)
+            }
+        }
+    }
+}
+
+/// A `multipart/byteranges` body with one part for each range of `body`.
+pub(crate) fn multipart(
+    body: &mut Body,
+    ranges: &[(u64, u64)],
+    content_type: Option<&str>,
+    boundary: &str,
+) -> io::Result<Vec<u8>> {
+    let len = body.len();
+    let mut data = Vec::new();
+    for &(start, end) in ranges {
+        data.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        if let Some(content_type) = content_type {
+            data.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        data.extend_from_slice(
+            format!("Content-Range: bytes {}-{}/{}\r\n\r\n", start, end, len).as_bytes(),
+        );
+        data.extend_from_slice(&body.read(start, end)?);
+        data.extend_from_slice(b"\r\n");
+    }
+    data.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        process,
+    };
+
+    use super::{Body, Range};
+
+    fn parse(header: &str, len: u64) -> Range {
+        Range::parse(Some(header), len)
+    }
+
+    #[test]
+    fn single_ranges() {
+        assert_eq!(parse("bytes=0-4", 10), Range::Partial(vec![(0, 4)]));
+        assert_eq!(parse("bytes=5-", 10), Range::Partial(vec![(5, 9)]));
+        assert_eq!(parse("bytes=3-100", 10), Range::Partial(vec![(3, 9)]));
+        assert_eq!(parse("bytes=-3", 10), Range::Partial(vec![(7, 9)]));
+        assert_eq!(parse("bytes=-30", 10), Range::Partial(vec![(0, 9)]));
+    }
+
+    #[test]
+    fn unusable_headers_mean_the_full_body() {
+        assert_eq!(Range::parse(None, 10), Range::Full);
+        assert_eq!(parse("items=0-4", 10), Range::Full);
+        assert_eq!(parse("bytes=4-2", 10), Range::Full);
+        assert_eq!(parse("bytes=a-", 10), Range::Full);
+        assert_eq!(parse("bytes=5", 10), Range::Full);
+    }
+
+    #[test]
+    fn unsatisfiable() {
+        assert_eq!(parse("bytes=10-", 10), Range::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", 10), Range::Unsatisfiable);
+        assert_eq!(parse("bytes=-5", 0), Range::Unsatisfiable);
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_ranges_are_merged() {
+        assert_eq!(parse("bytes=0-4,2-6", 10), Range::Partial(vec![(0, 6)]));
+        assert_eq!(parse("bytes=5-9,0-4", 10), Range::Partial(vec![(0, 9)]));
+        assert_eq!(
+            parse("bytes=0-1,-2,0-1", 10),
+            Range::Partial(vec![(0, 1), (8, 9)])
+        );
+        assert_eq!(parse("bytes=0-0,0-0,0-0", 10), Range::Partial(vec![(0, 0)]));
+    }
+
+    #[test]
+    fn too_many_ranges_mean_the_full_body() {
+        let sixteen = (0..16)
+            .map(|i| format!("{}-{}", i * 2, i * 2))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parse(&format!("bytes={}", sixteen.join(",")), 100),
+            Range::Partial((0..16).map(|i| (i * 2, i * 2)).collect())
+        );
+        let seventeen = (0..17)
+            .map(|i| format!("{}-{}", i * 2, i * 2))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parse(&format!("bytes={}", seventeen.join(",")), 100),
+            Range::Full
+        );
+    }
+
+    #[test]
+    fn file_ranges_are_read_where_they_start() {
+        let path = std::env::temp_dir().join(format!("rustgin-range-{}", process::id()));
+        let data: Vec<u8> = (0..=255).collect();
+        fs::write(&path, &data).unwrap();
+        let mut body = Body::file(File::open(&path).unwrap()).unwrap();
+        assert_eq!(body.len(), 256);
+        assert_eq!(body.read(200, 202).unwrap(), [200, 201, 202]);
+        assert_eq!(body.read(0, 1).unwrap(), [0, 1]);
+        assert_eq!(body.read(255, 255).unwrap(), [255]);
+        drop(body);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,755 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+use crate::{HTTPHandler, HTTPRequest, Method};
+
+type Validator = Box<dyn Fn(&HTTPRequest) -> Result<(), Vec<FieldError>> + Send + Sync>;
+type BodySink = Box<dyn Fn(&HTTPRequest) -> Box<dyn Write> + Send + Sync>;
+
+/// A registered handler together with its per-route options. Returned by the
+/// registration methods on [`crate::HttpServer`] so options can be chained on.
+pub struct Route {
+    pub(crate) handler: HTTPHandler,
+    pub(crate) keep_alive: bool,
+    /// `None` for a mount, which answers every method.
+    method: Option<Method>,
+    /// The path as registered, e.g. `/posts/:id`.
+    template: String,
+    /// The prefix stripped from the path of requests to a mount.
+    pub(crate) mount: Option<String>,
+    pub(crate) validator: Option<Validator>,
+    pub(crate) body_sink: Option<BodySink>,
+    counters: RouteCounters,
+}
+
+/// Why one field of a request failed a route's validation, see
+/// [`Route::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> FieldError {
+        FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Traffic through one route, returned by [`crate::HttpServer::route_stats`].
+/// Bytes count the request and response heads as well as the bodies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteStats {
+    pub requests: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+}
+
+#[derive(Default)]
+struct RouteCounters {
+    requests: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+impl Route {
+    fn new(method: Option<Method>, template: &str, handler: HTTPHandler) -> Route {
+        Route {
+            handler,
+            keep_alive: true,
+            method,
+            template: template.to_string(),
+            mount: None,
+            validator: None,
+            body_sink: None,
+            counters: RouteCounters::default(),
+        }
+    }
+
+    /// Identifies the route in [`crate::HttpServer::route_stats`], e.g.
+    /// `GET /posts/:id`, or `* /admin/*` for a mount.
+    pub(crate) fn name(&self) -> String {
+        match self.method {
+            Some(method) => format!("{} {}", method, self.template),
+            None => format!("* {}/*", self.template),
+        }
+    }
+
+    pub(crate) fn record(&self, received: u64, sent: u64) {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes_received
+            .fetch_add(received, Ordering::Relaxed);
+        self.counters.bytes_sent.fetch_add(sent, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> RouteStats {
+        RouteStats {
+            requests: self.counters.requests.load(Ordering::Relaxed),
+            bytes_received: self.counters.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.counters.bytes_sent.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Always closes the connection after this route responds, regardless of
+    /// the server's keep-alive setting or what the client asked for.
+    pub fn no_keep_alive(&mut self) -> &mut Route {
+        self.keep_alive = false;
+        self
+    }
+
+    /// Runs `validator` on each request before the handler, with its body
+    /// and parameters available as usual. Any field errors are sent back as
+    /// 422 with a JSON body listing them, see
+    /// [`crate::HTTPResponse::field_errors`], and the handler isn't run.
+    pub fn validate(
+        &mut self,
+        validator: impl Fn(&HTTPRequest) -> Result<(), Vec<FieldError>> + Send + Sync + 'static,
+    ) -> &mut Route {
+        self.validator = Some(Box::new(validator));
+        self
+    }
+
+    /// Pipes the body of each request, as it arrives, into the writer `sink`
+    /// returns for it, then runs the handler, which finds out how that went
+    /// from [`crate::HTTPRequest::streamed_body`]. The body is never held in
+    /// memory, and the server's body limits still apply.
+    pub fn stream_body_to(
+        &mut self,
+        sink: impl Fn(&HTTPRequest) -> Box<dyn Write> + Send + Sync + 'static,
+    ) -> &mut Route {
+        self.body_sink = Some(Box::new(sink));
+        self
+    }
+}
+
+/// Captured path parameters, keyed by name.
+pub(crate) type Params = HashMap<String, String>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    Param(String),
+    /// Matches the rest of the path, one or more segments.
+    Wildcard(String),
+}
+
+/// A parsed route path. A path may end in one optional group, written
+/// `/posts(/page/:n)?`, which matches both with and without the group, and
+/// its last segment may be a wildcard, written `/files/*path`, capturing the
+/// rest of the path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    segments: Vec<Segment>,
+}
+
+impl Pattern {
+    /// Expands `path` into the patterns it matches: one, or two when it ends
+    /// in an optional group.
+    fn parse(path: &str) -> Vec<Pattern> {
+        let (required, optional) = match path.find('(') {
+            Some(start) => {
+                let group = path[start..]
+                    .strip_prefix('(')
+                    .and_then(|group| group.strip_suffix(")?"))
+                    .filter(|group| !group.contains(['(', ')']))
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "Invalid route {}: an optional group must be a single `(...)?` at the end",
+                            path
+                        )
+                    });
+                (&path[..start], Some(group))
+            }
+            None => (path, None),
+        };
+
+        let short = Pattern::from_segments(required);
+        match optional {
+            Some(group) => {
+                let long = Pattern::from_segments(&format!("{}{}", required, group));
+                vec![short, long]
+            }
+            None => vec![short],
+        }
+    }
+
+    fn from_segments(path: &str) -> Pattern {
+        let segments = path
+            .split('/')
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Static(segment.to_string())
+                }
+            })
+            .collect::<Vec<Segment>>();
+        let last = segments.len() - 1;
+        if segments[..last]
+            .iter()
+            .any(|segment| matches!(segment, Segment::Wildcard(_)))
+        {
+            panic!(
+                "Invalid route {}: a wildcard must be the last segment",
+                path
+            );
+        }
+        Pattern { segments }
+    }
+
+    fn is_static(&self) -> bool {
+        self.segments
+            .iter()
+            .all(|segment| matches!(segment, Segment::Static(_)))
+    }
+
+    fn to_path(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Static(part) => part.clone(),
+                Segment::Param(name) => format!(":{}", name),
+                Segment::Wildcard(name) => format!("*{}", name),
+            })
+            .collect::<Vec<String>>()
+            .join("/")
+    }
+}
+
+/// The routes with parameters, one level per path segment, so finding one
+/// takes time in proportion to the path rather than to the number of routes.
+#[derive(Default)]
+struct Node {
+    statics: HashMap<String, Node>,
+    param: Option<Box<Node>>,
+    /// The routes whose pattern ends here, with the names of the parameters
+    /// on the way.
+    routes: HashMap<Method, (Vec<String>, usize)>,
+    /// The routes whose pattern ends in a wildcard here, the wildcard's name
+    /// last.
+    wildcards: HashMap<Method, (Vec<String>, usize)>,
+}
+
+impl Node {
+    fn insert(&mut self, pattern: &Pattern, method: Method, index: usize) {
+        let mut node = self;
+        let mut names = Vec::new();
+        let mut wildcard = false;
+        for segment in &pattern.segments {
+            node = match segment {
+                Segment::Static(part) => node.statics.entry(part.clone()).or_default(),
+                Segment::Param(name) => {
+                    names.push(name.clone());
+                    node.param.get_or_insert_with(Box::default)
+                }
+                Segment::Wildcard(name) => {
+                    names.push(name.clone());
+                    wildcard = true;
+                    break;
+                }
+            };
+        }
+        let routes = if wildcard {
+            &mut node.wildcards
+        } else {
+            &mut node.routes
+        };
+        match routes.get_mut(&method) {
+            // Registering a pattern again replaces its route, while one only
+            // naming its parameters differently is shadowed by the first.
+            Some(existing) if existing.0 == names => existing.1 = index,
+            Some(_) => {}
+            None => {
+                routes.insert(method, (names, index));
+            }
+        }
+    }
+
+    /// At each segment a static match is tried before a parameter, and a
+    /// parameter before a wildcard, so where two patterns first differ the
+    /// most specific one wins.
+    fn find<'p>(
+        &self,
+        method: Method,
+        parts: &[&'p str],
+        captured: &mut Vec<&'p str>,
+    ) -> Option<(usize, Params)> {
+        let Some((part, rest)) = parts.split_first() else {
+            return self.routes.get(&method).map(|(names, index)| {
+                let params = names
+                    .iter()
+                    .cloned()
+                    .zip(captured.iter().map(|part| part.to_string()))
+                    .collect();
+                (*index, params)
+            });
+        };
+        if let Some(found) = self
+            .statics
+            .get(*part)
+            .and_then(|child| child.find(method, rest, captured))
+        {
+            return Some(found);
+        }
+        if let Some(child) = &self.param {
+            captured.push(part);
+            let found = child.find(method, rest, captured);
+            captured.pop();
+            if found.is_some() {
+                return found;
+            }
+        }
+        self.wildcards.get(&method).map(|(names, index)| {
+            let params = names
+                .iter()
+                .cloned()
+                .zip(captured.iter().map(|part| part.to_string()))
+                .chain(names.last().cloned().map(|name| (name, parts.join("/"))))
+                .collect();
+            (*index, params)
+        })
+    }
+
+    fn methods(&self, parts: &[&str], methods: &mut Vec<Method>) {
+        let Some((part, rest)) = parts.split_first() else {
+            methods.extend(self.routes.keys());
+            return;
+        };
+        if let Some(child) = self.statics.get(*part) {
+            child.methods(rest, methods);
+        }
+        if let Some(child) = &self.param {
+            child.methods(rest, methods);
+        }
+        methods.extend(self.wildcards.keys());
+    }
+}
+
+/// Exact paths are looked up directly; paths with parameters are found in a
+/// trie. Regex routes are only tried when neither matched, in the order they were
+/// registered, and mounts only after that, the longest prefix first.
+#[derive(Default)]
+pub(crate) struct Router {
+    routes: Vec<Route>,
+    exact: HashMap<String, HashMap<Method, usize>>,
+    patterns: Node,
+    #[cfg(feature = "regex")]
+    regexes: Vec<(Regex, Method, usize)>,
+    /// Kept longest prefix first.
+    mounts: Vec<(String, usize)>,
+}
+
+/// The named groups `regex` captured in `path`.
+#[cfg(feature = "regex")]
+fn captures(regex: &Regex, path: &str) -> Option<Params> {
+    let captures = regex.captures(path)?;
+    Some(
+        regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().to_string())))
+            .collect(),
+    )
+}
+
+impl Router {
+    pub(crate) fn insert(
+        &mut self,
+        method: Method,
+        path: &str,
+        handler: HTTPHandler,
+    ) -> &mut Route {
+        let index = self.routes.len();
+        self.routes.push(Route::new(Some(method), path, handler));
+        for pattern in Pattern::parse(path) {
+            if pattern.is_static() {
+                self.exact
+                    .entry(pattern.to_path())
+                    .or_default()
+                    .insert(method, index);
+                continue;
+            }
+            self.patterns.insert(&pattern, method, index);
+        }
+        &mut self.routes[index]
+    }
+
+    /// Panics if `pattern` isn't a valid regex, like an invalid route path.
+    #[cfg(feature = "regex")]
+    pub(crate) fn insert_regex(
+        &mut self,
+        method: Method,
+        pattern: &str,
+        handler: HTTPHandler,
+    ) -> &mut Route {
+        let regex = Regex::new(pattern)
+            .unwrap_or_else(|err| panic!("Invalid route regex {}: {}", pattern, err));
+        let index = self.routes.len();
+        self.routes.push(Route::new(Some(method), pattern, handler));
+        self.regexes.push((regex, method, index));
+        &mut self.routes[index]
+    }
+
+    /// Routes `prefix` and every path below it, for any method. A trailing
+    /// `/` is ignored, so `/` mounts the handler everywhere.
+    pub(crate) fn insert_mount(&mut self, prefix: &str, handler: HTTPHandler) -> &mut Route {
+        let prefix = prefix.trim_end_matches('/');
+        let index = self.routes.len();
+        let mut route = Route::new(None, prefix, handler);
+        route.mount = Some(prefix.to_string());
+        self.routes.push(route);
+        match self
+            .mounts
+            .iter_mut()
+            .find(|(existing, _)| existing == prefix)
+        {
+            Some(entry) => entry.1 = index,
+            None => {
+                self.mounts.push((prefix.to_string(), index));
+                self.mounts
+                    .sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+            }
+        }
+        &mut self.routes[index]
+    }
+
+    pub(crate) fn find(&self, method: Method, path: &str) -> Option<(&Route, Params)> {
+        if let Some(&index) = self.exact.get(path).and_then(|routes| routes.get(&method)) {
+            return Some((&self.routes[index], Params::new()));
+        }
+        let parts = path.split('/').collect::<Vec<&str>>();
+        let found = self
+            .patterns
+            .find(method, &parts, &mut Vec::new())
+            .map(|(index, params)| (&self.routes[index], params));
+        #[cfg(feature = "regex")]
+        let found = found.or_else(|| {
+            self.regexes
+                .iter()
+                .filter(|(_, route_method, _)| *route_method == method)
+                .find_map(|(regex, _, index)| {
+                    captures(regex, path).map(|params| (&self.routes[*index], params))
+                })
+        });
+        found.or_else(|| {
+            self.mounts
+                .iter()
+                .find(|(prefix, _)| {
+                    path.strip_prefix(prefix.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+                })
+                .map(|(_, index)| (&self.routes[*index], Params::new()))
+        })
+    }
+
+    pub(crate) fn routes(&self) -> impl Iterator<Item = &Route> {
+        self.routes.iter()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Every method with a route matching `path`.
+    pub(crate) fn methods_for(&self, path: &str) -> Vec<Method> {
+        let parts = path.split('/').collect::<Vec<&str>>();
+        let mut found = self
+            .exact
+            .get(path)
+            .map_or_else(Vec::new, |routes| routes.keys().copied().collect());
+        self.patterns.methods(&parts, &mut found);
+        #[cfg(feature = "regex")]
+        found.extend(
+            self.regexes
+                .iter()
+                .filter(|(regex, _, _)| regex.is_match(path))
+                .map(|(_, method, _)| *method),
+        );
+        let mut methods = Vec::new();
+        for method in found {
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        }
+        methods
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, time::Instant};
+
+    use super::*;
+
+    fn handler(_: HTTPRequest) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// The matcher the trie replaced: every pattern is tried in turn, and of
+    /// those matching, the most specific wins, the first registered on a tie.
+    #[derive(Default)]
+    struct Linear {
+        patterns: Vec<(Pattern, Method, usize)>,
+    }
+
+    impl Linear {
+        fn insert(&mut self, method: Method, path: &str, index: usize) {
+            for pattern in Pattern::parse(path) {
+                match self
+                    .patterns
+                    .iter_mut()
+                    .find(|(existing, existing_method, _)| {
+                        *existing == pattern && *existing_method == method
+                    }) {
+                    Some(entry) => entry.2 = index,
+                    None => self.patterns.push((pattern, method, index)),
+                }
+            }
+        }
+
+        fn find(&self, method: Method, path: &str) -> Option<(usize, Params)> {
+            let parts = path.split('/').collect::<Vec<&str>>();
+            self.patterns
+                .iter()
+                .filter(|(_, route_method, _)| *route_method == method)
+                .filter_map(|(pattern, _, index)| {
+                    matches(pattern, &parts).map(|(rank, params)| (rank, *index, params))
+                })
+                .min_by(|a, b| a.0.cmp(&b.0))
+                .map(|(_, index, params)| (index, params))
+        }
+
+        fn methods_for(&self, path: &str) -> Vec<String> {
+            let parts = path.split('/').collect::<Vec<&str>>();
+            let mut methods = self
+                .patterns
+                .iter()
+                .filter(|(pattern, _, _)| matches(pattern, &parts).is_some())
+                .map(|(_, method, _)| method.to_string())
+                .collect::<Vec<String>>();
+            methods.sort();
+            methods.dedup();
+            methods
+        }
+    }
+
+    /// The parameters `pattern` captures from `parts`, and how specific the
+    /// match is: lower is more specific.
+    fn matches(pattern: &Pattern, parts: &[&str]) -> Option<(Vec<u8>, Params)> {
+        let mut rank = Vec::new();
+        let mut params = Params::new();
+        for (i, segment) in pattern.segments.iter().enumerate() {
+            match segment {
+                Segment::Static(expected) => {
+                    if parts.get(i) != Some(&expected.as_str()) {
+                        return None;
+                    }
+                    rank.push(0);
+                }
+                Segment::Param(name) => {
+                    params.insert(name.clone(), parts.get(i)?.to_string());
+                    rank.push(1);
+                }
+                Segment::Wildcard(name) => {
+                    if i >= parts.len() {
+                        return None;
+                    }
+                    params.insert(name.clone(), parts[i..].join("/"));
+                    rank.push(2);
+                    return Some((rank, params));
+                }
+            }
+        }
+        (parts.len() == pattern.segments.len()).then_some((rank, params))
+    }
+
+    /// A fixed sequence of pseudo-random numbers, so fixtures are the same
+    /// on every run.
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    fn route_of(router: &Router, route: &Route) -> usize {
+        router
+            .routes()
+            .position(|candidate| std::ptr::eq(candidate, route))
+            .unwrap()
+    }
+
+    fn methods_of(router: &Router, path: &str) -> Vec<String> {
+        let mut methods = router
+            .methods_for(path)
+            .iter()
+            .map(Method::to_string)
+            .collect::<Vec<String>>();
+        methods.sort();
+        methods
+    }
+
+    #[test]
+    fn trie_matches_like_the_linear_matcher() {
+        let mut random = Xorshift(0x2545_f491_4f6c_dd1d);
+        let segments = ["a", "b", "c", ":p", ":q", "*w"];
+        let methods = [Method::GET, Method::POST];
+        let mut router = Router::default();
+        let mut linear = Linear::default();
+        for index in 0..400 {
+            let depth = 1 + random.below(4);
+            let mut path = String::new();
+            for i in 0..depth {
+                let segment = random.pick(&segments);
+                // Wildcards can only come last.
+                if segment.starts_with('*') && i + 1 < depth {
+                    path.push_str("/c");
+                } else {
+                    path.push('/');
+                    path.push_str(segment);
+                }
+            }
+            if !path.contains('*') && random.below(5) == 0 {
+                path.push_str("(/b/:o)?");
+            }
+            let method = methods[random.below(methods.len())];
+            router.insert(method, &path, handler);
+            linear.insert(method, &path, index);
+        }
+
+        let parts = ["a", "b", "c", "x", ""];
+        let mut matched = 0;
+        for _ in 0..5000 {
+            let path = (0..random.below(6))
+                .map(|_| random.pick(&parts))
+                .fold(String::new(), |path, part| path + "/" + part);
+            let path = if path.is_empty() {
+                "/".to_string()
+            } else {
+                path
+            };
+            for method in methods {
+                let found = router
+                    .find(method, &path)
+                    .map(|(route, params)| (route_of(&router, route), params));
+                matched += usize::from(found.is_some());
+                assert_eq!(found, linear.find(method, &path), "{} {}", method, path);
+            }
+            assert_eq!(
+                methods_of(&router, &path),
+                linear.methods_for(&path),
+                "{}",
+                path
+            );
+        }
+        // Most probes should hit something, or the comparison proves little.
+        assert!(matched > 5000, "only {} matches", matched);
+    }
+
+    #[test]
+    fn static_beats_param_beats_wildcard() {
+        let mut router = Router::default();
+        router.insert(Method::GET, "/files/*path", handler);
+        router.insert(Method::GET, "/files/:name", handler);
+        router.insert(Method::GET, "/files/readme", handler);
+        let find = |path| {
+            let (route, params) = router.find(Method::GET, path).unwrap();
+            (route_of(&router, route), params)
+        };
+        assert_eq!(find("/files/readme"), (2, Params::new()));
+        assert_eq!(
+            find("/files/notes"),
+            (1, Params::from([("name".to_string(), "notes".to_string())]))
+        );
+        assert_eq!(
+            find("/files/docs/a/b"),
+            (
+                0,
+                Params::from([("path".to_string(), "docs/a/b".to_string())])
+            )
+        );
+        assert!(router.find(Method::GET, "/files").is_none());
+    }
+
+    #[test]
+    fn a_wildcard_falls_back_from_a_dead_end() {
+        let mut router = Router::default();
+        router.insert(Method::GET, "/a/:id/edit", handler);
+        router.insert(Method::GET, "/a/*rest", handler);
+        let (route, params) = router.find(Method::GET, "/a/1/view").unwrap();
+        assert_eq!(route_of(&router, route), 1);
+        assert_eq!(params["rest"], "1/view");
+        assert_eq!(methods_of(&router, "/a/1/view"), ["GET"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "a wildcard must be the last segment")]
+    fn wildcard_must_be_last() {
+        Router::default().insert(Method::GET, "/a/*rest/b", handler);
+    }
+
+    /// Matching 1000 routes with the trie against the linear matcher. Run
+    /// with `cargo test --release -- --ignored --nocapture router_benchmark`.
+    #[test]
+    #[ignore]
+    fn router_benchmark() {
+        const ROUTES: usize = 1000;
+        const LOOKUPS: usize = 100_000;
+        let mut router = Router::default();
+        let mut linear = Linear::default();
+        let mut paths = Vec::new();
+        for index in 0..ROUTES {
+            let path = match index % 4 {
+                0 => format!("/api/v{}/items{}", index % 7, index),
+                1 => format!("/api/v{}/items{}/:id", index % 7, index),
+                2 => format!("/users/:user/repos{}/:repo", index),
+                _ => format!("/static{}/*path", index),
+            };
+            router.insert(Method::GET, &path, handler);
+            linear.insert(Method::GET, &path, index);
+            paths.push(
+                path.replace(":id", "42")
+                    .replace(":user", "alice")
+                    .replace(":repo", "gin")
+                    .replace("*path", "css/site.css"),
+            );
+        }
+
+        let started = Instant::now();
+        for i in 0..LOOKUPS {
+            assert!(router.find(Method::GET, &paths[i % ROUTES]).is_some());
+        }
+        let trie = started.elapsed();
+        let started = Instant::now();
+        for i in 0..LOOKUPS {
+            assert!(linear.find(Method::GET, &paths[i % ROUTES]).is_some());
+        }
+        let scan = started.elapsed();
+        println!(
+            "{} routes: trie {:?} per lookup, linear {:?} per lookup",
+            ROUTES,
+            trie / LOOKUPS as u32,
+            scan / LOOKUPS as u32
+        );
+    }
+}
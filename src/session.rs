@@ -0,0 +1,202 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cookie::{self, CookieKey},
+    Clock, Reply, SystemClock,
+};
+
+const COOKIE_NAME: &str = "rustgin_session";
+
+/// The values stored in one session.
+pub type SessionData = HashMap<String, String>;
+
+/// Configures [`crate::HttpServer::sessions`]. The session cookie is signed
+/// with `secret`, and sessions expire `ttl` after they were last changed.
+#[derive(Clone)]
+pub struct SessionConfig {
+    pub secret: Vec<u8>,
+    pub ttl: Duration,
+}
+
+/// Where session data is kept between requests. Implementations are shared
+/// by every connection thread.
+pub trait SessionStore: Send + Sync {
+    /// Returns the data for `id`, or `None` if it is unknown or has expired.
+    fn load(&self, id: &str) -> Option<SessionData>;
+    fn save(&self, id: &str, data: &SessionData, ttl: Duration);
+    fn remove(&self, id: &str);
+}
+
+/// The default store, holding every session in memory. Expired sessions are
+/// swept out periodically as new ones are saved.
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, (SessionData, Instant)>>,
+    saves: AtomicUsize,
+    clock: Arc<dyn Clock>,
+}
+
+impl MemoryStore {
+    /// A store that expires sessions by `clock` rather than the system clock.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> MemoryStore {
+        MemoryStore {
+            sessions: Mutex::new(HashMap::new()),
+            saves: AtomicUsize::new(0),
+            clock,
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> MemoryStore {
+        MemoryStore::with_clock(Arc::new(SystemClock))
+    }
+}
+
+const SWEEP_INTERVAL: usize = 256;
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> Option<SessionData> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(id) {
+            Some((_, expires)) if *expires <= self.clock.now() => {
+                sessions.remove(id);
+                None
+            }
+            Some((data, _)) => Some(data.clone()),
+            None => None,
+        }
+    }
+
+    fn save(&self, id: &str, data: &SessionData, ttl: Duration) {
+        let now = self.clock.now();
+        let mut sessions = self.sessions.lock().unwrap();
+        if self
+            .saves
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(SWEEP_INTERVAL)
+        {
+            sessions.retain(|_, (_, expires)| *expires > now);
+        }
+        sessions.insert(id.to_string(), (data.clone(), now + ttl));
+    }
+
+    fn remove(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
+
+/// The session of the request being handled, see [`crate::HTTPRequest::session`].
+/// Changes are saved and the cookie set once the handler returns.
+pub struct Session {
+    id: Option<String>,
+    data: RefCell<SessionData>,
+    changed: Cell<bool>,
+}
+
+impl Session {
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.data.borrow().get(key).cloned()
+    }
+
+    pub fn set(&self, key: &str, value: &str) {
+        self.data
+            .borrow_mut()
+            .insert(key.to_string(), value.to_string());
+        self.changed.set(true);
+    }
+
+    pub fn remove(&self, key: &str) -> Option<String> {
+        let removed = self.data.borrow_mut().remove(key);
+        if removed.is_some() {
+            self.changed.set(true);
+        }
+        removed
+    }
+}
+
+/// Session support enabled on a server. Returned by
+/// [`crate::HttpServer::sessions`] so options can be chained on.
+pub struct Sessions {
+    config: SessionConfig,
+    key: CookieKey,
+    store: Box<dyn SessionStore>,
+}
+
+impl Sessions {
+    pub(crate) fn new(config: SessionConfig, clock: Arc<dyn Clock>) -> Sessions {
+        Sessions {
+            key: CookieKey::new(&config.secret),
+            config,
+            store: Box::new(MemoryStore::with_clock(clock)),
+        }
+    }
+
+    /// Keeps sessions in `store` instead of in memory.
+    pub fn store(&mut self, store: impl SessionStore + 'static) -> &mut Sessions {
+        self.store = Box::new(store);
+        self
+    }
+
+    /// Loads the session named by the request's session cookie. A missing,
+    /// forged or expired cookie starts a new, empty session.
+    pub(crate) fn load(&self, cookie: Option<&str>) -> Session {
+        let stored = cookie
+            .and_then(|cookie| self.key.verify(COOKIE_NAME, cookie))
+            .and_then(|id| Some((id.to_string(), self.store.load(id)?)));
+        let (id, data) = match stored {
+            Some((id, data)) => (Some(id), data),
+            None => (None, SessionData::new()),
+        };
+        Session {
+            id,
+            data: RefCell::new(data),
+            changed: Cell::new(false),
+        }
+    }
+
+    /// Stores a changed session and sets its cookie on `reply`. A session
+    /// left empty is removed and its cookie cleared.
+    pub(crate) fn save(&self, session: &Session, reply: &mut Reply) {
+        if !session.changed.get() {
+            return;
+        }
+        let Reply::Response(response) = reply else {
+            log::warn!("Session changes can't be saved with a frozen response");
+            return;
+        };
+        let data = session.data.borrow();
+        if data.is_empty() {
+            if let Some(id) = &session.id {
+                self.store.remove(id);
+                response.cookies.push(format!(
+                    "{}=; Path=/; Max-Age=0; HttpOnly; SameSite=Lax",
+                    COOKIE_NAME
+                ));
+            }
+            return;
+        }
+        let id = session
+            .id
+            .clone()
+            .unwrap_or_else(|| cookie::hex(&cookie::random_bytes(16)));
+        self.store.save(&id, &data, self.config.ttl);
+        response.cookies.push(format!(
+            "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+            COOKIE_NAME,
+            self.key.sign(COOKIE_NAME, &id),
+            self.config.ttl.as_secs()
+        ));
+    }
+
+    pub(crate) fn cookie_name(&self) -> &'static str {
+        COOKIE_NAME
+    }
+}
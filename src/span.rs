@@ -0,0 +1,116 @@
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::Clock;
+
+/// Timings and other values recorded while a request is handled, see
+/// [`crate::HTTPRequest::span`]. They are logged at info level together with
+/// the request once its response has been written:
+///
+/// `GET /posts/1 200 14ms db_ms=12 cache=miss`
+///
+/// With the `tracing` feature the request also runs inside a `request` span,
+/// and every recorded value is emitted as an event in it.
+pub struct RequestSpan {
+    method: String,
+    target: String,
+    clock: Arc<dyn Clock>,
+    start: Instant,
+    fields: RefCell<Vec<(String, String)>>,
+    /// Metric names and durations in milliseconds for `Server-Timing`.
+    timings: RefCell<Vec<(String, f64)>>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl RequestSpan {
+    pub(crate) fn new(method: &str, target: &str, clock: Arc<dyn Clock>) -> RequestSpan {
+        RequestSpan {
+            method: method.to_string(),
+            target: target.to_string(),
+            start: clock.now(),
+            clock,
+            fields: RefCell::new(Vec::new()),
+            timings: RefCell::new(Vec::new()),
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!("request", method, target),
+        }
+    }
+
+    /// Records `value` under `name`. Recording the same name again replaces
+    /// the earlier value.
+    pub fn record(&self, name: &str, value: impl Display) {
+        let value = value.to_string();
+        #[cfg(feature = "tracing")]
+        tracing::info!(parent: &self.span, field = name, value = %value);
+        let mut fields = self.fields.borrow_mut();
+        match fields.iter_mut().find(|(existing, _)| existing == name) {
+            Some(field) => field.1 = value,
+            None => fields.push((name.to_string(), value)),
+        }
+    }
+
+    /// Adds a `Server-Timing` metric taking `millis`, see
+    /// [`crate::HTTPRequest::server_timing`].
+    pub(crate) fn server_timing(&self, name: &str, millis: f64) {
+        self.timings.borrow_mut().push((name.to_string(), millis));
+    }
+
+    /// The value of the `Server-Timing` header, if anything was timed.
+    pub(crate) fn server_timing_header(&self) -> Option<String> {
+        let timings = self.timings.borrow();
+        if timings.is_empty() {
+            return None;
+        }
+        Some(
+            timings
+                .iter()
+                .map(|(name, millis)| format!("{};dur={}", name, millis))
+                .collect::<Vec<String>>()
+                .join(", "),
+        )
+    }
+
+    /// Makes the request's tracing span the current one until the guard is
+    /// dropped.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn enter(&self) -> tracing::span::Entered<'_> {
+        self.span.enter()
+    }
+
+    /// Logs the completed request with everything recorded for it, and warns
+    /// about it as well when it took longer than `slow`.
+    pub(crate) fn finish(&self, status: u16, slow: Option<Duration>) {
+        let duration = self.clock.now().saturating_duration_since(self.start);
+        if slow.is_some_and(|slow| duration > slow) {
+            let path = self.target.split('?').next().unwrap_or_default();
+            log::warn!(
+                "Slow request: {} {} took {}ms",
+                self.method,
+                path,
+                duration.as_millis()
+            );
+        }
+        let elapsed = duration.as_millis();
+        let fields = self
+            .fields
+            .borrow()
+            .iter()
+            .map(|(name, value)| format!(" {}={}", name, value))
+            .collect::<String>();
+        #[cfg(feature = "tracing")]
+        tracing::info!(parent: &self.span, status, elapsed_ms = elapsed as u64, "{}", fields.trim_start());
+        log::info!(
+            "{} {} {} {}ms{}",
+            self.method,
+            self.target,
+            status,
+            elapsed,
+            fields
+        );
+    }
+}
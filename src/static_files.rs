@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+#[cfg(feature = "compression")]
+use std::{io::Write, thread, time::SystemTime};
+
+use crate::{conditional::FileValidators, HTTPResponse, Status};
+
+/// Serves the files under a directory at a URL prefix. Returned by
+/// [`crate::HttpServer::static_dir`] so options can be chained on.
+pub struct StaticDir {
+    prefix: String,
+    root: PathBuf,
+    #[cfg(feature = "compression")]
+    compress_cache: Option<PathBuf>,
+}
+
+impl StaticDir {
+    pub(crate) fn new(prefix: String, root: PathBuf) -> StaticDir {
+        StaticDir {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            root,
+            #[cfg(feature = "compression")]
+            compress_cache: None,
+        }
+    }
+
+    /// Compresses text assets on first request and keeps the result under
+    /// `dir`. A cached copy is reused until the source file's modification
+    /// time changes.
+    #[cfg(feature = "compression")]
+    pub fn compress_cache(&mut self, dir: impl Into<PathBuf>) -> &mut StaticDir {
+        self.compress_cache = Some(dir.into());
+        self
+    }
+
+    /// Returns `None` when `target` is outside this directory's prefix or
+    /// doesn't name a file under it. Answers with 304 when the lowercased
+    /// request `headers` show the client's copy is current. The body is left
+    /// in the file returned with the response when there is one, for the
+    /// server to read only the ranges the request asks for.
+    pub(crate) fn serve(
+        &self,
+        target: &str,
+        headers: &HashMap<String, String>,
+    ) -> Option<io::Result<(HTTPResponse, Option<File>)>> {
+        let path = target.split(['?', '#']).next().unwrap_or_default();
+        let relative = path.strip_prefix(&self.prefix)?;
+        if !relative.is_empty() && !relative.starts_with('/') {
+            return None;
+        }
+        let relative = resolve(relative)?;
+
+        let mut file = self.root.join(&relative);
+        if file.is_dir() {
+            file.push("index.html");
+        }
+        if !file.is_file() {
+            return None;
+        }
+        let validators = match fs::metadata(&file) {
+            Ok(metadata) => FileValidators::of(&metadata),
+            Err(e) => return Some(Err(e)),
+        };
+        if validators.is_current(headers) {
+            return Some(Ok((validators.not_modified(), None)));
+        }
+        let accept_encoding = headers.get("accept-encoding").map(String::as_str);
+        Some(
+            self.respond(&file, &relative, accept_encoding)
+                .map(|(mut response, body)| {
+                    validators.apply(&mut response);
+                    (response, body)
+                }),
+        )
+    }
+
+    #[cfg_attr(not(feature = "compression"), allow(unused_variables))]
+    fn respond(
+        &self,
+        file: &Path,
+        relative: &Path,
+        accept_encoding: Option<&str>,
+    ) -> io::Result<(HTTPResponse, Option<File>)> {
+        let content_type = content_type(file);
+
+        #[cfg(feature = "compression")]
+        if let (Some(cache), Some(encoding)) = (&self.compress_cache, accept_encoding) {
+            if is_compressible(content_type) {
+                if let Some(encoding) = Encoding::negotiate(encoding) {
+                    let data = cached_compressed(file, &cache.join(relative), encoding)?;
+                    let mut response = HTTPResponse::from_bytes(Status::Ok, data);
+                    response.add_header("Content-Type".to_string(), content_type.to_string());
+                    response
+                        .add_header("Content-Encoding".to_string(), encoding.name().to_string());
+                    response.add_header("Vary".to_string(), "Accept-Encoding".to_string());
+                    return Ok((response, None));
+                }
+            }
+        }
+
+        let body = File::open(file)?;
+        let mut response = HTTPResponse::from_bytes(Status::Ok, Vec::new());
+        response.add_header("Content-Type".to_string(), content_type.to_string());
+        Ok((response, Some(body)))
+    }
+}
+
+/// Turns the URL path below the prefix into a relative file path, refusing
+/// anything that could escape the directory.
+fn resolve(relative: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(relative.trim_start_matches('/')).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+pub(crate) fn content_type(file: &Path) -> &'static str {
+    let extension = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "application/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(feature = "compression")]
+fn is_compressible(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || content_type.starts_with("application/javascript")
+        || content_type.starts_with("application/json")
+        || content_type.starts_with("application/xml")
+        || content_type.starts_with("application/wasm")
+        || content_type.starts_with("image/svg+xml")
+}
+
+#[cfg(feature = "compression")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+#[cfg(feature = "compression")]
+impl Encoding {
+    /// Picks the preferred encoding the client accepts, ignoring any it has
+    /// explicitly refused with `q=0`.
+    fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let accepted = accept_encoding
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                let name = parts.next()?.to_lowercase();
+                let refused = parts.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q == 0.0)
+                });
+                (!refused).then_some(name)
+            })
+            .collect::<Vec<String>>();
+        [Encoding::Brotli, Encoding::Gzip]
+            .into_iter()
+            .find(|encoding| accepted.iter().any(|name| name == encoding.name()))
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => ".br",
+            Encoding::Gzip => ".gz",
+        }
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Brotli => {
+                let mut compressed = Vec::new();
+                let mut writer = brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+                writer.write_all(data)?;
+                drop(writer);
+                Ok(compressed)
+            }
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// Returns the compressed contents of `source`, reusing the copy cached next
+/// to `cache_base` when it was made from the current version of the file.
+/// Cached copies carry the source's modification time so a changed source
+/// is noticed without keeping an index.
+#[cfg(feature = "compression")]
+fn cached_compressed(source: &Path, cache_base: &Path, encoding: Encoding) -> io::Result<Vec<u8>> {
+    let mut cached = cache_base.as_os_str().to_owned();
+    cached.push(encoding.extension());
+    let cached = PathBuf::from(cached);
+
+    let modified = fs::metadata(source)?.modified()?;
+    if let Ok(metadata) = fs::metadata(&cached) {
+        if metadata.modified().ok() == Some(modified) {
+            return fs::read(&cached);
+        }
+    }
+
+    let compressed = encoding.compress(&fs::read(source)?)?;
+    if let Err(e) = write_cache(&cached, &compressed, modified) {
+        log::warn!("Failed to cache {}: {}", cached.display(), e);
+    }
+    Ok(compressed)
+}
+
+#[cfg(feature = "compression")]
+fn write_cache(cached: &Path, data: &[u8], modified: SystemTime) -> io::Result<()> {
+    if let Some(parent) = cached.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Written aside and renamed so concurrent requests never read a partial file.
+    let mut temporary = cached.as_os_str().to_owned();
+    temporary.push(format!(".{:?}.tmp", thread::current().id()));
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(data)?;
+    file.set_modified(modified)?;
+    drop(file);
+    fs::rename(&temporary, cached)
+}
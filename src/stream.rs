@@ -0,0 +1,230 @@
+use std::{
+    io::{self, Write},
+    sync::{mpsc, Arc, Mutex},
+};
+
+/// Parts are sent on to the server once this much has been written.
+const STREAM_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Parts a [`ResponseStream`] can have waiting to be written before writing
+/// to it blocks, so a producer can't get far ahead of a slow client.
+const STREAM_QUEUE: usize = 4;
+
+/// A body sent as its parts are received. Shared by clones of the response;
+/// whichever is written first takes it.
+#[derive(Clone)]
+pub(crate) struct BodyStream(Arc<Mutex<Option<Parts>>>);
+
+pub(crate) struct Parts {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    /// Tells a [`ResponseStream`] how sending the body went.
+    done: Option<mpsc::Sender<io::Result<()>>>,
+}
+
+impl BodyStream {
+    pub(crate) fn new(receiver: mpsc::Receiver<Vec<u8>>) -> BodyStream {
+        BodyStream::with_done(receiver, None)
+    }
+
+    fn with_done(
+        receiver: mpsc::Receiver<Vec<u8>>,
+        done: Option<mpsc::Sender<io::Result<()>>>,
+    ) -> BodyStream {
+        BodyStream(Arc::new(Mutex::new(Some(Parts { receiver, done }))))
+    }
+
+    /// Reports the body as sent without writing it, for HEAD requests.
+    pub(crate) fn skip(&self) {
+        if let Some(parts) = self.0.lock().unwrap().take() {
+            parts.report(Ok(()));
+        }
+    }
+
+    /// Writes every part received, as a chunk followed by the last chunk
+    /// carrying `trailers` when `chunked`, and returns the number of bytes
+    /// written.
+    pub(crate) fn write(
+        &self,
+        writer: &mut impl Write,
+        chunked: bool,
+        trailers: &[(String, String)],
+    ) -> io::Result<usize> {
+        let Some(parts) = self.0.lock().unwrap().take() else {
+            if !chunked {
+                return Ok(0);
+            }
+            let last = last_chunk(trailers);
+            writer.write_all(&last)?;
+            return Ok(last.len());
+        };
+        let result = parts.write(writer, chunked, trailers);
+        match &result {
+            Ok(_) => parts.report(Ok(())),
+            Err(err) => parts.report(Err(io::Error::new(err.kind(), err.to_string()))),
+        }
+        result
+    }
+}
+
+impl Parts {
+    fn write(
+        &self,
+        writer: &mut impl Write,
+        chunked: bool,
+        trailers: &[(String, String)],
+    ) -> io::Result<usize> {
+        let mut written = 0;
+        for data in self.receiver.iter() {
+            // An empty chunk would end the body early.
+            if data.is_empty() {
+                continue;
+            }
+            if !chunked {
+                writer.write_all(&data)?;
+                written += data.len();
+                continue;
+            }
+            let mut chunk = format!("{:x}\r\n", data.len()).into_bytes();
+            chunk.extend_from_slice(&data);
+            chunk.extend_from_slice(b"\r\n");
+            writer.write_all(&chunk)?;
+            written += chunk.len();
+        }
+        if chunked {
+            let last = last_chunk(trailers);
+            writer.write_all(&last)?;
+            written += last.len();
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    fn report(self, result: io::Result<()>) {
+        if let Some(done) = self.done {
+            let _ = done.send(result);
+        }
+    }
+}
+
+/// The chunk ending a chunked body, followed by `trailers`.
+pub(crate) fn last_chunk(trailers: &[(String, String)]) -> Vec<u8> {
+    let mut last = b"0\r\n".to_vec();
+    for (name, value) in trailers {
+        last.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    }
+    last.extend_from_slice(b"\r\n");
+    last
+}
+
+/// Writes the body of a response created with
+/// [`crate::HTTPResponse::streaming`], usually from another thread once the
+/// handler has returned. Writes are buffered; [`ResponseStream::flush`] sends
+/// what has been written so far to the client. Sending fails with
+/// `BrokenPipe` once the body is no longer wanted: the client went away, or
+/// the request was HEAD.
+///
+/// Only a few parts are held for the server at a time, and writing blocks
+/// until the client has taken earlier ones, so write from a thread of its
+/// own rather than from the handler before it returns.
+pub struct ResponseStream {
+    sender: Option<mpsc::SyncSender<Vec<u8>>>,
+    buffer: Vec<u8>,
+    done: mpsc::Receiver<io::Result<()>>,
+}
+
+impl ResponseStream {
+    pub(crate) fn new() -> (ResponseStream, BodyStream) {
+        let (sender, receiver) = mpsc::sync_channel(STREAM_QUEUE);
+        let (done_sender, done) = mpsc::channel();
+        let stream = ResponseStream {
+            sender: Some(sender),
+            buffer: Vec::new(),
+            done,
+        };
+        (stream, BodyStream::with_done(receiver, Some(done_sender)))
+    }
+
+    /// Sends what is left in the buffer and ends the body, then waits until
+    /// the server has written the last chunk. Fails if the client went away
+    /// or the response was never sent.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()?;
+        self.sender = None;
+        match self.done.recv() {
+            Ok(result) => result,
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the streamed response was never sent",
+            )),
+        }
+    }
+}
+
+impl Write for ResponseStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= STREAM_BUFFER_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let sender = self.sender.as_ref().ok_or(io::ErrorKind::BrokenPipe)?;
+        sender
+            .send(std::mem::take(&mut self.buffer))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+/// Dropping the stream without [`ResponseStream::finish`] still sends what
+/// was buffered and ends the body.
+impl Drop for ResponseStream {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Writes the items of a JSON array streamed with
+/// [`crate::HTTPResponse::json_array_stream`], adding the commas between
+/// them.
+pub struct JsonArrayWriter {
+    stream: ResponseStream,
+    empty: bool,
+}
+
+impl JsonArrayWriter {
+    /// Appends `item`, which must already be serialized JSON. Fails with
+    /// `BrokenPipe` once the body is no longer wanted.
+    pub fn write(&mut self, item: &str) -> io::Result<()> {
+        if !self.empty {
+            self.stream.write_all(b",")?;
+        }
+        self.empty = false;
+        self.stream.write_all(item.as_bytes())
+    }
+
+    /// Sends the items written so far to the client.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+
+    /// Runs `produce` between the opening and closing brackets. If it fails
+    /// the array is left open, so the client can tell the body is incomplete.
+    pub(crate) fn run(
+        stream: ResponseStream,
+        produce: impl FnOnce(&mut JsonArrayWriter) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut writer = JsonArrayWriter {
+            stream,
+            empty: true,
+        };
+        writer.stream.write_all(b"[")?;
+        produce(&mut writer)?;
+        writer.stream.write_all(b"]")?;
+        writer.stream.finish()
+    }
+}
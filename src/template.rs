@@ -0,0 +1,50 @@
+/// Escapes the characters that are special in HTML text and attribute
+/// values.
+pub fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Replaces each `{key}` in `template` with its value, HTML-escaped, and each
+/// `{!key}` with its value as is. Braces around anything that isn't one of
+/// `values` are left alone.
+pub(crate) fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let substituted = rest.find('}').and_then(|end| {
+            let (key, raw) = match rest[..end].strip_prefix('!') {
+                Some(key) => (key, true),
+                None => (&rest[..end], false),
+            };
+            let (_, value) = values.iter().find(|(name, _)| *name == key)?;
+            let value = if raw {
+                value.to_string()
+            } else {
+                html_escape(value)
+            };
+            Some((end, value))
+        });
+        match substituted {
+            Some((end, value)) => {
+                rendered.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => rendered.push('{'),
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
@@ -0,0 +1,73 @@
+use crate::{router::Router, HTTPHandler, Method, Route};
+
+/// Routes that only answer requests for one host. Returned by
+/// [`crate::HttpServer::virtual_host`]; requests for other hosts use the
+/// server's own routes, or get 421 if it has none.
+pub struct VirtualHost {
+    /// Lowercased, without the port.
+    host: String,
+    /// Only requests for this port match. Without one, the port in the
+    /// `Host` header is ignored.
+    port: Option<u16>,
+    pub(crate) router: Router,
+}
+
+impl VirtualHost {
+    pub(crate) fn new(name: &str) -> VirtualHost {
+        let (host, port) = split_host(name);
+        VirtualHost {
+            host: host.to_lowercase(),
+            port,
+            router: Router::default(),
+        }
+    }
+
+    /// The host as registered, e.g. `example.com` or `example.com:8080`.
+    pub(crate) fn name(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{}", self.host, port),
+            None => self.host.clone(),
+        }
+    }
+
+    /// A `Host` header without a port is taken to be for port 80.
+    pub(crate) fn matches(&self, host: &str, port: Option<u16>) -> bool {
+        self.host.eq_ignore_ascii_case(host)
+            && self
+                .port
+                .is_none_or(|expected| expected == port.unwrap_or(80))
+    }
+
+    pub fn get(&mut self, url: String, func: HTTPHandler) -> &mut Route {
+        self.add_method(Method::GET, url, func)
+    }
+
+    pub fn post(&mut self, url: String, func: HTTPHandler) -> &mut Route {
+        self.add_method(Method::POST, url, func)
+    }
+
+    pub fn add_method(&mut self, method: Method, url: String, func: HTTPHandler) -> &mut Route {
+        self.router.insert(method, &url, func)
+    }
+
+    /// Like [`crate::HttpServer::mount_prefix`], for this host.
+    pub fn mount_prefix(&mut self, prefix: &str, func: HTTPHandler) -> &mut Route {
+        self.router.insert_mount(prefix, func)
+    }
+}
+
+/// Splits a `Host` value into the host and its port, if it has a valid one.
+/// IPv6 addresses lose their brackets.
+pub(crate) fn split_host(value: &str) -> (&str, Option<u16>) {
+    let value = value.trim();
+    if let Some(rest) = value.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((host, port)) => (host, port.strip_prefix(':').and_then(|p| p.parse().ok())),
+            None => (value, None),
+        };
+    }
+    match value.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()),
+        None => (value, None),
+    }
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::error::Error;
+
+use common::{body, exchange, get, header, start, status};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, Status};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+fn server(api_mode: bool) -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.set_api_mode(api_mode);
+    server.get("/".to_string(), ok);
+    server
+}
+
+#[test]
+fn not_found_in_api_mode_is_a_json_envelope() {
+    let (addr, _) = start(server(true));
+    let response = get(addr, "/missing");
+    assert_eq!(status(&response), 404);
+    assert_eq!(header(&response, "content-type"), Some("application/json"));
+    assert!(
+        body(&response).starts_with(r#"{"error": {"code": 404, "message": "#),
+        "{}",
+        response
+    );
+}
+
+#[test]
+fn method_not_allowed_in_api_mode_is_a_json_envelope() {
+    let (addr, _) = start(server(true));
+    let response = exchange(
+        addr,
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(status(&response), 405);
+    assert!(body(&response).contains(r#""code": 405"#), "{}", response);
+}
+
+#[test]
+fn not_found_is_plain_text_otherwise() {
+    let (addr, _) = start(server(false));
+    let response = get(addr, "/missing");
+    assert_eq!(status(&response), 404);
+    assert!(!body(&response).starts_with('{'));
+}
+
+#[test]
+fn json_error_escapes_the_message() {
+    let response = HTTPResponse::json_error(Status::BadRequest, "bad \"name\"\n");
+    let bytes = String::from_utf8(response.to_bytes()).unwrap();
+    assert_eq!(
+        body(&bytes),
+        r#"{"error": {"code": 400, "message": "bad \"name\"\n"}}"#
+    );
+}
@@ -0,0 +1,315 @@
+mod common;
+
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use common::{body, connect, exchange, header, split_responses, start, status, wait_until};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn get_body(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text = request.get_body().to_string();
+    request.send(&text)
+}
+
+fn body_text(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text = request.body_text()?.to_string();
+    request.send(&text)
+}
+
+fn utf8_offset(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let offset = request.body_text().err().map(|e| e.offset);
+    request.send(&format!("{:?}", offset))
+}
+
+fn post(path: &str, body: &[u8]) -> String {
+    let mut server = HttpServer::new(0);
+    server.post("/get_body".to_string(), get_body);
+    server.post("/body_text".to_string(), body_text);
+    server.post("/offset".to_string(), utf8_offset);
+    let (addr, _) = start(server);
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        body.len()
+    )
+    .into_bytes();
+    request.extend_from_slice(body);
+    exchange(addr, &request)
+}
+
+#[test]
+fn get_body_replaces_invalid_utf8() {
+    let response = post("/get_body", b"ok \xff\xfe ok");
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "ok \u{fffd}\u{fffd} ok");
+}
+
+#[test]
+fn body_text_rejects_invalid_utf8_with_its_offset() {
+    let response = post("/body_text", b"caf\xc3");
+    assert_eq!(status(&response), 400);
+    assert_eq!(body(&post("/offset", b"caf\xc3")), "Some(3)");
+    let response = post("/body_text", "café".as_bytes());
+    assert_eq!(body(&response), "café");
+}
+
+fn try_body(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text = match request.try_body() {
+        Ok(body) => format!("read {}", body.len()),
+        Err(e) => e.to_string(),
+    };
+    request.send(&text)
+}
+
+#[test]
+fn truncated_body_is_answered_with_400() {
+    let mut server = HttpServer::new(0);
+    server.post("/".to_string(), try_body);
+    let (addr, _) = start(server);
+    let response = exchange(
+        addr,
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nhello",
+    );
+    assert_eq!(status(&response), 400);
+}
+
+fn to_temp_file(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let (path, size) = request.body_to_temp_file()?;
+    let on_disk = std::fs::read(&path)?;
+    let expected = (0..size).map(|i| (i % 251) as u8);
+    let intact = on_disk.iter().copied().eq(expected);
+    request.send(&format!(
+        "{} {} {} {}",
+        size,
+        on_disk.len(),
+        intact,
+        path.display()
+    ))
+}
+
+#[test]
+fn body_to_temp_file_streams_a_large_upload_to_disk() {
+    let mut server = HttpServer::new(0);
+    server.set_max_body_size(8 << 20);
+    server.post("/".to_string(), to_temp_file);
+    let (addr, _) = start(server);
+    let upload: Vec<u8> = (0..(3 << 20) + 17).map(|i| (i % 251) as u8).collect();
+    let mut request = format!(
+        "POST / HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        upload.len()
+    )
+    .into_bytes();
+    request.extend_from_slice(&upload);
+    let response = exchange(addr, &request);
+    assert_eq!(status(&response), 200);
+    let reply: Vec<&str> = body(&response).splitn(4, ' ').collect();
+    assert_eq!(
+        reply[..3],
+        [
+            &*upload.len().to_string(),
+            &*upload.len().to_string(),
+            "true"
+        ]
+    );
+    assert!(
+        !std::path::Path::new(reply[3]).exists(),
+        "the temp file outlived the handler"
+    );
+}
+
+#[test]
+fn body_to_temp_file_respects_the_max_body_size() {
+    let mut server = HttpServer::new(0);
+    server.set_max_body_size(1024);
+    server.post("/".to_string(), to_temp_file);
+    let (addr, _) = start(server);
+    let mut request =
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 4096\r\nConnection: close\r\n\r\n".to_vec();
+    request.extend_from_slice(&[0; 4096]);
+    assert_eq!(status(&exchange(addr, &request)), 413);
+}
+
+fn progress(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut calls = Vec::new();
+    let len = request
+        .read_body_with_progress(|read, total| calls.push((read, total)))?
+        .len();
+    let increasing = calls.windows(2).all(|pair| pair[0].0 < pair[1].0);
+    let (last, total) = *calls.last().unwrap();
+    request.send(&format!(
+        "{} {} {} {:?} {}",
+        calls.len() > 1,
+        increasing,
+        last,
+        total,
+        len
+    ))
+}
+
+#[test]
+fn progress_callback_counts_up_to_the_body_size() {
+    let mut server = HttpServer::new(0);
+    server.post("/".to_string(), progress);
+    let (addr, _) = start(server);
+    let upload = vec![b'p'; 100_000];
+    let mut request =
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 100000\r\nConnection: close\r\n\r\n"
+            .to_vec();
+    request.extend_from_slice(&upload);
+    let response = exchange(addr, &request);
+    assert_eq!(body(&response), "true true 100000 Some(100000) 100000");
+
+    let chunked =
+        b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+        4\r\nabcd\r\n3\r\nefg\r\n0\r\n\r\n";
+    let response = exchange(addr, chunked);
+    assert!(body(&response).ends_with(" true 7 None 7"), "{}", response);
+}
+
+fn drain(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let drained = request.drain_body()?;
+    request.send(&format!("drained {}", drained))
+}
+
+fn ignore_body(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ignored")
+}
+
+#[test]
+fn drained_body_leaves_the_connection_ready_for_the_next_request() {
+    const SIZE: usize = 16 << 20;
+    let mut server = HttpServer::new(0);
+    server.set_max_body_size(SIZE as u64);
+    server.post("/drain".to_string(), drain);
+    server.post("/ignore".to_string(), ignore_body);
+    server.post("/get_body".to_string(), get_body);
+    let (addr, _) = start(server);
+
+    let mut raw = format!(
+        "POST /drain HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n",
+        SIZE
+    )
+    .into_bytes();
+    raw.resize(raw.len() + SIZE, b'd');
+    raw.extend_from_slice(
+        b"POST /drain HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+          5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+    );
+    raw.extend_from_slice(b"POST /ignore HTTP/1.1\r\nHost: x\r\nContent-Length: 7\r\n\r\nunread!");
+    raw.extend_from_slice(
+        b"POST /get_body HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\nConnection: close\r\n\r\nlast",
+    );
+    let started = Instant::now();
+    let response = exchange(addr, &raw);
+    let elapsed = started.elapsed();
+
+    let bodies: Vec<&str> = split_responses(&response)
+        .into_iter()
+        .map(|(_, body)| body)
+        .collect();
+    assert_eq!(
+        bodies,
+        [
+            format!("drained {}", SIZE).as_str(),
+            "drained 11",
+            "ignored",
+            "last"
+        ]
+    );
+    assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+}
+
+/// Everything written to a `Sink` so far.
+static SUNK: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+struct Sink;
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        SUNK.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn streamed(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let written = match request.streamed_body() {
+        Some(Ok(written)) => written.to_string(),
+        other => format!("{:?}", other),
+    };
+    request.send(&written)
+}
+
+#[test]
+fn streamed_upload_reaches_the_sink_as_it_arrives() {
+    let mut server = HttpServer::new(0);
+    server
+        .post("/upload".to_string(), streamed)
+        .stream_body_to(|_| Box::new(Sink));
+    let (addr, _) = start(server);
+
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: 12\r\n\r\nfirst ")
+        .unwrap();
+    // The handler hasn't run yet, with half the body still to come.
+    wait_until(|| *SUNK.lock().unwrap() == b"first ");
+    stream.write_all(b"second").unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "12");
+    assert_eq!(*SUNK.lock().unwrap(), b"first second");
+}
+
+/// Reads one response with a body from `stream`, without waiting for the
+/// connection to close.
+fn read_response(stream: &mut TcpStream) -> String {
+    let mut response = Vec::new();
+    let mut buf = [0; 512];
+    loop {
+        let text = String::from_utf8_lossy(&response).to_string();
+        if let Some((_, rest)) = text.split_once("\r\n\r\n") {
+            let len = header(&text, "content-length").map_or(0, |len| len.parse().unwrap());
+            if rest.len() >= len {
+                return text;
+            }
+        }
+        let n = stream.read(&mut buf).unwrap();
+        assert_ne!(n, 0, "{:?}", text);
+        response.extend_from_slice(&buf[..n]);
+    }
+}
+
+#[test]
+fn zero_length_body_leaves_the_connection_ready_for_the_next_request() {
+    let mut server = HttpServer::new(0);
+    server.post("/get_body".to_string(), get_body);
+    let (addr, _) = start(server);
+    let mut stream = connect(addr);
+
+    // Answered without waiting for more bytes, and none of the next request
+    // is taken as its body.
+    stream
+        .write_all(b"POST /get_body HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\n\r\n")
+        .unwrap();
+    let empty = read_response(&mut stream);
+    assert_eq!(status(&empty), 200);
+    assert_eq!(body(&empty), "");
+    stream
+        .write_all(b"POST /get_body HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\n\r\nPOST /get_body HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\nnext")
+        .unwrap();
+    assert_eq!(body(&read_response(&mut stream)), "");
+    let next = read_response(&mut stream);
+    assert_eq!(status(&next), 200);
+    assert_eq!(body(&next), "next");
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use common::{body, exchange, get, start};
+use RustGin::{CacheConfig, Clock, HTTPRequest, HTTPResponse, HttpServer, Status};
+
+/// A clock the test moves forward by hand.
+struct ManualClock(Mutex<Instant>);
+
+impl ManualClock {
+    fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+static EXPENSIVE_RUNS: AtomicUsize = AtomicUsize::new(0);
+static NO_STORE_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+fn expensive(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let run = EXPENSIVE_RUNS.fetch_add(1, Ordering::SeqCst) + 1;
+    request.send(&format!("run {}", run))
+}
+
+fn no_store(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let run = NO_STORE_RUNS.fetch_add(1, Ordering::SeqCst) + 1;
+    let mut response = HTTPResponse::new(Status::Ok, format!("run {}", run));
+    response.add_header("Cache-Control".to_string(), "no-store".to_string());
+    request.send_response(&response)
+}
+
+#[test]
+fn repeat_requests_within_the_ttl_are_served_from_the_cache() {
+    let clock = Arc::new(ManualClock(Mutex::new(Instant::now())));
+    let mut server = HttpServer::new(0);
+    server.set_clock(clock.clone());
+    server.response_cache(CacheConfig {
+        ttl: Duration::from_secs(30),
+        max_entries: 16,
+    });
+    server.get("/expensive".to_string(), expensive);
+    server.get("/no-store".to_string(), no_store);
+    let (addr, _) = start(server);
+
+    assert_eq!(body(&get(addr, "/expensive")), "run 1");
+    clock.advance(Duration::from_secs(29));
+    assert_eq!(body(&get(addr, "/expensive")), "run 1");
+    assert_eq!(EXPENSIVE_RUNS.load(Ordering::SeqCst), 1);
+
+    let with_cookie = exchange(
+        addr,
+        b"GET /expensive HTTP/1.1\r\nHost: x\r\nCookie: a=b\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(body(&with_cookie), "run 2");
+
+    clock.advance(Duration::from_secs(2));
+    assert_eq!(body(&get(addr, "/expensive")), "run 3");
+
+    assert_eq!(body(&get(addr, "/no-store")), "run 1");
+    assert_eq!(body(&get(addr, "/no-store")), "run 2");
+}
+
+static ITEM_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+fn item(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let run = ITEM_RUNS.fetch_add(1, Ordering::SeqCst) + 1;
+    let text = format!("{} run {}", request.param("id").unwrap(), run);
+    request.send(&text)
+}
+
+#[test]
+fn least_recently_used_entry_is_evicted() {
+    let mut server = HttpServer::new(0);
+    server.response_cache(CacheConfig {
+        ttl: Duration::from_secs(60),
+        max_entries: 2,
+    });
+    server.get("/items/:id".to_string(), item);
+    let (addr, _) = start(server);
+
+    assert_eq!(body(&get(addr, "/items/a")), "a run 1");
+    assert_eq!(body(&get(addr, "/items/b")), "b run 2");
+    assert_eq!(body(&get(addr, "/items/a")), "a run 1");
+    // `b` is now the least recently used, so it makes way for `c`.
+    assert_eq!(body(&get(addr, "/items/c")), "c run 3");
+    assert_eq!(body(&get(addr, "/items/a")), "a run 1");
+    assert_eq!(body(&get(addr, "/items/b")), "b run 4");
+}
+
+static CLOCKED_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+fn clocked(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let run = CLOCKED_RUNS.fetch_add(1, Ordering::SeqCst) + 1;
+    request.send(&format!("run {}", run))
+}
+
+#[test]
+fn entries_expire_by_the_injected_clock_alone() {
+    let clock = Arc::new(ManualClock(Mutex::new(Instant::now())));
+    let mut server = HttpServer::new(0);
+    server.set_clock(clock.clone());
+    server.response_cache(CacheConfig {
+        ttl: Duration::from_secs(3600),
+        max_entries: 16,
+    });
+    server.get("/clocked".to_string(), clocked);
+    let (addr, _) = start(server);
+
+    assert_eq!(body(&get(addr, "/clocked")), "run 1");
+    clock.advance(Duration::from_secs(3599));
+    assert_eq!(body(&get(addr, "/clocked")), "run 1");
+    // An hour passes without the test sleeping at all.
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(body(&get(addr, "/clocked")), "run 2");
+    assert_eq!(body(&get(addr, "/clocked")), "run 2");
+}
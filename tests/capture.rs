@@ -0,0 +1,49 @@
+mod common;
+
+use std::error::Error;
+
+use common::{body, exchange, split_responses, start};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, Status};
+
+fn echo_raw(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut raw = request.raw().to_vec();
+    raw.extend_from_slice(format!("|{}", request.body_len()).as_bytes());
+    request.send_response(&HTTPResponse::from_bytes(Status::Ok, raw))
+}
+
+fn server(capture: bool) -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.capture_raw(capture);
+    server.post("/".to_string(), echo_raw);
+    server
+}
+
+#[test]
+fn raw_is_the_request_as_sent() {
+    let (addr, _) = start(server(true));
+    let request = "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   3\r\nabc\r\n2\r\nde\r\n0\r\nX-Sum: 5\r\n\r\n";
+    let pipelined = format!("{}{}", request, request.replace("Host: x", "Host: y"));
+    let response = exchange(addr, pipelined.as_bytes());
+    let bodies = split_responses(&response)
+        .into_iter()
+        .map(|(_, body)| body)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        bodies,
+        [
+            format!("{}|5", request),
+            format!("{}|5", request.replace("Host: x", "Host: y"))
+        ]
+    );
+}
+
+#[test]
+fn raw_is_empty_unless_enabled() {
+    let (addr, _) = start(server(false));
+    let response = exchange(
+        addr,
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\nConnection: close\r\n\r\nabc",
+    );
+    assert_eq!(body(&response), "|3");
+}
@@ -0,0 +1,186 @@
+mod common;
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+};
+
+use common::{body, connect, exchange, header, start, status};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, Status};
+
+fn echo(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text = request.get_body().to_string();
+    request.send(&text)
+}
+
+fn body_len(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let before = request.body_len();
+    let after = request.get_body().len();
+    request.send(&format!("{} {}", before, after))
+}
+
+fn trailers(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text = format!("{:?}", request.trailers());
+    request.send(&text)
+}
+
+fn post_chunks(server: HttpServer, chunks: &str) -> String {
+    let (addr, _) = start(server);
+    let request = format!(
+        "POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{}",
+        chunks
+    );
+    exchange(addr, request.as_bytes())
+}
+
+fn server() -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.post("/".to_string(), echo);
+    server
+}
+
+#[test]
+fn chunks_are_joined() {
+    let response = post_chunks(server(), "5\r\nhello\r\n1 ;ext=1\r\n!\r\n0\r\n\r\n");
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "hello!");
+}
+
+#[test]
+fn chunk_sizes_must_be_hex_digits() {
+    for size in ["+5", " 5", "0x5", "-5", "", "5g"] {
+        let response = post_chunks(server(), &format!("{}\r\nhello\r\n0\r\n\r\n", size));
+        assert_eq!(status(&response), 400, "size {:?}", size);
+    }
+}
+
+#[test]
+fn oversized_chunk_is_refused() {
+    for (max, expected) in [(4, 400), (5, 200)] {
+        let mut server = server();
+        server.set_max_chunk_size(max);
+        let response = post_chunks(server, "5\r\nhello\r\n0\r\n\r\n");
+        assert_eq!(status(&response), expected, "limit {}", max);
+    }
+}
+
+#[test]
+fn body_len_is_the_decoded_length() {
+    let mut server = HttpServer::new(0);
+    server.post("/".to_string(), body_len);
+    let chunks = "1a\r\nabcdefghijklmnopqrstuvwxyz\r\n3;name=x\r\n123\r\n10\r\n0123456789abcdef\r\n0\r\n\r\n";
+    let response = post_chunks(server, chunks);
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "45 45");
+}
+
+#[test]
+fn trailers_reach_the_handler() {
+    let mut server = HttpServer::new(0);
+    server.post("/".to_string(), trailers);
+    let response = post_chunks(
+        server,
+        "5\r\nhello\r\n0\r\nX-Checksum: abc123\r\nX-Signature:\tsig \r\n\r\n",
+    );
+    assert_eq!(status(&response), 200);
+    assert_eq!(
+        body(&response),
+        r#"[("X-Checksum", "abc123"), ("X-Signature", "sig")]"#
+    );
+}
+
+#[test]
+fn malformed_trailer_names_are_refused() {
+    for trailer in [
+        " X-Checksum: abc",
+        "X-Checksum : abc",
+        ": abc",
+        "X Checksum: abc",
+    ] {
+        let mut server = HttpServer::new(0);
+        server.post("/".to_string(), trailers);
+        let response = post_chunks(server, &format!("5\r\nhello\r\n0\r\n{}\r\n\r\n", trailer));
+        assert_eq!(status(&response), 400, "trailer {:?}", trailer);
+    }
+}
+
+#[test]
+fn trailers_are_held_to_the_header_limits() {
+    let trailers =
+        |count: usize| -> String { (0..count).map(|i| format!("T{}: {}\r\n", i, i)).collect() };
+    for (count, expected) in [(5, 200), (6, 400)] {
+        let mut server = HttpServer::new(0);
+        server.set_max_headers(5);
+        server.post("/".to_string(), echo);
+        let response = post_chunks(server, &format!("0\r\n{}\r\n", trailers(count)));
+        assert_eq!(status(&response), expected, "{} trailers", count);
+    }
+    let mut server = HttpServer::new(0);
+    server.set_max_header_size(256);
+    server.post("/".to_string(), echo);
+    let long = format!("0\r\nX-Long: {}\r\n\r\n", "v".repeat(300));
+    assert_eq!(status(&post_chunks(server, &long)), 400);
+}
+
+#[test]
+fn oversized_chunked_body_is_refused_before_the_rest_arrives() {
+    let mut server = server();
+    server.set_max_body_size(1000);
+    let (addr, _) = start(server);
+    let chunk = format!("258\r\n{}\r\n", "c".repeat(600));
+    for sent in [
+        chunk.clone() + &chunk,
+        "800\r\n".to_string() + &"c".repeat(100),
+    ] {
+        let mut stream = connect(addr);
+        stream
+            .write_all(b"POST / HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n")
+            .unwrap();
+        stream.write_all(sent.as_bytes()).unwrap();
+        // The body is never finished and the connection left open: only an
+        // answer given mid-stream arrives before the read times out.
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert_eq!(status(&response), 413);
+    }
+}
+
+fn grpc_web(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut response = HTTPResponse::new(Status::Ok, "payload".to_string());
+    response.add_header(
+        "Content-Type".to_string(),
+        "application/grpc-web".to_string(),
+    );
+    response.trailer("grpc-status".to_string(), "0".to_string());
+    response.trailer("grpc-message".to_string(), "done".to_string());
+    request.send_response(&response)
+}
+
+#[test]
+fn trailers_follow_the_last_chunk() {
+    let mut server = HttpServer::new(0);
+    server.get("/call".to_string(), grpc_web);
+    let (addr, _) = start(server);
+
+    let response = exchange(
+        addr,
+        b"GET /call HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(status(&response), 200);
+    assert_eq!(header(&response, "transfer-encoding"), Some("chunked"));
+    assert_eq!(header(&response, "content-length"), None);
+    assert_eq!(
+        header(&response, "trailer"),
+        Some("grpc-status, grpc-message")
+    );
+    assert_eq!(
+        response.split_once("\r\n\r\n").unwrap().1,
+        "7\r\npayload\r\n0\r\ngrpc-status: 0\r\ngrpc-message: done\r\n\r\n"
+    );
+
+    // HTTP/1.0 has no chunked framing to carry them.
+    let response = exchange(addr, b"GET /call HTTP/1.0\r\nHost: x\r\n\r\n");
+    assert_eq!(header(&response, "trailer"), None);
+    assert_eq!(header(&response, "content-length"), Some("7"));
+    assert_eq!(body(&response), "payload");
+}
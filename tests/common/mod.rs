@@ -0,0 +1,116 @@
+#![allow(dead_code)]
+
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use RustGin::{HttpServer, ServerHandle};
+
+/// Serves `server` on a free local port from a background thread.
+pub fn start(server: HttpServer) -> (SocketAddr, ServerHandle) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = server.handle();
+    thread::spawn(move || server.listen_on(listener).unwrap());
+    (addr, handle)
+}
+
+/// Like [`start`], keeping `server` around for the test to inspect.
+pub fn start_shared(server: Arc<HttpServer>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || server.listen_on(listener).unwrap());
+    addr
+}
+
+pub fn connect(addr: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+}
+
+/// Sends `raw` on a fresh connection, closes the sending side and returns
+/// everything the server wrote back.
+pub fn exchange(addr: SocketAddr, raw: &[u8]) -> String {
+    String::from_utf8_lossy(&exchange_bytes(addr, raw)).into_owned()
+}
+
+pub fn exchange_bytes(addr: SocketAddr, raw: &[u8]) -> Vec<u8> {
+    let mut stream = connect(addr);
+    stream.write_all(raw).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut out = Vec::new();
+    let _ = stream.read_to_end(&mut out);
+    out
+}
+
+/// A GET for `path` that asks for the connection to be closed afterwards.
+pub fn get(addr: SocketAddr, path: &str) -> String {
+    exchange(
+        addr,
+        format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        )
+        .as_bytes(),
+    )
+}
+
+/// The status code of the first response in `response`.
+pub fn status(response: &str) -> u16 {
+    response
+        .split(' ')
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .unwrap_or_else(|| panic!("no status line in {:?}", response))
+}
+
+/// The value of the first `name` header in the first response in `response`.
+pub fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    let head = response.split("\r\n\r\n").next()?;
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// The body of the first response in `response`.
+pub fn body(response: &str) -> &str {
+    response.split_once("\r\n\r\n").map_or("", |(_, body)| body)
+}
+
+/// Splits pipelined responses framed by `Content-Length` into their heads
+/// and bodies.
+pub fn split_responses(mut raw: &str) -> Vec<(&str, &str)> {
+    let mut responses = Vec::new();
+    while let Some((head, rest)) = raw.split_once("\r\n\r\n") {
+        let len = header(raw, "content-length").map_or(0, |len| len.parse().unwrap());
+        responses.push((head, &rest[..len]));
+        raw = &rest[len..];
+    }
+    responses
+}
+
+/// Waits up to five seconds for `done` to hold, for counters updated just
+/// after a connection closes.
+pub fn wait_until(mut done: impl FnMut() -> bool) {
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while !done() {
+        assert!(std::time::Instant::now() < deadline, "timed out waiting");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// An empty directory of its own for `test` to work in.
+pub fn temp_dir(test: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("rustgin-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use std::error::Error;
+
+use common::{body, get, start, status};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn socket_options(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let stream = request.tcp_stream()?;
+    let text = format!("nodelay={} ttl={}", stream.nodelay()?, stream.ttl()?);
+    request.send(&text)
+}
+
+fn options(configure: bool) -> String {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), socket_options);
+    if configure {
+        server.configure_stream(|stream| {
+            stream.set_nodelay(true).unwrap();
+            stream.set_ttl(42).unwrap();
+        });
+    }
+    let (addr, _) = start(server);
+    let response = get(addr, "/");
+    assert_eq!(status(&response), 200);
+    body(&response).to_string()
+}
+
+#[test]
+fn options_set_in_the_hook_are_seen_by_handlers() {
+    assert_eq!(options(true), "nodelay=true ttl=42");
+    assert_ne!(options(false), "nodelay=true ttl=42");
+}
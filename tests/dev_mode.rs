@@ -0,0 +1,64 @@
+mod common;
+
+use std::{error::Error, fmt, io};
+
+use common::{body, get, start, status};
+use RustGin::{HTTPRequest, HttpServer};
+
+#[derive(Debug)]
+struct Layer {
+    message: &'static str,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+impl Error for Layer {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+fn fails(_: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let root = io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused");
+    let middle = Layer {
+        message: "could not reach the database",
+        source: Some(Box::new(root)),
+    };
+    Err(Box::new(Layer {
+        message: "failed to load user 7",
+        source: Some(Box::new(middle)),
+    }))
+}
+
+fn response(dev_mode: bool) -> String {
+    let mut server = HttpServer::new(0);
+    server.set_dev_mode(dev_mode);
+    server.get("/".to_string(), fails);
+    let (addr, _) = start(server);
+    get(addr, "/")
+}
+
+#[test]
+fn dev_mode_renders_every_level_of_the_source_chain() {
+    let response = response(true);
+    assert_eq!(status(&response), 500);
+    assert_eq!(
+        body(&response),
+        "Internal Server Error\n\n\
+         failed to load user 7\n\
+         caused by: could not reach the database\n\
+         caused by: connection refused"
+    );
+}
+
+#[test]
+fn production_error_stays_generic() {
+    let response = response(false);
+    assert_eq!(status(&response), 500);
+    assert_eq!(body(&response), "Internal Server Error");
+}
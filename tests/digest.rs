@@ -0,0 +1,74 @@
+#![cfg(feature = "digest")]
+
+mod common;
+
+use std::error::Error;
+
+use common::{body, exchange, start, status};
+use RustGin::{DigestAlgorithm, HTTPRequest, HttpServer};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn sha256(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let (_, digest) = request.body_digest(DigestAlgorithm::Sha256)?;
+    let digest = hex(&digest);
+    // The body is still there afterwards.
+    let text = format!("{} {}", digest, request.get_body());
+    request.send(&text)
+}
+
+fn sha512(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let (body, digest) = request.body_digest(DigestAlgorithm::Sha512)?;
+    let text = format!("{} {}", hex(&digest), body.len());
+    request.send(&text)
+}
+
+fn post(path: &str, body: &str) -> String {
+    let mut server = HttpServer::new(0);
+    server.post("/sha256".to_string(), sha256);
+    server.post("/sha512".to_string(), sha512);
+    let (addr, _) = start(server);
+    exchange(
+        addr,
+        format!(
+            "POST {} HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        )
+        .as_bytes(),
+    )
+}
+
+#[test]
+fn sha256_of_a_known_body() {
+    let response = post("/sha256", "abc");
+    assert_eq!(
+        body(&response),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad abc"
+    );
+}
+
+#[test]
+fn sha512_of_a_known_body() {
+    let response = post("/sha512", "abc");
+    assert_eq!(
+        body(&response),
+        "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+         2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f 3"
+    );
+}
+
+#[test]
+fn truncated_body_is_an_error() {
+    let mut server = HttpServer::new(0);
+    server.post("/sha256".to_string(), sha256);
+    let (addr, _) = start(server);
+    let response = exchange(
+        addr,
+        b"POST /sha256 HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nabc",
+    );
+    assert_eq!(status(&response), 400);
+}
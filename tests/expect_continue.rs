@@ -0,0 +1,72 @@
+mod common;
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+    net::{Shutdown, SocketAddr},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use common::{body, connect, start, status};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, Status};
+
+static REJECTED_BODIES_READ: AtomicUsize = AtomicUsize::new(0);
+
+fn upload(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let len = request.get_body().len();
+    request.send(&format!("stored {}", len))
+}
+
+fn rejected_upload(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.get_body();
+    REJECTED_BODIES_READ.fetch_add(1, Ordering::SeqCst);
+    request.send("stored")
+}
+
+fn server(handler: fn(HTTPRequest) -> Result<(), Box<dyn Error>>) -> SocketAddr {
+    let mut server = HttpServer::new(0);
+    server.post("/upload".to_string(), handler);
+    server.before_body(|request| {
+        let length = request.get_headers().get("content-length")?;
+        (length.parse::<u64>().ok()? > 1024)
+            .then(|| HTTPResponse::new(Status::PayloadTooLarge, "too large".to_string()))
+    });
+    start(server).0
+}
+
+fn head(length: usize) -> String {
+    format!(
+        "POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\nExpect: 100-continue\r\n\r\n",
+        length
+    )
+}
+
+#[test]
+fn rejecting_hook_answers_instead_of_100_continue() {
+    let addr = server(rejected_upload);
+    let mut stream = connect(addr);
+    stream.write_all(head(1 << 20).as_bytes()).unwrap();
+    // The body is never sent: the server must answer on the headers alone.
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(status(&response), 413, "{}", response);
+    assert!(!response.contains("100 Continue"), "{}", response);
+    assert_eq!(body(&response), "too large");
+    assert_eq!(REJECTED_BODIES_READ.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn accepted_upload_gets_100_continue_then_its_response() {
+    let addr = server(upload);
+    let mut stream = connect(addr);
+    stream.write_all(head(5).as_bytes()).unwrap();
+    let mut interim = [0; 25];
+    stream.read_exact(&mut interim).unwrap();
+    assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+    stream.write_all(b"hello").unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "stored 5");
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::{
+    error::Error,
+    io::{ErrorKind, Read, Write},
+    net::TcpListener,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use common::{body, connect, get, header, start, status};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+fn server() -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), ok);
+    server
+}
+
+#[test]
+fn paused_server_serves_waiting_clients_once_resumed() {
+    let (addr, handle) = start(server());
+    assert_eq!(status(&get(addr, "/")), 200);
+
+    handle.pause();
+    assert!(handle.is_paused());
+    let mut client = connect(addr);
+    client
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_millis(300)))
+        .unwrap();
+    let mut byte = [0; 1];
+    let waited = client.read(&mut byte).unwrap_err();
+    assert!(matches!(
+        waited.kind(),
+        ErrorKind::WouldBlock | ErrorKind::TimedOut
+    ));
+
+    handle.resume();
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut response = String::new();
+    client.read_to_string(&mut response).unwrap();
+    assert_eq!(status(&response), 200);
+}
+
+#[test]
+fn shutdown_while_paused_stops_listening() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = server();
+    let handle = server.handle();
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        server.listen_on(listener).unwrap();
+        done.send(()).unwrap();
+    });
+    thread::sleep(Duration::from_millis(50));
+    handle.pause();
+    handle.shutdown();
+    finished.recv_timeout(Duration::from_secs(5)).unwrap();
+}
+
+#[test]
+fn server_answers_503_until_ready() {
+    let mut server = server();
+    server.readiness(vec![]);
+    server.handle().set_ready(false);
+    let (addr, handle) = start(server);
+
+    let response = get(addr, "/");
+    assert_eq!(status(&response), 503);
+    assert_eq!(header(&response, "Retry-After"), Some("5"));
+    assert_eq!(status(&get(addr, "/livez")), 200);
+    let ready = get(addr, "/readyz");
+    assert_eq!(status(&ready), 503);
+    assert_eq!(body(&ready), "server: starting");
+
+    handle.set_ready(true);
+    let response = get(addr, "/");
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "ok");
+    assert_eq!(header(&response, "Retry-After"), None);
+    assert_eq!(status(&get(addr, "/readyz")), 200);
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::error::Error;
+
+use common::{exchange, header, start, status};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, Method, Status};
+
+fn get_page(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut response = HTTPResponse::new(Status::Ok, "the page".to_string());
+    response.add_header("X-Handler".to_string(), "get".to_string());
+    request.send_response(&response)
+}
+
+fn head_page(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut response = HTTPResponse::new(Status::Ok, String::new());
+    response.add_header("X-Handler".to_string(), "head".to_string());
+    request.send_response(&response)
+}
+
+fn head(addr: std::net::SocketAddr, path: &str) -> String {
+    exchange(
+        addr,
+        format!(
+            "HEAD {} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            path
+        )
+        .as_bytes(),
+    )
+}
+
+#[test]
+fn explicit_head_handler_takes_precedence() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), get_page);
+    server.add_method(Method::HEAD, "/".to_string(), head_page);
+    let (addr, _) = start(server);
+    let response = head(addr, "/");
+    assert_eq!(status(&response), 200);
+    assert_eq!(header(&response, "x-handler"), Some("head"));
+}
+
+#[test]
+fn head_falls_back_to_get_without_the_body() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), get_page);
+    let (addr, _) = start(server);
+    let response = head(addr, "/");
+    assert_eq!(status(&response), 200);
+    assert_eq!(header(&response, "x-handler"), Some("get"));
+    assert_eq!(header(&response, "content-length"), Some("8"));
+    assert!(response.ends_with("\r\n\r\n"), "{:?}", response);
+}
+
+fn head_with_body(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let response = HTTPResponse::new(Status::Ok, "a body by mistake".to_string());
+    request.send_response(&response)
+}
+
+#[test]
+fn head_handler_body_is_dropped_but_its_length_kept() {
+    let mut server = HttpServer::new(0);
+    server.add_method(Method::HEAD, "/".to_string(), head_with_body);
+    server.get("/next".to_string(), get_page);
+    let (addr, _) = start(server);
+    // The next response must start right after the first head, with none of
+    // the stray body in between.
+    let response = exchange(
+        addr,
+        b"HEAD / HTTP/1.1\r\nHost: x\r\n\r\nGET /next HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    let (first, next) = response.split_once("\r\n\r\n").unwrap();
+    assert_eq!(status(first), 200);
+    assert_eq!(header(first, "content-length"), Some("17"));
+    assert!(next.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", next);
+    assert!(next.ends_with("\r\n\r\nthe page"), "{:?}", next);
+}
+
+#[test]
+fn lowercase_head_is_answered_without_the_body() {
+    let mut server = HttpServer::new(0);
+    server.add_method(Method::HEAD, "/".to_string(), head_with_body);
+    server.get("/next".to_string(), get_page);
+    let (addr, _) = start(server);
+    let response = exchange(
+        addr,
+        b"head / HTTP/1.1\r\nHost: x\r\n\r\nGET /next HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    let (first, next) = response.split_once("\r\n\r\n").unwrap();
+    assert_eq!(status(first), 200);
+    assert_eq!(header(first, "content-length"), Some("17"));
+    assert!(next.starts_with("HTTP/1.1 200 OK\r\n"), "{:?}", next);
+}
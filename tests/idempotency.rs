@@ -0,0 +1,84 @@
+mod common;
+
+use std::{
+    error::Error,
+    net::SocketAddr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use common::{body, exchange, header, start, status};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, IdempotencyConfig, Status};
+
+static ORDERS: AtomicUsize = AtomicUsize::new(0);
+static ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+fn place_order(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let order = ORDERS.fetch_add(1, Ordering::SeqCst) + 1;
+    let mut response = HTTPResponse::new(Status::Ok, format!("order {}", order));
+    response.add_header("X-Order".to_string(), order.to_string());
+    request.send_response(&response)
+}
+
+fn send(addr: SocketAddr, method: &str, key: &str) -> String {
+    exchange(
+        addr,
+        format!(
+            "{} /orders HTTP/1.1\r\nHost: x\r\nIdempotency-Key: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            method, key
+        )
+        .as_bytes(),
+    )
+}
+
+#[test]
+fn repeated_key_runs_the_handler_once() {
+    let mut server = HttpServer::new(0);
+    server.post("/orders".to_string(), place_order);
+    server.get("/orders".to_string(), place_order);
+    server.idempotency(IdempotencyConfig {
+        ttl: Duration::from_secs(60),
+        max_entries: 100,
+    });
+    let (addr, _) = start(server);
+
+    let first = send(addr, "POST", "abc");
+    let retry = send(addr, "POST", "abc");
+    assert_eq!(ORDERS.load(Ordering::SeqCst), 1);
+    assert_eq!(first, retry);
+    for response in [&first, &retry] {
+        assert_eq!(status(response), 200);
+        assert_eq!(body(response), "order 1");
+        assert_eq!(header(response, "x-order"), Some("1"));
+    }
+
+    assert_eq!(body(&send(addr, "POST", "def")), "order 2");
+    // GET is safe to repeat, so its key is ignored.
+    assert_eq!(body(&send(addr, "GET", "abc")), "order 3");
+    assert_eq!(body(&send(addr, "GET", "abc")), "order 4");
+}
+
+fn fails_once(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    if ATTEMPTS.fetch_add(1, Ordering::SeqCst) == 0 {
+        panic!("handler failed");
+    }
+    request.send_response(&HTTPResponse::new(Status::Ok, "placed".to_string()))
+}
+
+#[test]
+fn handler_panic_frees_the_key_for_a_retry() {
+    let mut server = HttpServer::new(0);
+    server.post("/orders".to_string(), fails_once);
+    server.idempotency(IdempotencyConfig {
+        ttl: Duration::from_secs(60),
+        max_entries: 100,
+    });
+    let (addr, _) = start(server);
+
+    // The panic takes the connection down without a response.
+    assert_eq!(send(addr, "POST", "abc"), "");
+    let retry = send(addr, "POST", "abc");
+    assert_eq!(status(&retry), 200);
+    assert_eq!(body(&retry), "placed");
+    assert_eq!(ATTEMPTS.load(Ordering::SeqCst), 2);
+}
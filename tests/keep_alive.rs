@@ -0,0 +1,228 @@
+mod common;
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+use common::{connect, exchange, header, split_responses, start, start_shared, status, wait_until};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, Status};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+fn server() -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), ok);
+    server.get("/once".to_string(), ok).no_keep_alive();
+    server
+}
+
+const TWO_REQUESTS: &[u8] = b"GET / HTTP/1.1\r\nHost: x\r\n\r\nGET / HTTP/1.1\r\nHost: x\r\n\r\n";
+
+#[test]
+fn keep_alive_header_reflects_the_configuration() {
+    let mut server = server();
+    server.set_idle_timeout(Duration::from_secs(7));
+    server.set_max_requests_per_connection(3);
+    let (addr, _) = start(server);
+    let response = exchange(addr, TWO_REQUESTS);
+    let responses = split_responses(&response);
+    assert_eq!(responses.len(), 2);
+    assert_eq!(
+        header(responses[0].0, "keep-alive"),
+        Some("timeout=7, max=3")
+    );
+    assert_eq!(
+        header(responses[1].0, "keep-alive"),
+        Some("timeout=7, max=2")
+    );
+}
+
+#[test]
+fn keep_alive_header_is_absent_when_closing() {
+    let (addr, _) = start(server());
+    let response = exchange(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(header(&response, "connection"), Some("close"));
+    assert_eq!(header(&response, "keep-alive"), None);
+
+    let mut server = server();
+    server.set_max_requests_per_connection(1);
+    let (addr, _) = start(server);
+    let response = exchange(addr, TWO_REQUESTS);
+    assert_eq!(split_responses(&response).len(), 1);
+    assert_eq!(header(&response, "keep-alive"), None);
+}
+
+#[test]
+fn no_keep_alive_route_closes_the_connection() {
+    let (addr, _) = start(server());
+    let response = exchange(
+        addr,
+        b"GET /once HTTP/1.1\r\nHost: x\r\nConnection: keep-alive\r\n\r\n\
+          GET / HTTP/1.1\r\nHost: x\r\n\r\n",
+    );
+    let responses = split_responses(&response);
+    assert_eq!(responses.len(), 1);
+    assert_eq!(status(&response), 200);
+    assert_eq!(header(&response, "connection"), Some("close"));
+    // Other routes keep the connection open.
+    assert_eq!(split_responses(&exchange(addr, TWO_REQUESTS)).len(), 2);
+}
+
+/// The status of every response to the pipelined requests in `raw`.
+fn statuses(addr: std::net::SocketAddr, raw: &str) -> Vec<u16> {
+    split_responses(&exchange(addr, raw.as_bytes()))
+        .into_iter()
+        .map(|(head, _)| status(head))
+        .collect()
+}
+
+fn request_with_headers(count: usize) -> String {
+    let mut request = "GET / HTTP/1.1\r\nHost: x\r\n".to_string();
+    for i in 1..count {
+        request.push_str(&format!("X-Header-{}: {}\r\n", i, i));
+    }
+    request + "\r\n"
+}
+
+#[test]
+fn header_count_limit_applies_to_each_request_on_a_connection() {
+    let mut server = server();
+    server.set_max_headers(5);
+    let (addr, _) = start(server);
+
+    // Five headers each, fifteen between them: within the limit every time.
+    let raw = request_with_headers(5).repeat(3);
+    assert_eq!(statuses(addr, &raw), [200, 200, 200]);
+
+    let raw = request_with_headers(5) + &request_with_headers(6);
+    assert_eq!(statuses(addr, &raw), [200, 431]);
+}
+
+#[test]
+fn header_size_limit_applies_to_each_request_on_a_connection() {
+    let mut server = server();
+    server.set_max_header_size(200);
+    let (addr, _) = start(server);
+    let padded = |len: usize| {
+        format!(
+            "GET / HTTP/1.1\r\nHost: x\r\nX-Pad: {}\r\n\r\n",
+            "a".repeat(len)
+        )
+    };
+
+    let raw = padded(100).repeat(3);
+    assert_eq!(statuses(addr, &raw), [200, 200, 200]);
+
+    let raw = padded(100) + &padded(400);
+    assert_eq!(statuses(addr, &raw), [200, 431]);
+}
+
+/// Sends one request on `stream` and reads its response, leaving the
+/// connection open.
+fn request_on(stream: &mut TcpStream) -> String {
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut response = Vec::new();
+    let mut buf = [0; 512];
+    while !response.ends_with(b"\r\n\r\nok") {
+        let n = stream.read(&mut buf).unwrap();
+        assert_ne!(n, 0, "{:?}", String::from_utf8_lossy(&response));
+        response.extend_from_slice(&buf[..n]);
+    }
+    String::from_utf8(response).unwrap()
+}
+
+#[test]
+fn connections_beyond_the_idle_cap_are_closed_after_responding() {
+    let mut server = server();
+    server.set_max_idle_connections(1);
+    let server = Arc::new(server);
+    let addr = start_shared(server.clone());
+
+    let mut first = connect(addr);
+    assert_eq!(
+        header(&request_on(&mut first), "connection"),
+        Some("keep-alive")
+    );
+
+    // `first` is idle and holds the only place, so `second` is closed.
+    let mut second = connect(addr);
+    assert_eq!(
+        header(&request_on(&mut second), "connection"),
+        Some("close")
+    );
+    let mut rest = Vec::new();
+    second.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    // Once `first` goes, its place is free again.
+    first.shutdown(Shutdown::Both).unwrap();
+    wait_until(|| server.pool_stats().active == 0);
+    let mut third = connect(addr);
+    assert_eq!(
+        header(&request_on(&mut third), "connection"),
+        Some("keep-alive")
+    );
+}
+
+fn echo(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let value = request.get_headers().get("x-test").cloned();
+    let text = format!(
+        "[{}|{}]",
+        value.clone().unwrap_or_default(),
+        request.get_body()
+    );
+    let mut response = HTTPResponse::new(Status::Ok, text);
+    if let Some(value) = value {
+        response.add_header("X-Echo".to_string(), value);
+    }
+    request.send_response(&response)
+}
+
+#[test]
+fn reused_buffers_carry_nothing_into_the_next_request() {
+    let mut server = HttpServer::new(0);
+    server.get("/echo".to_string(), echo);
+    server.post("/echo".to_string(), echo);
+    let (addr, _) = start(server);
+    let long_header = "h".repeat(200);
+    let long_body = "a".repeat(3000);
+    let requests = format!(
+        "POST /echo HTTP/1.1\r\nHost: x\r\nX-Test: {}\r\nContent-Length: {}\r\n\r\n{}\
+         GET /echo HTTP/1.1\r\nHost: x\r\n\r\n\
+         POST /echo HTTP/1.1\r\nHost: x\r\nX-Test: s\r\nContent-Length: 1\r\nConnection: close\r\n\r\nb",
+        long_header,
+        long_body.len(),
+        long_body
+    );
+
+    let raw = exchange(addr, requests.as_bytes());
+    let responses = split_responses(&raw);
+    assert_eq!(responses.len(), 3, "{}", raw);
+    let expected = [
+        (
+            Some(long_header.as_str()),
+            format!("[{}|{}]", long_header, long_body),
+        ),
+        (None, "[|]".to_string()),
+        (Some("s"), "[s|b]".to_string()),
+    ];
+    for ((head, body), (echoed, text)) in responses.into_iter().zip(expected) {
+        assert_eq!(header(head, "x-echo"), echoed, "{}", head);
+        assert_eq!(
+            header(head, "content-length"),
+            Some(text.len().to_string().as_str())
+        );
+        assert_eq!(body, text);
+    }
+}
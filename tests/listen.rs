@@ -0,0 +1,24 @@
+mod common;
+
+use std::{error::Error, net::TcpListener, thread};
+
+use common::{body, get, status};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+#[test]
+fn serves_on_a_listener_bound_by_the_caller() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    // Known before the server starts.
+    let addr = listener.local_addr().unwrap();
+    let mut server = HttpServer::new(1);
+    server.get("/".to_string(), ok);
+    thread::spawn(move || server.listen_on(listener).unwrap());
+
+    let response = get(addr, "/");
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "ok");
+}
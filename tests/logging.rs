@@ -0,0 +1,215 @@
+mod common;
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+    net::Shutdown,
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use common::{body, connect, get, header, start, start_shared, status, wait_until};
+use log::{Level, Log, Metadata, Record};
+use RustGin::{Clock, HTTPRequest, HTTPResponse, HttpServer, Status};
+
+/// Keeps every message logged by the server, for the tests to search.
+struct Capture(Mutex<Vec<(Level, String)>>);
+
+impl Log for Capture {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        if record.target().starts_with("RustGin") {
+            let message = record.args().to_string();
+            self.0.lock().unwrap().push((record.level(), message));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGS: Capture = Capture(Mutex::new(Vec::new()));
+
+/// Starts capturing, and returns what has been logged at `level` so far
+/// that mentions `text`.
+fn logged(level: Level, text: &str) -> Vec<String> {
+    if log::set_logger(&LOGS).is_ok() {
+        log::set_max_level(log::LevelFilter::Trace);
+    }
+    LOGS.0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(logged, message)| *logged == level && message.contains(text))
+        .map(|(_, message)| message.clone())
+        .collect()
+}
+
+fn annotated(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.span().record("db_ms", 12);
+    request.span().record("cache", "miss");
+    request.span().record("db_ms", 15);
+    request.send("ok")
+}
+
+#[test]
+fn recorded_fields_appear_in_the_completion_log_line() {
+    logged(Level::Info, "");
+    let mut server = HttpServer::new(0);
+    server.get("/annotated".to_string(), annotated);
+    let (addr, _) = start(server);
+    get(addr, "/annotated?page=2");
+
+    wait_until(|| !logged(Level::Info, "GET /annotated").is_empty());
+    let lines = logged(Level::Info, "GET /annotated");
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    let line = &lines[0];
+    assert!(line.starts_with("GET /annotated?page=2 200 "), "{}", line);
+    assert!(line.ends_with("ms db_ms=15 cache=miss"), "{}", line);
+}
+
+/// A clock that only moves when a handler says so.
+struct ManualClock(Mutex<Instant>);
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
+}
+
+static CLOCK: OnceLock<Arc<ManualClock>> = std::sync::OnceLock::new();
+
+fn clock() -> Arc<ManualClock> {
+    CLOCK
+        .get_or_init(|| Arc::new(ManualClock(Mutex::new(Instant::now()))))
+        .clone()
+}
+
+fn fast(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    *clock().0.lock().unwrap() += Duration::from_millis(499);
+    request.send("fast")
+}
+
+fn slow(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    *clock().0.lock().unwrap() += Duration::from_millis(750);
+    request.send("slow")
+}
+
+#[test]
+fn only_requests_over_the_threshold_are_logged_as_slow() {
+    logged(Level::Warn, "");
+    let mut server = HttpServer::new(0);
+    server.set_clock(clock());
+    server.log_slow_requests(Duration::from_millis(500));
+    server.get("/fast".to_string(), fast);
+    server.get("/slow".to_string(), slow);
+    let (addr, _) = start(server);
+
+    get(addr, "/fast");
+    get(addr, "/slow?id=1");
+    wait_until(|| logged(Level::Info, "GET /slow").len() == 1);
+    assert_eq!(
+        logged(Level::Warn, "Slow request"),
+        ["Slow request: GET /slow took 750ms"]
+    );
+}
+
+fn giant_cookie(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut response = HTTPResponse::new(Status::Ok, "fine".to_string());
+    response.set_cookie("huge", &"c".repeat(8192));
+    request.send_response(&response)
+}
+
+fn small(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("small")
+}
+
+#[test]
+fn oversized_response_headers_give_500_and_an_error_log() {
+    logged(Level::Error, "");
+    let mut server = HttpServer::new(0);
+    server.set_max_response_header_size(4096);
+    server.get("/giant-cookie".to_string(), giant_cookie);
+    server.get("/small".to_string(), small);
+    let (addr, _) = start(server);
+
+    let response = get(addr, "/giant-cookie");
+    assert_eq!(status(&response), 500);
+    assert_eq!(header(&response, "set-cookie"), None);
+    let length: usize = header(&response, "content-length")
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(body(&response).len(), length);
+    let errors = logged(Level::Error, "GET /giant-cookie");
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(
+        errors[0].contains("over the limit of 4096"),
+        "{}",
+        errors[0]
+    );
+
+    assert_eq!(body(&get(addr, "/small")), "small");
+}
+
+/// Everything logged about a connection or request failing.
+fn connection_problems() -> Vec<String> {
+    let mut problems = Vec::new();
+    for level in [Level::Error, Level::Warn, Level::Debug] {
+        problems.extend(logged(level, "Connection closed"));
+        problems.extend(logged(level, "Failed to read request"));
+    }
+    problems
+}
+
+#[test]
+fn idle_close_ends_quietly_and_mid_request_close_is_reported() {
+    logged(Level::Info, "");
+    let mut server = HttpServer::new(0);
+    server.get("/small".to_string(), small);
+    let server = Arc::new(server);
+    let addr = start_shared(server.clone());
+    let connection_closed = || server.pool_stats().active == 0;
+
+    // Closed while idle between requests: nothing is logged.
+    let before = connection_problems().len();
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"GET /small HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut response = Vec::new();
+    let mut buf = [0; 256];
+    while !response.ends_with(b"small") {
+        let n = stream.read(&mut buf).unwrap();
+        assert_ne!(n, 0, "{:?}", String::from_utf8_lossy(&response));
+        response.extend_from_slice(&buf[..n]);
+    }
+    assert!(response.starts_with(b"HTTP/1.1 200"));
+    stream.shutdown(Shutdown::Write).unwrap();
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    wait_until(connection_closed);
+    let after = connection_problems();
+    assert_eq!(after.len(), before, "{:?}", after);
+
+    // Closed partway through the second request's head: 400, and a warning.
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"GET /small HTTP/1.1\r\nHost: x\r\n\r\nGET /small HTTP/1.1\r\nHo")
+        .unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut raw = String::new();
+    stream.read_to_string(&mut raw).unwrap();
+    let second = &raw[raw.find("smallHTTP/1.1").unwrap() + "small".len()..];
+    assert_eq!(status(second), 400);
+    wait_until(connection_closed);
+    assert_eq!(
+        logged(
+            Level::Warn,
+            "Connection closed in the middle of a request head"
+        )
+        .len(),
+        1
+    );
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use std::{
+    error::Error,
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{body, get, start, status, wait_until};
+use RustGin::{HTTPRequest, HttpServer};
+
+/// The sender for the request `poll` is holding open.
+static WAITING: Mutex<Option<mpsc::Sender<Vec<u8>>>> = Mutex::new(None);
+
+fn poll(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let (sender, receiver) = mpsc::channel();
+    *WAITING.lock().unwrap() = Some(sender);
+    request.long_poll(&receiver, Duration::from_secs(3))
+}
+
+fn quiet(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let (_sender, receiver) = mpsc::channel();
+    request.long_poll(&receiver, Duration::from_millis(300))
+}
+
+fn server() -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.get("/poll".to_string(), poll);
+    server.get("/quiet".to_string(), quiet);
+    server
+}
+
+#[test]
+fn data_before_the_timeout_is_sent_with_200() {
+    let (addr, _) = start(server());
+    let started = Instant::now();
+    let client = thread::spawn(move || get(addr, "/poll"));
+    wait_until(|| WAITING.lock().unwrap().is_some());
+    let sender = WAITING.lock().unwrap().take().unwrap();
+    sender.send(b"new message".to_vec()).unwrap();
+
+    let response = client.join().unwrap();
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "new message");
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn no_data_gives_204_after_the_timeout() {
+    let (addr, _) = start(server());
+    let started = Instant::now();
+    let response = get(addr, "/quiet");
+    assert!(started.elapsed() >= Duration::from_millis(300));
+    assert_eq!(status(&response), 204);
+    assert_eq!(body(&response), "");
+}
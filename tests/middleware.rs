@@ -0,0 +1,150 @@
+mod common;
+
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+use common::{exchange, get, header, start, status};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, MiddlewareStack, Status};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+fn fails(_: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    Err("handler failed".into())
+}
+
+#[test]
+fn after_hooks_run_on_every_response_including_errors() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), ok);
+    server.get("/fails".to_string(), fails);
+    server.after(|_, response| {
+        response.add_header("X-Frame-Options".to_string(), "DENY".to_string());
+    });
+    // A header already set is kept, so the first hook's value shows they
+    // ran in order.
+    server.after(|_, response| {
+        response.add_header("X-Frame-Options".to_string(), "SAMEORIGIN".to_string());
+    });
+    let (addr, _) = start(server);
+
+    let responses = [
+        get(addr, "/"),
+        get(addr, "/missing"),
+        get(addr, "/fails"),
+        exchange(
+            addr,
+            b"DELETE / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        ),
+    ];
+    let statuses: Vec<u16> = responses.iter().map(|response| status(response)).collect();
+    assert_eq!(statuses, [200, 404, 500, 405]);
+    for response in &responses {
+        assert_eq!(
+            header(response, "x-frame-options"),
+            Some("DENY"),
+            "{}",
+            response
+        );
+    }
+}
+
+fn timed(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.server_timing("db", 12.3);
+    request.server_timing("render", 4.0);
+    request.send("ok")
+}
+
+#[test]
+fn recorded_timings_are_sent_as_server_timing() {
+    let mut server = HttpServer::new(0);
+    server.get("/timed".to_string(), timed);
+    server.get("/".to_string(), ok);
+    server.after(|request, _| request.server_timing("total", 0.5));
+    let (addr, _) = start(server);
+
+    let response = get(addr, "/timed");
+    assert_eq!(
+        header(&response, "server-timing"),
+        Some("db;dur=12.3, render;dur=4, total;dur=0.5")
+    );
+    assert_eq!(
+        header(&get(addr, "/"), "server-timing"),
+        Some("total;dur=0.5")
+    );
+}
+
+#[test]
+fn nothing_timed_sends_no_server_timing() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), ok);
+    let (addr, _) = start(server);
+    assert_eq!(header(&get(addr, "/"), "server-timing"), None);
+}
+
+#[test]
+fn middleware_stack_runs_in_order_and_is_shared_between_servers() {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut stack = MiddlewareStack::new();
+    let (first, second, third) = (log.clone(), log.clone(), log.clone());
+    stack
+        .before("request-id", move |request| {
+            first
+                .lock()
+                .unwrap()
+                .push(format!("request-id {}", request.path()));
+            None
+        })
+        .before("auth", move |request| {
+            second
+                .lock()
+                .unwrap()
+                .push(format!("auth {}", request.path()));
+            (request.path() == "/private")
+                .then(|| HTTPResponse::new(Status::Conflict, "no".to_string()))
+        })
+        .after("security-headers", move |request, response| {
+            third
+                .lock()
+                .unwrap()
+                .push(format!("security-headers {}", request.path()));
+            response.add_header("X-Frame-Options".to_string(), "DENY".to_string());
+        });
+    assert_eq!(
+        stack.names().collect::<Vec<_>>(),
+        ["request-id", "auth", "security-headers"]
+    );
+    assert_eq!(stack.len(), 3);
+
+    let mut addrs = Vec::new();
+    for _ in 0..2 {
+        let mut server = HttpServer::new(0);
+        server.get("/".to_string(), ok);
+        server.get("/private".to_string(), ok);
+        server.middleware(&stack);
+        addrs.push(start(server).0);
+    }
+    for addr in addrs {
+        log.lock().unwrap().clear();
+        let response = get(addr, "/");
+        assert_eq!(status(&response), 200);
+        assert_eq!(header(&response, "x-frame-options"), Some("DENY"));
+        let response = get(addr, "/private");
+        assert_eq!(status(&response), 409);
+        assert_eq!(header(&response, "x-frame-options"), Some("DENY"));
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "request-id /",
+                "auth /",
+                "security-headers /",
+                "request-id /private",
+                "auth /private",
+                "security-headers /private",
+            ]
+        );
+    }
+}
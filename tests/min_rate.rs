@@ -0,0 +1,80 @@
+mod common;
+
+use std::{
+    error::Error,
+    io::{Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{body, connect, exchange, start, status};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn length(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let len = request.get_body().len();
+    request.send(&len.to_string())
+}
+
+fn server() -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.set_min_rate(100);
+    server.post("/".to_string(), length);
+    server
+}
+
+const HEAD: &[u8] =
+    b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 200\r\nConnection: close\r\n\r\n";
+
+#[test]
+fn normal_body_is_read() {
+    let (addr, _) = start(server());
+    let mut request = HEAD.to_vec();
+    request.extend_from_slice(&[b'a'; 200]);
+    let response = exchange(addr, &request);
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "200");
+}
+
+#[test]
+fn slow_body_is_aborted() {
+    let (addr, _) = start(server());
+    let mut stream = connect(addr);
+    stream.write_all(HEAD).unwrap();
+    let mut sender = stream.try_clone().unwrap();
+    // About 30 bytes a second, well under the minimum; stops once the
+    // server gives up.
+    thread::spawn(move || {
+        for _ in 0..20 {
+            if sender.write_all(&[b'a'; 10]).is_err() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(300));
+        }
+    });
+    let started = Instant::now();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    assert!(started.elapsed() < Duration::from_secs(4));
+    assert_eq!(status(&response), 408);
+}
+
+/// Takes its time before reading the body, which isn't the client's fault.
+fn slow_handler(request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    thread::sleep(Duration::from_millis(2500));
+    length(request)
+}
+
+#[test]
+fn time_before_the_handler_reads_is_not_counted() {
+    let mut server = HttpServer::new(0);
+    server.set_min_rate(10_000);
+    server.post("/".to_string(), slow_handler);
+    let (addr, _) = start(server);
+    let mut request =
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 20000\r\nConnection: close\r\n\r\n"
+            .to_vec();
+    request.extend_from_slice(&[b'a'; 20000]);
+    let response = exchange(addr, &request);
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "20000");
+}
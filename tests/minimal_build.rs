@@ -0,0 +1,49 @@
+//! The core server with every optional feature left out, as in a build with
+//! `default-features = false`. Only compiled when no feature is enabled.
+#![cfg(not(any(
+    feature = "compression",
+    feature = "digest",
+    feature = "private-cookies",
+    feature = "regex",
+    feature = "sessions",
+    feature = "signed-cookies",
+    feature = "tracing"
+)))]
+
+mod common;
+
+use std::error::Error;
+
+use common::{body, exchange, get, header, start, status};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, Status};
+
+fn hello(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let name = request.param("name").unwrap_or("world").to_string();
+    request.send(&format!("hello {}", name))
+}
+
+fn echo(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut response = HTTPResponse::new(Status::Ok, request.get_body().to_string());
+    response.set_cookie("last", "echo");
+    request.send_response(&response)
+}
+
+#[test]
+fn serves_basic_requests_without_optional_features() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), hello);
+    server.get("/hello/:name".to_string(), hello);
+    server.post("/echo".to_string(), echo);
+    let (addr, _) = start(server);
+
+    assert_eq!(body(&get(addr, "/")), "hello world");
+    assert_eq!(body(&get(addr, "/hello/gin")), "hello gin");
+    assert_eq!(status(&get(addr, "/missing")), 404);
+    let response = exchange(
+        addr,
+        b"POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\nConnection: close\r\n\r\nping",
+    );
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "ping");
+    assert_eq!(header(&response, "set-cookie"), Some("last=echo; Path=/"));
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use std::{error::Error, io::Write, net::Shutdown, thread, time::Duration};
+
+use common::{body, connect, exchange, start, status};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+fn echo_header(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let value = request
+        .get_headers()
+        .get("x-test")
+        .cloned()
+        .unwrap_or_default();
+    request.send(&format!("[{}]", value))
+}
+
+fn dump(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut out = format!("{} {}\n", request.get_method(), request.original_path());
+    for (name, value) in request.original_headers() {
+        out.push_str(&format!("{}: [{}]\n", name, value));
+    }
+    out.push_str(request.get_body());
+    request.send(&out)
+}
+
+fn server() -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), ok);
+    server.get("/echo".to_string(), echo_header);
+    server.get("/dump".to_string(), dump);
+    server.post("/dump".to_string(), dump);
+    server
+}
+
+/// Sends `head` in two writes far enough apart that the server reads it a
+/// line at a time instead of all at once.
+fn exchange_split(addr: std::net::SocketAddr, head: &str) -> String {
+    let (first, rest) = head.split_at(head.find('\n').unwrap() + 1);
+    let mut stream = connect(addr);
+    stream.write_all(first.as_bytes()).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    stream.write_all(rest.as_bytes()).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut out = String::new();
+    let _ = std::io::Read::read_to_string(&mut stream, &mut out);
+    out
+}
+
+/// Sends `head` both in one piece and split across reads.
+fn both_paths(head: &str) -> [String; 2] {
+    let (addr, _) = start(server());
+    [exchange(addr, head.as_bytes()), exchange_split(addr, head)]
+}
+
+#[test]
+fn unknown_method_gets_501() {
+    let (addr, _) = start(server());
+    let response = exchange(addr, b"BREW / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(status(&response), 501);
+}
+
+#[test]
+fn method_that_is_not_a_token_gets_400() {
+    let (addr, _) = start(server());
+    let response = exchange(addr, b"GE(T / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(status(&response), 400);
+}
+
+#[test]
+fn header_value_is_trimmed_without_needing_a_space() {
+    for response in both_paths("GET /echo HTTP/1.1\r\nHost: x\r\nX-Test:\t value \r\n\r\n") {
+        assert_eq!(status(&response), 200);
+        assert_eq!(body(&response), "[value]");
+    }
+}
+
+#[test]
+fn line_without_a_colon_gets_400() {
+    for response in both_paths("GET /echo HTTP/1.1\r\nHost: x\r\nnot a header\r\nX-Test: a\r\n\r\n")
+    {
+        assert_eq!(status(&response), 400);
+    }
+}
+
+#[test]
+fn whitespace_before_the_colon_gets_400() {
+    for response in both_paths("GET /echo HTTP/1.1\r\nHost: x\r\nX-Test : a\r\n\r\n") {
+        assert_eq!(status(&response), 400);
+    }
+}
+
+#[test]
+fn folded_header_line_gets_400() {
+    for response in both_paths("GET /echo HTTP/1.1\r\nHost: x\r\nX-Test: a\r\n b: c\r\n\r\n") {
+        assert_eq!(status(&response), 400);
+    }
+}
+
+fn echo_body(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let body = request.get_body().to_string();
+    request.send(&body)
+}
+
+fn post(raw: &[u8]) -> String {
+    let mut server = server();
+    server.post("/".to_string(), echo_body);
+    let (addr, _) = start(server);
+    exchange(addr, raw)
+}
+
+#[test]
+fn conflicting_content_lengths_get_400() {
+    let response = post(
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\nContent-Length: 10\r\n\r\nhellohello",
+    );
+    assert_eq!(status(&response), 400);
+    let response = post(
+        b"POST / HTTP/1.1\r\nHost: x\r\ncontent-length: 10\r\nContent-Length: 5\r\n\r\nhellohello",
+    );
+    assert_eq!(status(&response), 400);
+}
+
+#[test]
+fn repeated_matching_content_length_is_accepted() {
+    let response = post(
+        b"POST / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+    );
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "hello");
+}
+
+#[test]
+fn buffered_and_line_by_line_heads_give_the_same_request() {
+    let heads = [
+        "GET /dump HTTP/1.1\r\nHost: x\r\n\r\n",
+        "GET /dump?a=1 HTTP/1.1\r\nHost: x\r\nAccept: */*\r\nX-Test:\t spaced \r\nx-test: again\r\n\r\n",
+        "POST /dump HTTP/1.1\nHost: x\nContent-Length: 5\n\nhello",
+    ];
+    for head in heads {
+        let [whole, split] = both_paths(head);
+        assert_eq!(status(&whole), 200, "{:?}", head);
+        assert_eq!(status(&split), 200, "{:?}", head);
+        assert_eq!(body(&whole), body(&split));
+    }
+    let [whole, _] = both_paths(heads[1]);
+    assert_eq!(
+        body(&whole),
+        "GET /dump\nHost: [x]\nAccept: [*/*]\nX-Test: [spaced]\nx-test: [again]\n"
+    );
+}
+
+#[test]
+fn get_and_head_bodies_are_rejected_unless_allowed() {
+    let requests: [&[u8]; 3] = [
+        b"GET /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello",
+        b"GET /echo HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+        b"HEAD / HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello",
+    ];
+    let (addr, _) = start(server());
+    for request in requests {
+        assert_eq!(status(&exchange(addr, request)), 400);
+    }
+    let empty = exchange(
+        addr,
+        b"GET /echo HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\n\r\n",
+    );
+    assert_eq!(status(&empty), 200);
+
+    let mut server = server();
+    server.set_allow_get_bodies(true);
+    let (addr, _) = start(server);
+    for request in requests {
+        assert_eq!(status(&exchange(addr, request)), 200);
+    }
+    let response = exchange(
+        addr,
+        b"GET /dump HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello",
+    );
+    assert!(body(&response).ends_with("hello"));
+}
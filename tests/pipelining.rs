@@ -0,0 +1,74 @@
+mod common;
+
+use std::{error::Error, io::Read, io::Write, net::Shutdown, thread};
+
+use common::{connect, exchange, split_responses, start};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn echo_query(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let target = request.get_target().to_string();
+    request.send(&target)
+}
+
+#[test]
+fn pipelined_requests_are_answered_in_order() {
+    const REQUESTS: usize = 2000;
+    let mut server = HttpServer::new(0);
+    server.set_max_requests_per_connection(REQUESTS + 1);
+    server.get("/".to_string(), echo_query);
+    let (addr, _) = start(server);
+
+    let mut stream = connect(addr);
+    let mut sender = stream.try_clone().unwrap();
+    // Far more than fits in the read buffer, written while responses are
+    // read so neither side's socket buffer fills up for good.
+    let writer = thread::spawn(move || {
+        for i in 0..REQUESTS {
+            let request = format!("GET /?{} HTTP/1.1\r\nHost: x\r\n\r\n", i);
+            sender.write_all(request.as_bytes()).unwrap();
+        }
+        sender.shutdown(Shutdown::Write).unwrap();
+    });
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    writer.join().unwrap();
+
+    let bodies = split_responses(&out)
+        .into_iter()
+        .map(|(_, body)| body.to_string())
+        .collect::<Vec<_>>();
+    let expected = (0..REQUESTS)
+        .map(|i| format!("/?{}", i))
+        .collect::<Vec<_>>();
+    assert_eq!(bodies, expected);
+}
+
+fn echo_body(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text = format!("{} {}", request.get_target(), request.get_body());
+    request.send(&text)
+}
+
+#[test]
+fn requests_past_the_pipelining_limit_are_still_answered_in_order() {
+    let mut server = HttpServer::new(0);
+    server.set_max_pipelined(2);
+    server.get("/".to_string(), echo_body);
+    server.post("/".to_string(), echo_body);
+    let (addr, _) = start(server);
+
+    let requests = [
+        "GET /?0 HTTP/1.1\r\nHost: x\r\n\r\n",
+        "POST /?1 HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhello",
+        "POST /?2 HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+        "GET /?3 HTTP/1.1\r\nHost: x\r\n\r\n",
+    ]
+    .repeat(5)
+    .concat();
+    let out = exchange(addr, requests.as_bytes());
+    let bodies = split_responses(&out)
+        .into_iter()
+        .map(|(_, body)| body.to_string())
+        .collect::<Vec<_>>();
+    let expected = ["/?0 ", "/?1 hello", "/?2 abc", "/?3 "].repeat(5);
+    assert_eq!(bodies, expected);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::{error::Error, sync::Arc, thread, time::Duration};
+
+use common::{body, exchange, get, split_responses, start, start_shared, status, wait_until};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+fn boom(_: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    panic!("handler failed")
+}
+
+#[test]
+fn handler_panic_keeps_the_worker() {
+    let mut server = HttpServer::new(0);
+    server.set_workers(1);
+    server.get("/".to_string(), ok);
+    server.get("/boom".to_string(), boom);
+    let server = Arc::new(server);
+    let addr = start_shared(server.clone());
+    for _ in 0..3 {
+        assert_eq!(exchange(addr, b"GET /boom HTTP/1.1\r\nHost: x\r\n\r\n"), "");
+    }
+    // The only worker is still there to serve this.
+    assert_eq!(status(&get(addr, "/")), 200);
+    wait_until(|| server.pool_stats().active == 0);
+    assert_eq!(server.pool_stats().panicked, 3);
+}
+
+fn slow(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    thread::sleep(Duration::from_millis(500));
+    request.send("done")
+}
+
+#[test]
+fn stats_reflect_a_batch_of_slow_requests() {
+    let mut server = HttpServer::new(0);
+    server.set_workers(2);
+    server.get("/slow".to_string(), slow);
+    let server = Arc::new(server);
+    let addr = start_shared(server.clone());
+    let idle = server.pool_stats();
+    assert_eq!(
+        (idle.workers, idle.active, idle.idle, idle.queue_depth),
+        (2, 0, 2, 0)
+    );
+
+    let clients = (0..5)
+        .map(|_| thread::spawn(move || get(addr, "/slow")))
+        .collect::<Vec<_>>();
+    wait_until(|| server.pool_stats().queue_depth == 3);
+    let busy = server.pool_stats();
+    assert_eq!((busy.active, busy.idle), (2, 0));
+
+    for client in clients {
+        assert_eq!(status(&client.join().unwrap()), 200);
+    }
+    wait_until(|| server.pool_stats().active == 0);
+    let done = server.pool_stats();
+    assert_eq!(done.queue_depth, 0);
+    // Connections are counted as queued until a worker picks them up.
+    assert!((3..=5).contains(&done.peak_queue_depth));
+}
+
+fn echo(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let body = request.get_body().to_string();
+    request.send(&format!("[{}]", body))
+}
+
+#[test]
+fn pooled_buffers_carry_nothing_between_requests() {
+    let mut server = HttpServer::new(0);
+    server.set_workers(1);
+    server.set_buffer_pool(4);
+    server.post("/echo".to_string(), echo);
+    let (addr, _) = start(server);
+    let post = |body: &str| {
+        format!(
+            "POST /echo HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    };
+
+    // Keep-alive requests reuse the connection's buffers, new connections
+    // the pool's.
+    let long = "a".repeat(4096);
+    let pipelined = format!("{}{}{}", post(&long), post("bb"), post(""));
+    let responses = exchange(addr, pipelined.as_bytes());
+    let bodies = split_responses(&responses)
+        .into_iter()
+        .map(|(_, body)| body.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(bodies, [format!("[{}]", long), "[bb]".into(), "[]".into()]);
+    for sent in ["c", "", "dd"] {
+        let response = exchange(addr, post(sent).as_bytes());
+        assert_eq!(body(&response), format!("[{}]", sent));
+    }
+}
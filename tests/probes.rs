@@ -0,0 +1,37 @@
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use common::{body, get, start, status};
+use RustGin::HttpServer;
+
+static CACHE_WARM: AtomicBool = AtomicBool::new(false);
+
+fn database() -> Result<(), String> {
+    Ok(())
+}
+
+fn cache() -> Result<(), String> {
+    if CACHE_WARM.load(Ordering::SeqCst) {
+        Ok(())
+    } else {
+        Err("cache: cold".to_string())
+    }
+}
+
+#[test]
+fn failing_readiness_check_gives_503_while_liveness_stays_200() {
+    let mut server = HttpServer::new(0);
+    server.readiness(vec![database, cache]);
+    let (addr, _) = start(server);
+
+    let ready = get(addr, "/readyz");
+    assert_eq!(status(&ready), 503);
+    assert_eq!(body(&ready), "cache: cold");
+    let live = get(addr, "/livez");
+    assert_eq!(status(&live), 200);
+
+    CACHE_WARM.store(true, Ordering::SeqCst);
+    assert_eq!(status(&get(addr, "/readyz")), 200);
+    assert_eq!(status(&get(addr, "/livez")), 200);
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use std::error::Error;
+
+use common::{body, exchange, split_responses, start};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn describe(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let peer = request
+        .peer_addr()
+        .map(|ip| ip.to_string())
+        .unwrap_or_default();
+    let text = format!("{} {} {}", peer, request.scheme(), request.host());
+    request.send(&text)
+}
+
+fn server(trusted: &[&str]) -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.trust_proxies(trusted);
+    server.get("/".to_string(), describe);
+    server
+}
+
+fn request(trusted: &[&str], headers: &str) -> String {
+    let (addr, _) = start(server(trusted));
+    let response = exchange(
+        addr,
+        format!(
+            "GET / HTTP/1.1\r\nHost: origin.example\r\n{}Connection: close\r\n\r\n",
+            headers
+        )
+        .as_bytes(),
+    );
+    body(&response).to_string()
+}
+
+#[test]
+fn forwarding_headers_from_untrusted_peers_are_ignored() {
+    let headers = "X-Forwarded-For: 1.2.3.4\r\nX-Forwarded-Proto: https\r\nX-Forwarded-Host: real.example\r\n";
+    assert_eq!(request(&[], headers), "127.0.0.1 http origin.example");
+}
+
+#[test]
+fn forwarding_headers_apply_only_from_peers_in_a_trusted_range() {
+    let headers = "X-Forwarded-For: 1.2.3.4\r\nX-Forwarded-Proto: https\r\nX-Forwarded-Host: real.example\r\n";
+    for trusted in [
+        &["127.0.0.0/8"][..],
+        &["10.0.0.0/8", "127.0.0.1/32"],
+        &["::ffff:127.0.0.1/128"],
+    ] {
+        assert_eq!(
+            request(trusted, headers),
+            "1.2.3.4 https real.example",
+            "{:?}",
+            trusted
+        );
+    }
+    for untrusted in [&["10.0.0.0/8"][..], &["127.0.0.2"], &["::1/128"]] {
+        assert_eq!(
+            request(untrusted, headers),
+            "127.0.0.1 http origin.example",
+            "{:?}",
+            untrusted
+        );
+    }
+}
+
+#[test]
+fn x_forwarded_values_come_from_the_clients_entry() {
+    // The client sent the first value of each itself; the proxy appended
+    // the second.
+    let headers = "X-Forwarded-For: 6.6.6.6, 1.2.3.4\r\n\
+                   X-Forwarded-Proto: https, http\r\n\
+                   X-Forwarded-Host: evil.example, real.example\r\n";
+    assert_eq!(
+        request(&["127.0.0.1"], headers),
+        "1.2.3.4 http real.example"
+    );
+    // Trusting the client's address too reaches the entry before it.
+    assert_eq!(
+        request(&["127.0.0.1", "1.2.3.4"], headers),
+        "6.6.6.6 https evil.example"
+    );
+}
+
+#[test]
+fn single_proxy_values_apply_to_the_client() {
+    let headers = "X-Forwarded-For: 1.2.3.4\r\nX-Forwarded-Proto: https\r\nX-Forwarded-Host: real.example\r\n";
+    assert_eq!(
+        request(&["127.0.0.1"], headers),
+        "1.2.3.4 https real.example"
+    );
+}
+
+#[test]
+fn forwarded_values_come_from_the_clients_element() {
+    let headers = "Forwarded: for=6.6.6.6;proto=https;host=evil.example, \
+                   for=1.2.3.4;proto=http;host=real.example\r\n";
+    assert_eq!(
+        request(&["127.0.0.1"], headers),
+        "1.2.3.4 http real.example"
+    );
+    assert_eq!(
+        request(&["127.0.0.1", "1.2.3.4"], headers),
+        "6.6.6.6 https evil.example"
+    );
+}
+
+#[test]
+fn forwarded_is_preferred_over_x_forwarded() {
+    let headers = "Forwarded: for=1.2.3.4;proto=https;host=real.example\r\n\
+                   X-Forwarded-For: 5.5.5.5\r\nX-Forwarded-Host: other.example\r\n";
+    assert_eq!(
+        request(&["127.0.0.1"], headers),
+        "1.2.3.4 https real.example"
+    );
+}
+
+fn forward_headers(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let mut text: String = request
+        .original_headers()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
+    text.push_str(&format!(
+        "[{:?}]",
+        request.get_headers().get("x-custom-header")
+    ));
+    request.send(&text)
+}
+
+#[test]
+fn original_headers_keep_their_casing_order_and_repeats() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), forward_headers);
+    let (addr, _) = start(server);
+    let headers = "host: x\r\nX-Custom-Header: one\r\nx-ALL-caps: two\r\nX-Custom-Header: three\r\nConnection: close\r\n";
+    let response = exchange(
+        addr,
+        format!("GET / HTTP/1.1\r\n{}\r\n", headers).as_bytes(),
+    );
+    assert_eq!(body(&response), format!("{}[Some(\"three\")]", headers));
+}
+
+fn forwardable(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text: String = request
+        .forwardable_headers()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
+    request.send(&text)
+}
+
+#[test]
+fn hop_by_hop_headers_and_those_named_in_connection_are_not_forwarded() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), forwardable);
+    let (addr, _) = start(server);
+    let raw = exchange(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: x\r\nConnection: keep-alive, X-Hop\r\nX-Hop: 1\r\n\
+          Keep-Alive: timeout=5\r\nTE: trailers\r\nProxy-Authorization: Basic eA==\r\n\
+          X-Kept: 2\r\n\r\n\
+          GET / HTTP/1.1\r\nHost: y\r\nconnection: Upgrade,\tclose\r\nUpgrade: websocket\r\n\
+          x-hop: 3\r\n\r\n",
+    );
+    let responses = split_responses(&raw);
+    let bodies: Vec<&str> = responses.iter().map(|(_, body)| *body).collect();
+    // The first request's keep-alive was honoured, so the second was read,
+    // and the `close` among the second's tokens was too.
+    assert_eq!(
+        bodies,
+        ["Host: x\r\nX-Kept: 2\r\n", "Host: y\r\nx-hop: 3\r\n"]
+    );
+    assert!(responses[1].0.contains("\r\nConnection: close"));
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use std::error::Error;
+
+use common::{exchange, split_responses, start};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn describe(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let padding = request
+        .get_headers()
+        .get("x-padding")
+        .map_or(0, String::len);
+    let body = request.get_body().to_string();
+    let text = format!("{} {} {}", request.get_target(), padding, body);
+    request.send(&text)
+}
+
+/// A mix of requests bigger and smaller than the buffers tried: a large
+/// header, a Content-Length body, a chunked body, all pipelined.
+fn requests() -> String {
+    let body = "b".repeat(20_000);
+    format!(
+        "GET /large-header HTTP/1.1\r\nHost: x\r\nX-Padding: {}\r\n\r\n\
+         POST /sized HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n{}\
+         POST /chunked HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n\
+         GET /last HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        "p".repeat(10_000),
+        body.len(),
+        body
+    )
+}
+
+fn bodies(buffer_size: Option<usize>) -> Vec<String> {
+    let mut server = HttpServer::new(0);
+    if let Some(size) = buffer_size {
+        server.set_read_buffer_size(size);
+    }
+    for path in ["/large-header", "/last"] {
+        server.get(path.to_string(), describe);
+    }
+    for path in ["/sized", "/chunked"] {
+        server.post(path.to_string(), describe);
+    }
+    let (addr, _) = start(server);
+    let raw = exchange(addr, requests().as_bytes());
+    split_responses(&raw)
+        .into_iter()
+        .map(|(_, body)| body.to_string())
+        .collect()
+}
+
+#[test]
+fn requests_are_read_the_same_with_any_buffer_size() {
+    let expected = bodies(None);
+    assert_eq!(
+        expected,
+        [
+            "/large-header 10000 ".to_string(),
+            format!("/sized 0 {}", "b".repeat(20_000)),
+            "/chunked 0 abcde".to_string(),
+            "/last 0 ".to_string(),
+        ]
+    );
+    for size in [1, 7, 512, 64 * 1024] {
+        assert_eq!(bodies(Some(size)), expected, "buffer of {} bytes", size);
+    }
+}
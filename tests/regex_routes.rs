@@ -0,0 +1,40 @@
+#![cfg(feature = "regex")]
+
+mod common;
+
+use std::error::Error;
+
+use common::{body, get, start, status};
+use RustGin::{HTTPRequest, HttpServer, Method};
+
+fn item(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let id = request.param("id").unwrap_or_default().to_string();
+    request.send(&format!("item {}", id))
+}
+
+fn named(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let name = request.param("name").unwrap_or_default().to_string();
+    request.send(&format!("named {}", name))
+}
+
+#[test]
+fn numeric_id_matches_and_alphabetic_falls_through_to_404() {
+    let mut server = HttpServer::new(0);
+    server.route_regex(r"^/items/(?P<id>\d+)$", Method::GET, item);
+    let (addr, _) = start(server);
+
+    let response = get(addr, "/items/42");
+    assert_eq!(status(&response), 200);
+    assert_eq!(body(&response), "item 42");
+    assert_eq!(status(&get(addr, "/items/abc")), 404);
+    assert_eq!(status(&get(addr, "/items/42/more")), 404);
+}
+
+#[test]
+fn path_routes_are_tried_before_regex_routes() {
+    let mut server = HttpServer::new(0);
+    server.route_regex(r"^/items/(?P<id>\w+)$", Method::GET, item);
+    server.get("/items/:name".to_string(), named);
+    let (addr, _) = start(server);
+    assert_eq!(body(&get(addr, "/items/7")), "named 7");
+}
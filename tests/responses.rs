@@ -0,0 +1,234 @@
+mod common;
+
+use std::{error::Error, sync::OnceLock};
+
+use common::{body, exchange, get, header, split_responses, start, status};
+use RustGin::{FrozenResponse, HTTPRequest, HTTPResponse, HttpServer, ResponseAlreadySent, Status};
+
+fn serialized(response: &HTTPResponse) -> String {
+    String::from_utf8(response.to_bytes()).unwrap()
+}
+
+#[test]
+fn attachment_with_an_ascii_filename() {
+    let mut response = HTTPResponse::new(Status::Ok, "a,b".to_string());
+    response.attachment("report \"final\".csv");
+    assert_eq!(
+        header(&serialized(&response), "content-disposition"),
+        Some(r#"attachment; filename="report \"final\".csv""#)
+    );
+}
+
+#[test]
+fn attachment_with_a_unicode_filename() {
+    let mut response = HTTPResponse::new(Status::Ok, "a,b".to_string());
+    response.attachment("résumé €.pdf");
+    assert_eq!(
+        header(&serialized(&response), "content-disposition"),
+        Some("attachment; filename=\"r_sum_ _.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9%20%E2%82%AC.pdf")
+    );
+}
+
+fn sends_then_fails(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("first")?;
+    let second = request.send("second");
+    assert!(second.unwrap_err().is::<ResponseAlreadySent>());
+    Err("handler failed after responding".into())
+}
+
+#[test]
+fn a_handler_that_already_responded_gets_nothing_appended() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), sends_then_fails);
+    let (addr, _) = start(server);
+    let raw = exchange(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: x\r\n\r\nGET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    let responses = split_responses(&raw);
+    assert_eq!(responses.len(), 2, "{:?}", raw);
+    for (head, body) in responses {
+        assert_eq!(status(head), 200);
+        assert_eq!(body, "first");
+    }
+}
+
+fn health() -> HTTPResponse {
+    let mut response = HTTPResponse::new(Status::Ok, r#"{"status":"ok"}"#.to_string());
+    response.add_header("Content-Type".to_string(), "application/json".to_string());
+    response.add_header("Cache-Control".to_string(), "no-store".to_string());
+    response.set_cookie("seen", "1");
+    response
+}
+
+fn dynamic(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send_response(&health())
+}
+
+fn frozen(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    static FROZEN: OnceLock<FrozenResponse> = OnceLock::new();
+    request.send_frozen(FROZEN.get_or_init(|| health().freeze()))
+}
+
+#[test]
+fn frozen_response_is_byte_identical_to_the_dynamic_one() {
+    let mut server = HttpServer::new(0);
+    server.get("/dynamic".to_string(), dynamic);
+    server.get("/frozen".to_string(), frozen);
+    let (addr, _) = start(server);
+    for connection in ["keep-alive", "close"] {
+        let request = |path: &str| {
+            format!(
+                "GET {} HTTP/1.1\r\nHost: x\r\nConnection: {}\r\n\r\n",
+                path, connection
+            )
+        };
+        let dynamic = exchange(addr, request("/dynamic").as_bytes());
+        let frozen = exchange(addr, request("/frozen").as_bytes());
+        assert_eq!(status(&dynamic), 200);
+        assert_eq!(frozen, dynamic);
+    }
+    let mut server = HttpServer::new(0);
+    server.get("/frozen".to_string(), frozen);
+    let (addr, _) = start(server);
+    let raw = exchange(
+        addr,
+        b"GET /frozen HTTP/1.1\r\nHost: x\r\n\r\nGET /frozen HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    let body = String::from_utf8(health().to_bytes()).unwrap();
+    let body = body.split_once("\r\n\r\n").unwrap().1;
+    assert_eq!(
+        split_responses(&raw)
+            .into_iter()
+            .map(|(_, body)| body)
+            .collect::<Vec<_>>(),
+        [body, body]
+    );
+}
+
+#[test]
+fn binary_response_serializes_to_exact_bytes() {
+    let mut response = HTTPResponse::from_bytes(Status::Ok, vec![0, 0xff, b'\r', b'\n', 0x80]);
+    response.add_header(
+        "Content-Type".to_string(),
+        "application/octet-stream".to_string(),
+    );
+    response.add_header("X-One".to_string(), "1".to_string());
+    response.set_cookie("a", "b");
+    let mut expected = b"HTTP/1.1 200 OK\r\n\
+        Content-Length: 5\r\n\
+        Content-Type: application/octet-stream\r\n\
+        X-One: 1\r\n\
+        Set-Cookie: a=b; Path=/\r\n\
+        \r\n"
+        .to_vec();
+    expected.extend_from_slice(&[0, 0xff, b'\r', b'\n', 0x80]);
+    assert_eq!(response.to_bytes(), expected);
+}
+
+#[test]
+fn redirect_helpers_set_status_and_location() {
+    for (response, code, reason) in [
+        (
+            HTTPResponse::redirect_permanent("/new"),
+            301,
+            "Moved Permanently",
+        ),
+        (HTTPResponse::redirect_temporary("/new"), 302, "Found"),
+        (
+            HTTPResponse::redirect_temporary_preserve("/new"),
+            307,
+            "Temporary Redirect",
+        ),
+        (
+            HTTPResponse::redirect_permanent_preserve("/new"),
+            308,
+            "Permanent Redirect",
+        ),
+    ] {
+        let raw = serialized(&response);
+        assert!(
+            raw.starts_with(&format!("HTTP/1.1 {} {}\r\n", code, reason)),
+            "{}",
+            raw
+        );
+        assert_eq!(header(&raw, "location"), Some("/new"), "{}", raw);
+    }
+}
+
+#[test]
+fn render_substitutes_escaped_values() {
+    let response = HTTPResponse::render(
+        "<h1>{name}</h1><p title=\"{name}\">{!note} {missing}</p>",
+        &[("name", "<Tom & \"Jerry's\">"), ("note", "<b>raw</b>")],
+    );
+    let raw = serialized(&response);
+    assert!(raw.starts_with("HTTP/1.1 200 OK\r\n"), "{}", raw);
+    assert_eq!(
+        header(&raw, "content-type"),
+        Some("text/html; charset=utf-8")
+    );
+    assert_eq!(
+        raw.split_once("\r\n\r\n").unwrap().1,
+        "<h1>&lt;Tom &amp; &quot;Jerry&#39;s&quot;&gt;</h1>\
+         <p title=\"&lt;Tom &amp; &quot;Jerry&#39;s&quot;&gt;\"><b>raw</b> {missing}</p>"
+    );
+}
+
+fn needs_tls(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send_response(&HTTPResponse::upgrade_required("TLS/1.2, HTTP/1.1"))
+}
+
+#[test]
+fn upgrade_required_names_the_protocol() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), needs_tls);
+    let (addr, _) = start(server);
+    let raw = get(addr, "/");
+    assert!(
+        raw.starts_with("HTTP/1.1 426 Upgrade Required\r\n"),
+        "{}",
+        raw
+    );
+    assert_eq!(header(&raw, "upgrade"), Some("TLS/1.2, HTTP/1.1"));
+    assert_eq!(header(&raw, "connection"), Some("Upgrade"));
+}
+
+fn plain(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("hello")
+}
+
+fn json(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send_json("{}")
+}
+
+#[test]
+fn responses_without_a_content_type_get_the_default() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), plain);
+    let (addr, _) = start(server);
+    assert_eq!(
+        header(&get(addr, "/"), "content-type"),
+        Some("text/plain; charset=utf-8")
+    );
+
+    let mut server = HttpServer::new(0);
+    server.set_default_content_type("text/html; charset=iso-8859-1");
+    server.get("/".to_string(), plain);
+    server.get("/json".to_string(), json);
+    let (addr, _) = start(server);
+    let response = get(addr, "/");
+    assert_eq!(body(&response), "hello");
+    assert_eq!(
+        header(&response, "content-type"),
+        Some("text/html; charset=iso-8859-1")
+    );
+    assert_eq!(
+        header(&get(addr, "/missing"), "content-type"),
+        Some("text/html; charset=iso-8859-1")
+    );
+    assert_eq!(
+        header(&get(addr, "/json"), "content-type"),
+        Some("application/json")
+    );
+}
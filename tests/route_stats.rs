@@ -0,0 +1,62 @@
+mod common;
+
+use std::{error::Error, sync::Arc};
+
+use common::{exchange, start_shared, wait_until};
+use RustGin::{HTTPRequest, HttpServer, RouteStats};
+
+fn post(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let id = request.param("id").unwrap_or_default().to_string();
+    request.send(&format!("post {}", id))
+}
+
+fn upload(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let len = request.get_body().len();
+    request.send(&format!("stored {} bytes", len))
+}
+
+#[test]
+fn bytes_are_attributed_to_the_matched_route_template() {
+    let mut server = HttpServer::new(0);
+    server.get("/posts/:id".to_string(), post);
+    server.post("/upload".to_string(), upload);
+    let server = Arc::new(server);
+    let addr = start_shared(server.clone());
+
+    let mut expected = [RouteStats::default(); 2];
+    let mut send = |route: usize, raw: String| {
+        let response = exchange(addr, raw.as_bytes());
+        expected[route].requests += 1;
+        expected[route].bytes_received += raw.len() as u64;
+        expected[route].bytes_sent += response.len() as u64;
+    };
+    for id in [1, 22, 333] {
+        send(
+            0,
+            format!(
+                "GET /posts/{} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                id
+            ),
+        );
+    }
+    for len in [10, 5000] {
+        send(
+            1,
+            format!(
+                "POST /upload HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                len,
+                "u".repeat(len)
+            ),
+        );
+    }
+    exchange(
+        addr,
+        b"GET /elsewhere HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+
+    wait_until(|| server.route_stats()["POST /upload"].requests == 2);
+    let stats = server.route_stats();
+    assert_eq!(stats.len(), 2, "{:?}", stats);
+    assert_eq!(stats["GET /posts/:id"], expected[0]);
+    assert_eq!(stats["POST /upload"], expected[1]);
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use std::error::Error;
+
+use common::{body, exchange, get, header, start, status};
+use RustGin::{FieldError, HTTPRequest, HttpServer, Method};
+
+fn page(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let n = format!("{:?}", request.param("n"));
+    request.send(&n)
+}
+
+#[test]
+fn optional_group_matches_short_and_long_forms() {
+    let mut server = HttpServer::new(0);
+    server.get("/posts(/page/:n)?".to_string(), page);
+    let (addr, _) = start(server);
+
+    let short = get(addr, "/posts");
+    assert_eq!(status(&short), 200);
+    assert_eq!(body(&short), "None");
+
+    let long = get(addr, "/posts/page/3");
+    assert_eq!(status(&long), 200);
+    assert_eq!(body(&long), "Some(\"3\")");
+
+    assert_eq!(status(&get(addr, "/posts/page")), 404);
+    assert_eq!(status(&get(addr, "/posts/page/3/4")), 404);
+}
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+#[test]
+fn globally_disallowed_method_gets_405_on_any_path() {
+    let mut server = HttpServer::new(0);
+    server.allowed_methods(&[Method::GET, Method::POST]);
+    server.get("/".to_string(), ok);
+    server.add_method(Method::PUT, "/items".to_string(), ok);
+    let (addr, _) = start(server);
+
+    for path in ["/items", "/", "/missing"] {
+        let response = exchange(
+            addr,
+            format!(
+                "PUT {} HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                path
+            )
+            .as_bytes(),
+        );
+        assert_eq!(status(&response), 405, "{}", path);
+        assert_eq!(
+            header(&response, "allow"),
+            Some("GET, HEAD, POST"),
+            "{}",
+            path
+        );
+    }
+    assert_eq!(status(&get(addr, "/")), 200);
+    let head = exchange(
+        addr,
+        b"HEAD / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(status(&head), 200);
+}
+
+fn paths(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text = format!("{} {}", request.path(), request.original_path());
+    request.send(&text)
+}
+
+#[test]
+fn mount_sees_the_path_below_its_prefix() {
+    let mut server = HttpServer::new(0);
+    server.mount_prefix("/admin", paths);
+    server.get("/administrator".to_string(), ok);
+    let (addr, _) = start(server);
+
+    for (target, expected) in [
+        ("/admin/users", "/users /admin/users"),
+        ("/admin/users/7?tab=roles", "/users/7 /admin/users/7"),
+        ("/admin", "/ /admin"),
+        ("/admin/", "/ /admin/"),
+    ] {
+        let response = get(addr, target);
+        assert_eq!(status(&response), 200, "{}", target);
+        assert_eq!(body(&response), expected, "{}", target);
+    }
+    // A prefix only matches whole segments.
+    assert_eq!(body(&get(addr, "/administrator")), "ok");
+    assert_eq!(status(&get(addr, "/adminx")), 404);
+}
+
+fn create_user(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("created")
+}
+
+fn validate_user(request: &HTTPRequest) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let body = request.get_body();
+    let field = |name: &str| {
+        body.split('&')
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+            .unwrap_or_default()
+    };
+    if field("name").is_empty() {
+        errors.push(FieldError::new("name", "is required"));
+    }
+    if field("age").parse::<u8>().is_err() {
+        errors.push(FieldError::new("age", "must be a \"number\""));
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[test]
+fn failed_validation_gives_422_with_the_field_errors() {
+    let mut server = HttpServer::new(0);
+    server
+        .post("/users".to_string(), create_user)
+        .validate(validate_user);
+    let (addr, _) = start(server);
+    let post = |body: &str| {
+        let request = format!(
+            "POST /users HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        exchange(addr, request.as_bytes())
+    };
+
+    let rejected = post("name=&age=old");
+    assert_eq!(status(&rejected), 422);
+    assert_eq!(header(&rejected, "content-type"), Some("application/json"));
+    assert_eq!(
+        body(&rejected),
+        r#"{"errors": [{"field": "name", "message": "is required"}, {"field": "age", "message": "must be a \"number\""}]}"#
+    );
+
+    let accepted = post("name=ada&age=36");
+    assert_eq!(status(&accepted), 200);
+    assert_eq!(body(&accepted), "created");
+}
@@ -0,0 +1,133 @@
+#![cfg(feature = "sessions")]
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::{body, exchange, header, start};
+use RustGin::{HTTPRequest, HttpServer, SessionConfig, SessionData, SessionStore};
+
+fn login(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.session().unwrap().set("user", "alice");
+    request.send("logged in")
+}
+
+fn whoami(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let user = format!("{:?}", request.session().unwrap().get("user"));
+    request.send(&user)
+}
+
+fn logout(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.session().unwrap().remove("user");
+    request.send("logged out")
+}
+
+fn server() -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.get("/login".to_string(), login);
+    server.get("/whoami".to_string(), whoami);
+    server.get("/logout".to_string(), logout);
+    server
+}
+
+fn config() -> SessionConfig {
+    SessionConfig {
+        secret: b"a secret of reasonable length".to_vec(),
+        ttl: Duration::from_secs(60),
+    }
+}
+
+/// Requests `path` sending `cookie`, returning the response.
+fn get_with_cookie(addr: SocketAddr, path: &str, cookie: Option<&str>) -> String {
+    let cookie = cookie.map_or(String::new(), |cookie| format!("Cookie: {}\r\n", cookie));
+    exchange(
+        addr,
+        format!(
+            "GET {} HTTP/1.1\r\nHost: x\r\n{}Connection: close\r\n\r\n",
+            path, cookie
+        )
+        .as_bytes(),
+    )
+}
+
+/// The `name=value` pair of the response's `Set-Cookie` header.
+fn session_cookie(response: &str) -> &str {
+    header(response, "set-cookie")
+        .and_then(|cookie| cookie.split(';').next())
+        .unwrap_or_else(|| panic!("no session cookie in {:?}", response))
+}
+
+#[test]
+fn data_set_in_one_request_is_read_in_the_next() {
+    let mut server = server();
+    server.sessions(config());
+    let (addr, _) = start(server);
+
+    let response = get_with_cookie(addr, "/login", None);
+    let cookie = session_cookie(&response).to_string();
+    assert!(cookie.starts_with("rustgin_session="));
+
+    let response = get_with_cookie(addr, "/whoami", Some(&cookie));
+    assert_eq!(body(&response), "Some(\"alice\")");
+    assert_eq!(header(&response, "set-cookie"), None);
+
+    assert_eq!(body(&get_with_cookie(addr, "/whoami", None)), "None");
+    let forged = format!("{}0", cookie);
+    assert_eq!(
+        body(&get_with_cookie(addr, "/whoami", Some(&forged))),
+        "None"
+    );
+
+    let response = get_with_cookie(addr, "/logout", Some(&cookie));
+    assert_eq!(session_cookie(&response), "rustgin_session=");
+    assert_eq!(
+        body(&get_with_cookie(addr, "/whoami", Some(&cookie))),
+        "None"
+    );
+}
+
+#[derive(Clone, Default)]
+struct SharedStore(Arc<Mutex<HashMap<String, SessionData>>>);
+
+impl SessionStore for SharedStore {
+    fn load(&self, id: &str) -> Option<SessionData> {
+        self.0.lock().unwrap().get(id).cloned()
+    }
+
+    fn save(&self, id: &str, data: &SessionData, _ttl: Duration) {
+        self.0.lock().unwrap().insert(id.to_string(), data.clone());
+    }
+
+    fn remove(&self, id: &str) {
+        self.0.lock().unwrap().remove(id);
+    }
+}
+
+#[test]
+fn sessions_can_be_kept_in_another_store() {
+    let store = SharedStore::default();
+    let mut server = server();
+    server.sessions(config()).store(store.clone());
+    let (addr, _) = start(server);
+
+    let cookie = session_cookie(&get_with_cookie(addr, "/login", None)).to_string();
+    let sessions = store.0.lock().unwrap().clone();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(
+        sessions
+            .values()
+            .next()
+            .unwrap()
+            .get("user")
+            .map(String::as_str),
+        Some("alice")
+    );
+    let response = get_with_cookie(addr, "/whoami", Some(&cookie));
+    assert_eq!(body(&response), "Some(\"alice\")");
+}
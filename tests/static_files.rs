@@ -0,0 +1,295 @@
+mod common;
+
+use std::{collections::HashMap, error::Error, fs, net::SocketAddr, path::PathBuf, sync::OnceLock};
+
+use common::{body, exchange_bytes, header, start, status, temp_dir};
+use RustGin::{EmbeddedAsset, HTTPRequest, HTTPResponse, HttpServer};
+
+fn get_with(addr: SocketAddr, path: &str, headers: &str) -> Vec<u8> {
+    exchange_bytes(
+        addr,
+        format!(
+            "GET {} HTTP/1.1\r\nHost: x\r\n{}Connection: close\r\n\r\n",
+            path, headers
+        )
+        .as_bytes(),
+    )
+}
+
+/// The head of `response` as text and its body as bytes.
+fn split(response: &[u8]) -> (String, Vec<u8>) {
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .expect("no end of head")
+        + 4;
+    (
+        String::from_utf8_lossy(&response[..end]).into_owned(),
+        response[end..].to_vec(),
+    )
+}
+
+#[test]
+fn serves_files_under_the_prefix() {
+    let root = temp_dir("static-serve");
+    fs::write(root.join("hello.txt"), "hello").unwrap();
+    let mut server = HttpServer::new(0);
+    server.static_dir("/assets".to_string(), &root);
+    let (addr, _) = start(server);
+
+    let (head, data) = split(&get_with(addr, "/assets/hello.txt", ""));
+    assert_eq!(status(&head), 200);
+    assert_eq!(
+        header(&head, "content-type"),
+        Some("text/plain; charset=utf-8")
+    );
+    assert_eq!(data, b"hello");
+    let response = String::from_utf8(get_with(addr, "/assets/../Cargo.toml", "")).unwrap();
+    assert_eq!(status(&response), 404);
+    assert!(body(&response).len() < 100);
+}
+
+#[test]
+fn unsatisfiable_range_gets_416_with_the_size() {
+    let root = temp_dir("static-range");
+    let data: Vec<u8> = (0..100).collect();
+    fs::write(root.join("data.bin"), &data).unwrap();
+    let mut server = HttpServer::new(0);
+    server.static_dir("/files".to_string(), &root);
+    let (addr, _) = start(server);
+
+    for range in ["bytes=99999-", "bytes=100-", "bytes=100-200"] {
+        let response = get_with(addr, "/files/data.bin", &format!("Range: {}\r\n", range));
+        let (head, _) = split(&response);
+        assert_eq!(status(&head), 416, "{}", range);
+        assert_eq!(
+            header(&head, "content-range"),
+            Some("bytes */100"),
+            "{}",
+            range
+        );
+    }
+
+    let (head, body) = split(&get_with(addr, "/files/data.bin", "Range: bytes=90-\r\n"));
+    assert_eq!(status(&head), 206);
+    assert_eq!(header(&head, "content-range"), Some("bytes 90-99/100"));
+    assert_eq!(body, &data[90..]);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_copies_are_cached_until_the_file_changes() {
+    use std::{
+        fs::File,
+        io::{Read, Write},
+        path::Path,
+        time::{Duration, SystemTime},
+    };
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(time).unwrap();
+    }
+
+    fn gunzip(data: &[u8]) -> String {
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(data)
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    let root = temp_dir("static-compress");
+    let cache = root.join("cache");
+    let source = root.join("public").join("site.css");
+    fs::create_dir_all(source.parent().unwrap()).unwrap();
+    fs::write(&source, "body { color: red }").unwrap();
+    let mut server = HttpServer::new(0);
+    server
+        .static_dir("/".to_string(), root.join("public"))
+        .compress_cache(&cache);
+    let (addr, _) = start(server);
+    let gzip = "Accept-Encoding: gzip\r\n";
+
+    let (head, data) = split(&get_with(addr, "/site.css", gzip));
+    assert_eq!(header(&head, "content-encoding"), Some("gzip"));
+    assert_eq!(gunzip(&data), "body { color: red }");
+    let cached = cache.join("site.css.gz");
+    assert!(cached.is_file());
+
+    // Swap the cached copy for a marker: the next response comes from it.
+    let mut marker = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    marker.write_all(b"from the cache").unwrap();
+    let marker = marker.finish().unwrap();
+    fs::write(&cached, marker).unwrap();
+    set_modified(&cached, fs::metadata(&source).unwrap().modified().unwrap());
+    let (_, data) = split(&get_with(addr, "/site.css", gzip));
+    assert_eq!(gunzip(&data), "from the cache");
+
+    // A new version of the file replaces the cached copy.
+    fs::write(&source, "body { color: blue }").unwrap();
+    set_modified(&source, SystemTime::now() + Duration::from_secs(10));
+    let (_, data) = split(&get_with(addr, "/site.css", gzip));
+    assert_eq!(gunzip(&data), "body { color: blue }");
+
+    // Clients that don't accept gzip get the file as it is.
+    let (head, data) = split(&get_with(addr, "/site.css", ""));
+    assert_eq!(header(&head, "content-encoding"), None);
+    assert_eq!(data, b"body { color: blue }");
+}
+
+/// The file `report` serves.
+static REPORT: OnceLock<PathBuf> = OnceLock::new();
+
+fn report(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let response = HTTPResponse::conditional_file(&request, REPORT.get().unwrap())?;
+    request.send_response(&response)
+}
+
+#[test]
+fn conditional_file_gives_304_only_when_the_copy_is_current() {
+    let root = temp_dir("conditional-file");
+    let path = root.join("report.txt");
+    fs::write(&path, "a,b\n1,2\n").unwrap();
+    REPORT.set(path).unwrap();
+    let mut server = HttpServer::new(0);
+    server.get("/report".to_string(), report);
+    let (addr, _) = start(server);
+
+    let (head, data) = split(&get_with(addr, "/report", ""));
+    assert_eq!(status(&head), 200);
+    assert_eq!(data, b"a,b\n1,2\n");
+    assert_eq!(
+        header(&head, "content-type"),
+        Some("text/plain; charset=utf-8")
+    );
+    let etag = header(&head, "etag").unwrap().to_string();
+    let modified = header(&head, "last-modified").unwrap().to_string();
+
+    for validator in [
+        format!("If-None-Match: {}\r\n", etag),
+        format!("If-Modified-Since: {}\r\n", modified),
+    ] {
+        let (head, data) = split(&get_with(addr, "/report", &validator));
+        assert_eq!(status(&head), 304, "{}", validator);
+        assert_eq!(header(&head, "etag"), Some(etag.as_str()));
+        assert!(data.is_empty());
+    }
+
+    let (head, data) = split(&get_with(addr, "/report", "If-None-Match: \"stale\"\r\n"));
+    assert_eq!(status(&head), 200);
+    assert_eq!(data, b"a,b\n1,2\n");
+    assert_eq!(header(&head, "etag"), Some(etag.as_str()));
+    assert_eq!(header(&head, "last-modified"), Some(modified.as_str()));
+}
+
+#[test]
+fn closed_open_and_suffix_ranges_of_a_known_size_file() {
+    let root = temp_dir("static-range-forms");
+    let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+    fs::write(root.join("data.bin"), &data).unwrap();
+    let mut server = HttpServer::new(0);
+    server.static_dir("/files".to_string(), &root);
+    let (addr, _) = start(server);
+
+    for (range, content_range, expected) in [
+        ("bytes=0-99", "bytes 0-99/1000", &data[..100]),
+        ("bytes=100-", "bytes 100-999/1000", &data[100..]),
+        ("bytes=-50", "bytes 950-999/1000", &data[950..]),
+        ("bytes=-5000", "bytes 0-999/1000", &data[..]),
+    ] {
+        let response = get_with(addr, "/files/data.bin", &format!("Range: {}\r\n", range));
+        let (head, body) = split(&response);
+        assert_eq!(status(&head), 206, "{}", range);
+        assert_eq!(header(&head, "content-range"), Some(content_range));
+        assert_eq!(
+            header(&head, "content-length"),
+            Some(expected.len().to_string().as_str())
+        );
+        assert_eq!(body, expected, "{}", range);
+    }
+}
+
+#[test]
+fn multiple_ranges_are_parts_of_a_multipart_body() {
+    let root = temp_dir("static-multi-range");
+    let data: Vec<u8> = (0..=255).cycle().take(1 << 20).collect();
+    fs::write(root.join("data.bin"), &data).unwrap();
+    let mut server = HttpServer::new(0);
+    server.static_dir("/files".to_string(), &root);
+    let (addr, _) = start(server);
+
+    let response = get_with(addr, "/files/data.bin", "Range: bytes=10-19,-5\r\n");
+    let (head, body) = split(&response);
+    assert_eq!(status(&head), 206);
+    let boundary = header(&head, "content-type")
+        .and_then(|value| value.strip_prefix("multipart/byteranges; boundary="))
+        .expect("no multipart content type");
+    let mut expected = Vec::new();
+    for (start, end) in [(10, 19), ((1 << 20) - 5, (1 << 20) - 1)] {
+        expected.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                boundary,
+                start,
+                end,
+                1 << 20
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(&data[start..=end]);
+        expected.extend_from_slice(b"\r\n");
+    }
+    expected.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    assert_eq!(body, expected);
+}
+
+/// FNV-1a, the hash embedded asset `ETag`s are made from.
+fn fnv1a(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("\"{:016x}\"", hash)
+}
+
+#[test]
+fn embedded_assets_are_served_with_a_content_hash_etag() {
+    const CSS: &[u8] = b"body { color: red; }\n";
+    const JS: &[u8] = b"console.log(1);\n";
+    let mut server = HttpServer::new(0);
+    server.embedded_assets(HashMap::from([
+        (
+            "/site.css".to_string(),
+            EmbeddedAsset {
+                bytes: CSS,
+                content_type: "text/css",
+            },
+        ),
+        (
+            "/app.js".to_string(),
+            EmbeddedAsset {
+                bytes: JS,
+                content_type: "text/javascript",
+            },
+        ),
+    ]));
+    let (addr, _) = start(server);
+
+    for (path, bytes, content_type) in [
+        ("/site.css", CSS, "text/css"),
+        ("/app.js?v=2", JS, "text/javascript"),
+    ] {
+        let (head, data) = split(&get_with(addr, path, ""));
+        assert_eq!(status(&head), 200, "{}", path);
+        assert_eq!(data, bytes);
+        assert_eq!(header(&head, "content-type"), Some(content_type));
+        assert_eq!(header(&head, "etag"), Some(fnv1a(bytes).as_str()));
+    }
+
+    let current = format!("If-None-Match: {}\r\n", fnv1a(CSS));
+    let (head, data) = split(&get_with(addr, "/site.css", &current));
+    assert_eq!(status(&head), 304);
+    assert!(data.is_empty());
+    let (head, _) = split(&get_with(addr, "/missing.css", ""));
+    assert_eq!(status(&head), 404);
+}
@@ -0,0 +1,256 @@
+mod common;
+
+use std::{
+    error::Error,
+    io::{self, BufRead, BufReader, Read, Write},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use common::{connect, start};
+use RustGin::{HTTPRequest, HTTPResponse, HttpServer, Status};
+
+const PARTS: usize = 5;
+
+/// Lets the producer in `from_channel` send each part only once the client
+/// has received the previous one.
+static RECEIVED: Mutex<Option<mpsc::Receiver<()>>> = Mutex::new(None);
+
+fn from_channel(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let received = RECEIVED.lock().unwrap().take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for i in 0..PARTS {
+            sender.send(format!("part {}", i).into_bytes()).unwrap();
+            received.recv().unwrap();
+        }
+    });
+    request.send_response(&HTTPResponse::from_channel(Status::Ok, receiver))
+}
+
+/// Reads the response head, returning it.
+fn read_head(reader: &mut impl BufRead) -> String {
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        assert_ne!(reader.read_line(&mut head).unwrap(), 0, "{}", head);
+    }
+    head
+}
+
+/// Reads one chunk, returning its data, or `None` for the last chunk, which
+/// must carry no trailers.
+fn read_chunk(reader: &mut impl BufRead) -> Option<String> {
+    let mut size = String::new();
+    reader.read_line(&mut size).unwrap();
+    let size = usize::from_str_radix(size.trim_end(), 16).unwrap();
+    let mut data = vec![0; size + 2];
+    reader.read_exact(&mut data).unwrap();
+    assert!(data.ends_with(b"\r\n"));
+    data.truncate(size);
+    (size > 0).then(|| String::from_utf8(data).unwrap())
+}
+
+#[test]
+fn channel_parts_reach_the_client_in_order_as_they_are_sent() {
+    let (ack, received) = mpsc::channel();
+    *RECEIVED.lock().unwrap() = Some(received);
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), from_channel);
+    let (addr, _) = start(server);
+
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut reader = BufReader::new(stream);
+    let head = read_head(&mut reader);
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(
+        head.contains("\r\nTransfer-Encoding: chunked\r\n"),
+        "{}",
+        head
+    );
+    for i in 0..PARTS {
+        assert_eq!(read_chunk(&mut reader), Some(format!("part {}", i)));
+        ack.send(()).unwrap();
+    }
+    assert_eq!(read_chunk(&mut reader), None);
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty(), "{:?}", rest);
+}
+
+/// How `finish` went for the response of `streamed`.
+static FINISHED: Mutex<Option<mpsc::Sender<io::Result<()>>>> = Mutex::new(None);
+
+fn streamed(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let finished = FINISHED.lock().unwrap().take().unwrap();
+    let (response, mut stream) = HTTPResponse::streaming(Status::Ok);
+    request.send_response(&response)?;
+    thread::spawn(move || {
+        // Well under the stream's buffer, so only `finish` sends it.
+        stream.write_all(b"hello, ").unwrap();
+        stream.write_all(b"world").unwrap();
+        finished.send(stream.finish()).unwrap();
+    });
+    Ok(())
+}
+
+#[test]
+fn finish_sends_buffered_data_and_ends_the_chunked_body() {
+    let (finished, result) = mpsc::channel();
+    *FINISHED.lock().unwrap() = Some(finished);
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), streamed);
+    let (addr, _) = start(server);
+
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let mut reader = BufReader::new(stream);
+    read_head(&mut reader);
+    assert_eq!(read_chunk(&mut reader), Some("hello, world".to_string()));
+    assert_eq!(read_chunk(&mut reader), None);
+    result.recv().unwrap().unwrap();
+
+    // The connection was kept alive: the body's framing ended cleanly.
+    reader
+        .get_mut()
+        .write_all(b"GET /missing HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    assert!(read_head(&mut reader).starts_with("HTTP/1.1 404"));
+}
+
+fn produced_elsewhere(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for i in 0..100 {
+            sender.send(format!("line {}\n", i).into_bytes()).unwrap();
+        }
+    });
+    request.send_response(&HTTPResponse::from_channel(Status::Ok, receiver))
+}
+
+#[test]
+fn http_1_0_client_gets_the_streamed_body_ended_by_close() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), produced_elsewhere);
+    let (addr, _) = start(server);
+
+    let mut stream = connect(addr);
+    // Asking to keep the connection open can't be honoured: only closing it
+    // tells the client where the body ends.
+    stream
+        .write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+    assert!(!head.to_lowercase().contains("content-length"), "{}", head);
+    assert!(
+        !head.to_lowercase().contains("transfer-encoding"),
+        "{}",
+        head
+    );
+    let expected: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+    assert_eq!(body, expected);
+}
+
+fn item(i: usize) -> String {
+    format!(r#"{{"id":{},"name":"item \"{}\""}}"#, i, i)
+}
+
+fn json_items(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let count: usize = request.param("count").unwrap().parse()?;
+    let response = HTTPResponse::json_array_stream(Status::Ok, move |writer| {
+        for i in 0..count {
+            writer.write(&item(i))?;
+        }
+        Ok(())
+    });
+    request.send_response(&response)
+}
+
+#[test]
+fn streamed_json_array_matches_the_buffered_one() {
+    let mut server = HttpServer::new(0);
+    server.get("/items/:count".to_string(), json_items);
+    let (addr, _) = start(server);
+
+    for count in [0, 1, 3, 2000] {
+        let mut stream = connect(addr);
+        stream
+            .write_all(
+                format!(
+                    "GET /items/{} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                    count
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        let mut reader = BufReader::new(stream);
+        let head = read_head(&mut reader);
+        assert!(
+            head.contains("\r\nContent-Type: application/json\r\n"),
+            "{}",
+            head
+        );
+        let mut chunks = Vec::new();
+        while let Some(chunk) = read_chunk(&mut reader) {
+            chunks.push(chunk);
+        }
+        if count == 2000 {
+            assert!(chunks.len() > 1, "sent in {} chunk", chunks.len());
+        }
+        let buffered = format!("[{}]", (0..count).map(item).collect::<Vec<_>>().join(","));
+        assert_eq!(chunks.concat(), buffered, "{} items", count);
+    }
+}
+
+/// Items `endless_items` has written so far.
+static PRODUCED: AtomicUsize = AtomicUsize::new(0);
+
+fn endless_items(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let item = format!("\"{}\"", "x".repeat(1022));
+    let response = HTTPResponse::json_array_stream(Status::Ok, move |writer| {
+        for _ in 0..200_000 {
+            writer.write(&item)?;
+            PRODUCED.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(())
+    });
+    request.send_response(&response)
+}
+
+#[test]
+fn slow_reader_holds_back_the_producer() {
+    let mut server = HttpServer::new(0);
+    server.get("/endless".to_string(), endless_items);
+    let (addr, _) = start(server);
+
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"GET /endless HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut reader = BufReader::new(stream);
+    read_head(&mut reader);
+    // Reading nothing more, the producer stops once the socket buffers and
+    // the stream's few parts are full, far short of the 200 MB array.
+    let mut produced = PRODUCED.load(Ordering::SeqCst);
+    loop {
+        thread::sleep(Duration::from_millis(300));
+        let now = PRODUCED.load(Ordering::SeqCst);
+        if now == produced {
+            break;
+        }
+        produced = now;
+    }
+    assert!(produced < 50_000, "{} items produced", produced);
+    assert!(read_chunk(&mut reader).unwrap().starts_with("[\"xxx"));
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::{
+    error::Error,
+    net::TcpListener,
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use common::{exchange, get, status, wait_until};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+fn boom(_: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    panic!("handler failed")
+}
+
+/// Serves `server` supervised, returning once `listen_supervised_on` has
+/// returned after a shutdown.
+fn check_handler_panics(server: HttpServer) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = Arc::new(server);
+    let handle = server.handle();
+    let (done, finished) = mpsc::channel();
+    let serving = server.clone();
+    thread::spawn(move || done.send(serving.listen_supervised_on(listener).is_ok()));
+
+    assert_eq!(exchange(addr, b"GET /boom HTTP/1.1\r\nHost: x\r\n\r\n"), "");
+    assert_eq!(status(&get(addr, "/")), 200);
+    wait_until(|| server.pool_stats().panicked == 1);
+
+    handle.shutdown();
+    let returned_ok = finished.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(returned_ok);
+}
+
+fn server() -> HttpServer {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), ok);
+    server.get("/boom".to_string(), boom);
+    server
+}
+
+#[test]
+fn handler_panic_is_isolated_with_a_thread_per_connection() {
+    check_handler_panics(server());
+}
+
+#[test]
+fn handler_panic_is_isolated_with_a_worker_pool() {
+    let mut server = server();
+    server.set_workers(2);
+    check_handler_panics(server);
+}
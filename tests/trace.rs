@@ -0,0 +1,42 @@
+mod common;
+
+use std::error::Error;
+
+use common::{body, exchange, header, start, status};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn ok(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("ok")
+}
+
+fn trace(enabled: bool) -> String {
+    let mut server = HttpServer::new(0);
+    server.get("/echo".to_string(), ok);
+    if enabled {
+        server.enable_trace();
+    }
+    let (addr, _) = start(server);
+    exchange(
+        addr,
+        b"TRACE /echo?x=1 HTTP/1.1\r\nHost: x\r\nX-Trace-Me: yes\r\nCookie: id=secret\r\nAuthorization: Basic c2VjcmV0\r\nConnection: close\r\n\r\n",
+    )
+}
+
+#[test]
+fn enabled_trace_echoes_the_request() {
+    let response = trace(true);
+    assert_eq!(status(&response), 200);
+    assert_eq!(header(&response, "content-type"), Some("message/http"));
+    assert_eq!(
+        body(&response),
+        "TRACE /echo?x=1 HTTP/1.1\r\nHost: x\r\nX-Trace-Me: yes\r\nConnection: close\r\n"
+    );
+}
+
+#[test]
+fn disabled_trace_gets_405() {
+    let response = trace(false);
+    assert_eq!(status(&response), 405);
+    assert_eq!(header(&response, "allow"), Some("GET, HEAD"));
+    assert!(!response.contains("X-Trace-Me"));
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use std::{error::Error, net::SocketAddr};
+
+use common::{body, exchange, start, status};
+use RustGin::{HTTPRequest, HttpServer};
+
+fn describe(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    let text = format!("{} {:?}", request.host(), request.host_port());
+    request.send(&text)
+}
+
+fn fallback(mut request: HTTPRequest) -> Result<(), Box<dyn Error>> {
+    request.send("fallback")
+}
+
+fn get_host(addr: SocketAddr, host: &str) -> String {
+    exchange(
+        addr,
+        format!(
+            "GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            host
+        )
+        .as_bytes(),
+    )
+}
+
+#[test]
+fn host_with_and_without_a_port_reach_the_same_virtual_host() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), fallback);
+    server
+        .virtual_host("example.com")
+        .get("/".to_string(), describe);
+    server.virtual_host("[::1]").get("/".to_string(), describe);
+    let (addr, _) = start(server);
+
+    for (host, expected) in [
+        ("example.com", "example.com None"),
+        ("example.com:80", "example.com Some(80)"),
+        ("EXAMPLE.com:8443", "EXAMPLE.com Some(8443)"),
+        ("[::1]", "::1 None"),
+        ("[::1]:8080", "::1 Some(8080)"),
+    ] {
+        let response = get_host(addr, host);
+        assert_eq!(status(&response), 200, "{}", host);
+        assert_eq!(body(&response), expected, "{}", host);
+    }
+    assert_eq!(body(&get_host(addr, "other.com:80")), "fallback");
+}
+
+#[test]
+fn virtual_host_with_a_port_only_matches_that_port() {
+    let mut server = HttpServer::new(0);
+    server.get("/".to_string(), fallback);
+    server
+        .virtual_host("example.com:8080")
+        .get("/".to_string(), describe);
+    let (addr, _) = start(server);
+    assert_eq!(
+        body(&get_host(addr, "example.com:8080")),
+        "example.com Some(8080)"
+    );
+    assert_eq!(body(&get_host(addr, "example.com:9090")), "fallback");
+    assert_eq!(body(&get_host(addr, "example.com")), "fallback");
+}
+
+#[test]
+fn unknown_host_without_a_default_gets_421() {
+    let mut server = HttpServer::new(0);
+    server
+        .virtual_host("example.com")
+        .get("/".to_string(), describe);
+    let (addr, _) = start(server);
+
+    assert_eq!(status(&get_host(addr, "example.com")), 200);
+    let response = get_host(addr, "other.com");
+    assert_eq!(status(&response), 421);
+    assert_eq!(body(&response), "Misdirected Request");
+    // A known host still gets 404 for a path it doesn't have.
+    let missing = exchange(
+        addr,
+        b"GET /missing HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(status(&missing), 404);
+}